@@ -0,0 +1,231 @@
+pub use crate::board::*;
+pub use crate::engine::*;
+use crate::epd::{parse_fen_or_epd, EpdRecord};
+use crate::san::parse_san;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/*
+    The outcome of analysing a single position
+    The score is in centipawns from white's point of view, like get_evaluation
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnalysisResult {
+    pub best_move: Option<String>,
+    pub score: i32,
+    // the moves the search expects to be played, starting with the best move
+    pub pv: Vec<Move>,
+    // deepest ply from the root the last depth searched reached, counting quiescence search
+    pub seldepth: usize,
+    // positions visited by the search, counting quiescence search
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+/*
+    Search every position to the given depth, spreading the positions over 'threads' worker threads
+
+    Results are returned in the same order as the positions
+*/
+pub fn analyze_batch(
+    positions: &[BoardState],
+    depth: u8,
+    threads: usize,
+    options: &SearchOptions,
+) -> Vec<AnalysisResult> {
+    let next_position = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; positions.len()]);
+
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1).min(positions.len()) {
+            scope.spawn(|| loop {
+                let index = next_position.fetch_add(1, Ordering::Relaxed);
+                if index >= positions.len() {
+                    break;
+                }
+                let result = analyze_position(&positions[index], depth, options);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.unwrap())
+        .collect()
+}
+
+fn analyze_position(board: &BoardState, depth: u8, options: &SearchOptions) -> AnalysisResult {
+    let start = Instant::now();
+    let mut search = SearchState::new(*options);
+    let ((next_board, score), _) = iterative_deepening_search(board, depth, &mut search);
+    AnalysisResult {
+        best_move: next_board.and_then(|b| b.last_move),
+        score,
+        pv: search.principal_variation().to_vec(),
+        seldepth: search.seldepth,
+        nodes: search.nodes,
+        time: start.elapsed(),
+    }
+}
+
+/*
+    Whether the move found for a test suite record is correct, ie it is one of the best moves
+    given by 'bm' and none of the moves to avoid given by 'am'
+    Returns None if the record gives neither, so there is nothing to check against
+
+    The record's moves can be in SAN (ie Rxf4), as the epd standard asks, or in coordinate notation
+    (ie e2e4), and are compared in coordinate notation
+*/
+fn solves_record(record: &EpdRecord, best_move: Option<&str>) -> Option<bool> {
+    let best_moves = record.operation("bm");
+    let avoid_moves = record.operation("am");
+    if best_moves.is_none() && avoid_moves.is_none() {
+        return None;
+    }
+    let best_move = match best_move {
+        Some(m) => m,
+        None => return Some(false),
+    };
+    let is_best_move = |m: &String| match parse_san(&record.board, m)
+        .or_else(|_| Move::from_uci(&record.board, m))
+    {
+        Ok(mov) => mov.to_uci() == best_move,
+        Err(_) => m == best_move,
+    };
+    Some(
+        best_moves.is_none_or(|moves| moves.iter().any(is_best_move))
+            && avoid_moves.is_none_or(|moves| !moves.iter().any(is_best_move)),
+    )
+}
+
+/*
+    Analyse every position in a file, one fen string or epd record per line, and print the
+    best move, score and search time for each
+
+    Records with 'bm' or 'am' operations are treated as a test suite, and are marked as
+    solved or failed with a summary printed at the end
+*/
+pub fn analyze_file(path: &str, depth: u8, threads: usize, options: &SearchOptions) {
+    let contents = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(err) => {
+            println!("Could not read {}: {}", path, err);
+            return;
+        }
+    };
+
+    let mut fens = vec![];
+    let mut records = vec![];
+    for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+        match parse_fen_or_epd(line) {
+            Ok(record) => {
+                fens.push(line.trim());
+                records.push(record);
+            }
+            Err(err) => println!("{} : {}", err, line),
+        }
+    }
+    let positions: Vec<BoardState> = records.iter().map(|r| r.board.clone()).collect();
+
+    let start = Instant::now();
+    let results = analyze_batch(&positions, depth, threads, options);
+    let mut solved = 0;
+    let mut graded = 0;
+    for ((fen, record), result) in fens.iter().zip(&records).zip(results) {
+        let grade = match solves_record(record, result.best_move.as_deref()) {
+            Some(true) => {
+                graded += 1;
+                solved += 1;
+                " ; solved"
+            }
+            Some(false) => {
+                graded += 1;
+                " ; failed"
+            }
+            None => "",
+        };
+        let pv: Vec<String> = result.pv.iter().map(|m| m.to_uci()).collect();
+        println!(
+            "{} ; bestmove {} ; score {} ; pv {} ; nodes {} ; nps {} ; time {}ms{}",
+            fen,
+            result.best_move.unwrap_or_else(|| "none".to_string()),
+            result.score,
+            pv.join(" "),
+            result.nodes,
+            nodes_per_second(result.nodes, result.time),
+            result.time.as_millis(),
+            grade
+        );
+    }
+
+    if graded > 0 {
+        println!(
+            "Solved {} of {} positions in {}ms",
+            solved,
+            graded,
+            start.elapsed().as_millis()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batch_matches_single_search() {
+        let positions: Vec<BoardState> = [
+            DEFAULT_FEN_STRING,
+            "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+            "4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1",
+        ]
+        .iter()
+        .map(|fen| board_from_fen(fen).unwrap())
+        .collect();
+
+        let options = SearchOptions::default();
+        let results = analyze_batch(&positions, 2, 2, &options);
+        assert_eq!(results.len(), 3);
+        for (board, result) in positions.iter().zip(results) {
+            let single = analyze_position(board, 2, &options);
+            assert_eq!(result.best_move, single.best_move);
+            assert_eq!(result.score, single.score);
+            assert_eq!(result.pv, single.pv);
+        }
+    }
+
+    #[test]
+    fn batch_finds_mate() {
+        let positions = vec![board_from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap()];
+        let results = analyze_batch(&positions, 1, 4, &SearchOptions::default());
+        assert_eq!(results[0].best_move, Some("a1a8".to_string()));
+        assert_eq!(results[0].pv.len(), 1);
+    }
+
+    #[test]
+    fn grade_suite_records() {
+        let record =
+            parse_fen_or_epd("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm a1a8; id \"mate\";").unwrap();
+        assert_eq!(solves_record(&record, Some("a1a8")), Some(true));
+        assert_eq!(solves_record(&record, Some("g1f1")), Some(false));
+        assert_eq!(solves_record(&record, None), Some(false));
+
+        let record = parse_fen_or_epd("4k3/8/4p3/3p4/8/8/8/3QK3 w - - am d1d5 d1a4;").unwrap();
+        assert_eq!(solves_record(&record, Some("d1d5")), Some(false));
+        assert_eq!(solves_record(&record, Some("d1d2")), Some(true));
+
+        // moves in SAN, as most test suites give them
+        let record = parse_fen_or_epd("6k1/5ppp/8/8/8/8/8/R5K1 w - - bm Ra8#; am Kf1;").unwrap();
+        assert_eq!(solves_record(&record, Some("a1a8")), Some(true));
+        assert_eq!(solves_record(&record, Some("g1f1")), Some(false));
+
+        let record = parse_fen_or_epd(DEFAULT_FEN_STRING).unwrap();
+        assert_eq!(solves_record(&record, Some("e2e4")), None);
+    }
+}
@@ -0,0 +1,219 @@
+pub use crate::board::*;
+
+pub(crate) type Point = (usize, usize);
+
+// Row and column steps along the lines rooks and bishops move on, and the jumps a knight makes,
+// shared by everything which walks the board from a square
+pub(crate) const STRAIGHT: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+pub(crate) const DIAGONAL: [(i8, i8); 4] = [(1, -1), (1, 1), (-1, 1), (-1, -1)];
+pub(crate) const KNIGHT_OFFSETS: [(i8, i8); 8] = [
+    (1, 2),
+    (1, -2),
+    (2, 1),
+    (2, -1),
+    (-1, 2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+];
+
+/*
+    The number of pieces of each color attacking each square, kept up to date by
+    BoardState::set_square as pieces are put on and taken off the board, so whether a square is
+    attacked is a lookup rather than a search of the board
+
+    A square counts as attacked whatever is on it, so a piece which is defended is attacked by its
+    own side. Rooks, bishops and queens attack up to and including the first piece on each line
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct AttackMap([[[u8; 8]; 8]; 2]);
+
+impl AttackMap {
+    /*
+        The attacks of every piece on the board, worked out from scratch
+    */
+    pub fn new(board: &[[u8; 12]; 12]) -> AttackMap {
+        let mut attacks = AttackMap::default();
+        for row in BOARD_START..BOARD_END {
+            for col in BOARD_START..BOARD_END {
+                attacks.add_piece(board, (row, col), 1);
+            }
+        }
+        attacks
+    }
+
+    /*
+        The number of pieces of the given color attacking a square
+    */
+    pub fn count(&self, color: PieceColor, square: Point) -> u8 {
+        self.0[color as usize][square.0 - BOARD_START][square.1 - BOARD_START]
+    }
+
+    pub fn is_attacked(&self, color: PieceColor, square: Point) -> bool {
+        self.count(color, square) > 0
+    }
+
+    /*
+        Put a piece (or EMPTY) on a square of the board, updating the map for the attacks of the
+        piece it replaces, its own attacks, and the lines through the square which it opens or
+        closes
+    */
+    pub fn set_square(&mut self, board: &mut [[u8; 12]; 12], square: Point, new_piece: u8) {
+        let old_piece = board[square.0][square.1];
+        self.add_piece(board, square, -1);
+        if is_empty(old_piece) && !is_empty(new_piece) {
+            // the new piece cuts off the lines through its square
+            self.add_lines_through(board, square, -1);
+        }
+        board[square.0][square.1] = new_piece;
+        if !is_empty(old_piece) && is_empty(new_piece) {
+            self.add_lines_through(board, square, 1);
+        }
+        self.add_piece(board, square, 1);
+    }
+
+    fn add(&mut self, color: PieceColor, square: Point, delta: i8) {
+        let count = &mut self.0[color as usize][square.0 - BOARD_START][square.1 - BOARD_START];
+        *count = count.wrapping_add(delta as u8);
+    }
+
+    /*
+        Add 'delta' to the squares attacked by the piece on a square, if there is one
+    */
+    fn add_piece(&mut self, board: &[[u8; 12]; 12], square: Point, delta: i8) {
+        let piece = board[square.0][square.1];
+        let color = match get_color(piece) {
+            Some(color) => color,
+            None => return,
+        };
+        let mut add_offsets = |offsets: &[(i8, i8)]| {
+            for m in offsets {
+                let target = (
+                    (square.0 as i8 + m.0) as usize,
+                    (square.1 as i8 + m.1) as usize,
+                );
+                if !is_outside_board(board[target.0][target.1]) {
+                    self.add(color, target, delta);
+                }
+            }
+        };
+        match piece & PIECE_MASK {
+            // white pawns move up the board
+            PAWN if color == PieceColor::White => add_offsets(&[(-1, -1), (-1, 1)]),
+            PAWN => add_offsets(&[(1, -1), (1, 1)]),
+            KNIGHT => add_offsets(&KNIGHT_OFFSETS),
+            KING => {
+                add_offsets(&STRAIGHT);
+                add_offsets(&DIAGONAL);
+            }
+            BISHOP => self.add_lines(board, color, square, &DIAGONAL, delta),
+            ROOK => self.add_lines(board, color, square, &STRAIGHT, delta),
+            QUEEN => {
+                self.add_lines(board, color, square, &STRAIGHT, delta);
+                self.add_lines(board, color, square, &DIAGONAL, delta);
+            }
+            _ => {}
+        }
+    }
+
+    /*
+        Add 'delta' to the squares along each direction from a square, up to and including the
+        first piece
+    */
+    fn add_lines(
+        &mut self,
+        board: &[[u8; 12]; 12],
+        color: PieceColor,
+        square: Point,
+        directions: &[(i8, i8)],
+        delta: i8,
+    ) {
+        for m in directions {
+            let mut row = square.0 as i8 + m.0;
+            let mut col = square.1 as i8 + m.1;
+            loop {
+                let target = board[row as usize][col as usize];
+                if is_outside_board(target) {
+                    break;
+                }
+                self.add(color, (row as usize, col as usize), delta);
+                if !is_empty(target) {
+                    break;
+                }
+                row += m.0;
+                col += m.1;
+            }
+        }
+    }
+
+    /*
+        Add 'delta' to the squares beyond a square attacked by the rooks, bishops and queens which
+        attack the square, ie the squares they would attack if the square were empty
+    */
+    fn add_lines_through(&mut self, board: &[[u8; 12]; 12], square: Point, delta: i8) {
+        for (directions, slider) in &[(STRAIGHT, ROOK), (DIAGONAL, BISHOP)] {
+            for m in directions {
+                let mut row = square.0 as i8 + m.0;
+                let mut col = square.1 as i8 + m.1;
+                while is_empty(board[row as usize][col as usize]) {
+                    row += m.0;
+                    col += m.1;
+                }
+                let piece = board[row as usize][col as usize];
+                let color = match get_color(piece) {
+                    Some(color) => color,
+                    None => continue,
+                };
+                if piece & PIECE_MASK == *slider || piece & PIECE_MASK == QUEEN {
+                    self.add_lines(board, color, square, &[(-m.0, -m.1)], delta);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attacks_from_scratch() {
+        let b = board_from_fen("4k3/8/8/3q4/8/1P6/8/R3K2N w - - 0 1").unwrap();
+        let attacks = AttackMap::new(&b.board);
+        let square = |name: &str| Square::from_algebraic(name).unwrap().point();
+        // the queen attacks along its lines up to and including the first piece
+        assert_eq!(attacks.count(PieceColor::Black, square("d1")), 1);
+        assert_eq!(attacks.count(PieceColor::Black, square("b3")), 1);
+        assert!(!attacks.is_attacked(PieceColor::Black, square("a2")));
+        assert_eq!(attacks.count(PieceColor::Black, square("d8")), 2);
+        // the rook and king both defend d1, the knight defends f2 with the king
+        assert_eq!(attacks.count(PieceColor::White, square("d1")), 2);
+        assert_eq!(attacks.count(PieceColor::White, square("f2")), 2);
+        assert_eq!(attacks.count(PieceColor::White, square("c4")), 1);
+        assert!(!attacks.is_attacked(PieceColor::White, square("b4")));
+    }
+
+    #[test]
+    fn attacks_follow_moves() {
+        // castling, en passant, promotions and discovered lines through the squares moved from
+        for fen in &[
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/8/8/8/3pP3/8/8/R3K2R b KQkq e3 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            let b = board_from_fen(fen).unwrap();
+            for new_board in generate_child_boards(&b) {
+                assert_eq!(
+                    new_board.attacks,
+                    AttackMap::new(&new_board.board),
+                    "{}",
+                    new_board.last_move.unwrap()
+                );
+                for next_board in generate_child_boards(&new_board) {
+                    assert_eq!(next_board.attacks, AttackMap::new(&next_board.board));
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,13 @@
 #![allow(dead_code)]
+pub use crate::attacks::AttackMap;
 pub use crate::engine::*;
 pub use crate::utils::*;
 use colored::*;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::str::FromStr;
 
 // Board position for the start of a new game
 pub const DEFAULT_FEN_STRING: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
@@ -33,6 +39,99 @@ pub const BOARD_END: usize = 10;
 
 type Point = (usize, usize);
 
+/*
+    A square within the 8x8 playing area of the board
+
+    Stored as the row and column of the 12x12 board array (so row 2 is the eighth rank) so it can
+    be used to index BoardState::board directly, but every constructor checks the square is on
+    the board, and file/rank accessors are provided so callers don't have to know the layout
+*/
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Square {
+    row: usize,
+    col: usize,
+}
+
+impl Square {
+    /*
+        Square at the given row and column of the board array
+    */
+    pub fn new(row: usize, col: usize) -> Option<Square> {
+        if (BOARD_START..BOARD_END).contains(&row) && (BOARD_START..BOARD_END).contains(&col) {
+            Some(Square { row, col })
+        } else {
+            None
+        }
+    }
+
+    /*
+        Square on the given file and rank, both counted from 0 (ie a1 is 0, 0)
+    */
+    pub fn from_file_rank(file: usize, rank: usize) -> Option<Square> {
+        if file >= 8 || rank >= 8 {
+            return None;
+        }
+        Square::new(BOARD_END - 1 - rank, BOARD_START + file)
+    }
+
+    /*
+        Square from its algebraic name (ie e4)
+    */
+    pub fn from_algebraic(name: &str) -> Option<Square> {
+        let (row, col) = algebraic_pairs_to_board_position(name)?;
+        Square::new(row, col)
+    }
+
+    pub fn file(&self) -> usize {
+        self.col - BOARD_START
+    }
+
+    pub fn rank(&self) -> usize {
+        BOARD_END - 1 - self.row
+    }
+
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    pub fn col(&self) -> usize {
+        self.col
+    }
+
+    pub fn point(&self) -> Point {
+        (self.row, self.col)
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", board_position_to_algebraic_pair(self.point()))
+    }
+}
+
+// squares are written by their algebraic name, so a deserialized square is always on the board
+#[cfg(feature = "serde")]
+impl serde::Serialize for Square {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Square {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Square, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Square::from_algebraic(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid square {}", name)))
+    }
+}
+
+// Game phase of a position with all the minor and major pieces still on the board
+pub const MAX_GAME_PHASE: i32 = 24;
+
+// Files of the rooks each castling right castles with in standard chess, in the order KQkq
+pub const DEFAULT_CASTLING_ROOK_FILES: [usize; 4] = [7, 0, 7, 0];
+
 pub fn get_color(square: u8) -> Option<PieceColor> {
     if is_empty(square) || is_outside_board(square) {
         return None;
@@ -91,8 +190,9 @@ pub fn algebraic_pairs_to_board_position(pair: &str) -> Option<Point> {
         return None;
     }
 
-    let c = pair.chars().nth(0).unwrap();
-    let r = pair.chars().nth(1).unwrap();
+    let mut chars = pair.chars();
+    let c = chars.next()?;
+    let r = chars.next()?;
     let col = match c {
         'a' => 0,
         'b' => 1,
@@ -105,10 +205,11 @@ pub fn algebraic_pairs_to_board_position(pair: &str) -> Option<Point> {
         _ => return None,
     };
 
-    let row = BOARD_END - (r.to_digit(10).unwrap() as usize);
-    if !(BOARD_START..BOARD_END).contains(&row) {
+    let rank = r.to_digit(10)? as usize;
+    if !(1..=8).contains(&rank) {
         return None;
     }
+    let row = BOARD_END - rank;
 
     Some((row, col + BOARD_START))
 }
@@ -136,7 +237,7 @@ pub fn board_position_to_algebraic_pair(pair: Point) -> String {
         9 => "h",
         _ => "h",
     };
-    col.to_string() + &row.to_string()
+    col.to_string() + row
 }
 
 fn get_piece_character(piece: u8) -> &'static str {
@@ -151,7 +252,7 @@ fn get_piece_character(piece: u8) -> &'static str {
     }
 }
 
-fn get_piece_character_simple(piece: u8) -> &'static str {
+pub fn get_piece_character_simple(piece: u8) -> &'static str {
     if is_white(piece) {
         match piece & PIECE_MASK {
             PAWN => "P",
@@ -175,7 +276,8 @@ fn get_piece_character_simple(piece: u8) -> &'static str {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PieceColor {
     Black,
     White,
@@ -190,56 +292,273 @@ impl PieceColor {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PieceKind {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl PieceKind {
+    pub fn as_bits(&self) -> u8 {
+        match *self {
+            PieceKind::Pawn => PAWN,
+            PieceKind::Knight => KNIGHT,
+            PieceKind::Bishop => BISHOP,
+            PieceKind::Rook => ROOK,
+            PieceKind::Queen => QUEEN,
+            PieceKind::King => KING,
+        }
+    }
+}
+
+/*
+    A piece on the board, for code outside the engine which should not need to know how pieces
+    are packed into the u8 values of BoardState::board
+*/
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Piece {
+    pub color: PieceColor,
+    pub kind: PieceKind,
+}
+
+impl Piece {
+    pub fn new(color: PieceColor, kind: PieceKind) -> Piece {
+        Piece { color, kind }
+    }
+
+    /*
+        The piece stored in a square of the board, None for empty squares and the border
+    */
+    pub fn from_u8(square: u8) -> Option<Piece> {
+        let color = get_color(square)?;
+        let kind = match square & PIECE_MASK {
+            PAWN => PieceKind::Pawn,
+            KNIGHT => PieceKind::Knight,
+            BISHOP => PieceKind::Bishop,
+            ROOK => PieceKind::Rook,
+            QUEEN => PieceKind::Queen,
+            KING => PieceKind::King,
+            _ => return None,
+        };
+        Some(Piece { color, kind })
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        self.color.as_mask() | self.kind.as_bits()
+    }
+
+    /*
+        The piece written with the fen letter c, upper case for white and lower case for black
+    */
+    pub fn from_fen_char(c: char) -> Option<Piece> {
+        Piece::from_u8(get_piece_from_fen_string_char(c)?)
+    }
+
+    pub fn fen_char(&self) -> char {
+        get_piece_character_simple(self.as_u8())
+            .chars()
+            .next()
+            .unwrap()
+    }
+}
+
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.fen_char())
+    }
+}
+
+/*
+    The squares holding one type of piece for one side, stored as a set of square indices
+    (row by row from the top left of the board) so it is cheap to copy along with the board
+*/
+#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+pub struct PieceList(u64);
+
+impl PieceList {
+    fn index(square: Point) -> u32 {
+        ((square.0 - BOARD_START) * 8 + square.1 - BOARD_START) as u32
+    }
+
+    pub fn insert(&mut self, square: Point) {
+        self.0 |= 1 << PieceList::index(square);
+    }
+
+    pub fn remove(&mut self, square: Point) {
+        self.0 &= !(1 << PieceList::index(square));
+    }
+
+    pub fn contains(&self, square: Point) -> bool {
+        self.0 & (1 << PieceList::index(square)) != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /*
+        The squares in the list, in the same order as scanning the board row by row
+    */
+    pub fn iter(&self) -> impl Iterator<Item = Point> {
+        let mut squares = self.0;
+        std::iter::from_fn(move || {
+            if squares == 0 {
+                return None;
+            }
+            let index = squares.trailing_zeros() as usize;
+            squares &= squares - 1;
+            Some((index / 8 + BOARD_START, index % 8 + BOARD_START))
+        })
+    }
+}
+
+/*
+    How BoardState::render draws the board
+*/
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderOptions {
+    // unicode pieces on colored squares, rather than fen letters (which work in any terminal)
+    pub unicode: bool,
+    // file letters below the board as well as above, and rank numbers on the left as well as the right
+    pub coordinates_both_sides: bool,
+}
+
+impl RenderOptions {
+    pub fn pretty() -> RenderOptions {
+        RenderOptions {
+            unicode: true,
+            ..RenderOptions::default()
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct BoardState {
-    pub full_move_clock: u8, // The number of the full moves. It starts at 1, and is incremented after Black's move
-    pub half_move_clock: u8, // The number of half moves since the last capture or pawn advance, used for the fifty-move rule
+    pub full_move_clock: u16, // The number of the full moves. It starts at 1, and is incremented after Black's move
+    pub half_move_clock: u16, // The number of half moves since the last capture or pawn advance, used for the fifty-move rule
     pub board: [[u8; 12]; 12],
     pub to_move: PieceColor,
     // if a pawn, on the last move, made a double move, this is set, otherwise this is None
-    pub pawn_double_move: Option<Point>,
-    pub white_king_location: Point,
-    pub black_king_location: Point,
+    pub pawn_double_move: Option<Square>,
+    // only meaningful when the side has exactly one king, a position without one is left on a1
+    pub white_king_location: Square,
+    pub black_king_location: Square,
     pub white_king_side_castle: bool,
     pub white_queen_side_castle: bool,
     pub black_king_side_castle: bool,
     pub black_queen_side_castle: bool,
+    // file of the rook each castling right castles with, in the order KQkq, only differs from
+    // DEFAULT_CASTLING_ROOK_FILES in Chess960
+    pub castling_rook_files: [usize; 4],
     pub black_total_piece_value: i32,
     pub white_total_piece_value: i32,
     pub last_move: Option<String>, // the start and last position of the last move made
+    // the squares of each type of piece, indexed by color and then by piece (ie PAWN)
+    pub piece_lists: [[PieceList; 7]; 2],
+    // sum of piece_square_value over every piece on the board, positive when white's pieces are better placed
+    pub piece_square_score: i32,
+    // the number of pieces of each color attacking each square
+    pub attacks: AttackMap,
+}
+
+// boards are written as their fen and last move, so a deserialized board is checked like any
+// other fen and everything kept in sync with the board (piece lists, attacks, material and so on)
+// is rebuilt rather than trusted
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedBoard {
+    fen: String,
+    last_move: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for BoardState {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedBoard {
+            fen: self.to_fen(),
+            last_move: self.last_move.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for BoardState {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<BoardState, D::Error> {
+        let serialized = SerializedBoard::deserialize(deserializer)?;
+        let mut board: BoardState = serialized.fen.parse().map_err(|err| {
+            serde::de::Error::custom(format!("invalid board {}: {}", serialized.fen, err))
+        })?;
+        board.last_move = serialized.last_move;
+        Ok(board)
+    }
 }
 
 impl BoardState {
-    pub fn pretty_print_board(&self) {
-        println!("a b c d e f g h");
+    /*
+        Draw the board as text, one line per rank from the eighth down to the first
+    */
+    pub fn render(&self, options: &RenderOptions) -> String {
+        let margin = if options.coordinates_both_sides {
+            "  "
+        } else {
+            ""
+        };
+        let mut board = format!("{}a b c d e f g h\n", margin);
         for i in BOARD_START..BOARD_END {
+            let rank = 10 - i;
+            if options.coordinates_both_sides {
+                board += &format!("{} ", rank);
+            }
             for j in BOARD_START..BOARD_END {
+                if !options.unicode {
+                    board += &format!("{} ", get_piece_character_simple(self.board[i][j]));
+                    continue;
+                }
                 let piece = format!("{} ", get_piece_character(self.board[i][j]));
-                if (i + j) % 2 != 0 {
-                    if is_white(self.board[i][j]) {
-                        print!("{}", piece.white().on_truecolor(158, 93, 30));
-                    } else {
-                        print!("{}", piece.black().on_truecolor(158, 93, 30));
-                    }
-                } else if is_white(self.board[i][j]) {
-                    print!("{}", piece.white().on_truecolor(205, 170, 125));
+                let piece = if is_white(self.board[i][j]) {
+                    piece.white()
                 } else {
-                    print!("{}", piece.black().on_truecolor(205, 170, 125));
-                }
+                    piece.black()
+                };
+                let piece = if (i + j) % 2 != 0 {
+                    piece.on_truecolor(158, 93, 30)
+                } else {
+                    piece.on_truecolor(205, 170, 125)
+                };
+                board += &piece.to_string();
             }
-            println!(" {}", 10 - i);
+            board += &format!(" {}\n", rank);
+        }
+        if options.coordinates_both_sides {
+            board += &format!("{}a b c d e f g h\n", margin);
         }
+        board
+    }
+
+    /*
+        Write the board drawn by render to any writer (ie a log file or a buffer in a test)
+    */
+    pub fn write_board(&self, out: &mut dyn io::Write, options: &RenderOptions) -> io::Result<()> {
+        out.write_all(self.render(options).as_bytes())
+    }
+
+    pub fn pretty_print_board(&self) {
+        print!("{}", self.render(&RenderOptions::pretty()));
     }
 
     pub fn simple_board(&self) -> String {
-        let mut board = "\na b c d e f g h\n".to_string();
-        for i in BOARD_START..BOARD_END {
-            for j in BOARD_START..BOARD_END {
-                board = format!("{}{} ", board, get_piece_character_simple(self.board[i][j]));
-            }
-            board = format!("{} {}\n", board, 10 - i);
-        }
-        board
+        format!("\n{}", self.render(&RenderOptions::default()))
     }
 
     pub fn simple_print_board(&self) {
@@ -252,12 +571,287 @@ impl BoardState {
             PieceColor::Black => self.to_move = PieceColor::White,
         }
     }
+
+    /*
+        Pass the move to the other side without moving a piece, clearing the en passant square as
+        it can only be taken straight after the double move
+
+        Returns the en passant square to give back to unmake_null_move
+    */
+    pub fn make_null_move(&mut self) -> Option<Square> {
+        self.swap_color();
+        self.pawn_double_move.take()
+    }
+
+    /*
+        Undo make_null_move, given the en passant square it returned
+    */
+    pub fn unmake_null_move(&mut self, pawn_double_move: Option<Square>) {
+        self.swap_color();
+        self.pawn_double_move = pawn_double_move;
+    }
+
+    /*
+        Put a piece (or EMPTY) on a square, keeping the piece lists, material, piece square score
+        and attacks in sync with the board, so a piece that is replaced is also taken off them
+    */
+    pub fn set_square(&mut self, square: Point, piece: u8) {
+        let old_piece = self.board[square.0][square.1];
+        if let Some(color) = get_color(old_piece) {
+            self.piece_lists[color as usize][(old_piece & PIECE_MASK) as usize].remove(square);
+            *self.total_piece_value_mut(color) -= PIECE_VALUES[(old_piece & PIECE_MASK) as usize];
+            self.piece_square_score -= piece_square_value(square, old_piece);
+        }
+        self.attacks.set_square(&mut self.board, square, piece);
+        if let Some(color) = get_color(piece) {
+            self.piece_lists[color as usize][(piece & PIECE_MASK) as usize].insert(square);
+            *self.total_piece_value_mut(color) += PIECE_VALUES[(piece & PIECE_MASK) as usize];
+            self.piece_square_score += piece_square_value(square, piece);
+        }
+    }
+
+    fn total_piece_value_mut(&mut self, color: PieceColor) -> &mut i32 {
+        match color {
+            PieceColor::White => &mut self.white_total_piece_value,
+            PieceColor::Black => &mut self.black_total_piece_value,
+        }
+    }
+
+    /*
+        The en passant square, if the side to move has a pawn which can capture onto it
+    */
+    pub fn en_passant_capture_square(&self) -> Option<Square> {
+        let square = self.pawn_double_move?;
+        let (row, pawn) = match self.to_move {
+            PieceColor::White => (square.row() + 1, WHITE | PAWN),
+            PieceColor::Black => (square.row() - 1, BLACK | PAWN),
+        };
+        let col = square.col();
+        if self.board[row][col - 1] == pawn || self.board[row][col + 1] == pawn {
+            Some(square)
+        } else {
+            None
+        }
+    }
+
+    /*
+        The castling rights in the order KQkq, the same order as castling_rook_files
+    */
+    pub fn castling_rights(&self) -> [bool; 4] {
+        [
+            self.white_king_side_castle,
+            self.white_queen_side_castle,
+            self.black_king_side_castle,
+            self.black_queen_side_castle,
+        ]
+    }
+
+    /*
+        The file of the rook each castling right castles with, None for a right which has been
+        lost, as the file it castled with says nothing about the position any more
+    */
+    pub fn held_castling_rook_files(&self) -> [Option<usize>; 4] {
+        let rights = self.castling_rights();
+        let mut files = [None; 4];
+        for (index, file) in files.iter_mut().enumerate() {
+            if rights[index] {
+                *file = Some(self.castling_rook_files[index]);
+            }
+        }
+        files
+    }
+
+    /*
+        A key identifying the position for the repetition rules, see zobrist::position_key
+    */
+    pub fn position_key(&self) -> u64 {
+        crate::zobrist::position_key(self)
+    }
+
+    /*
+        The piece on a square, None if it is empty
+    */
+    pub fn piece_at(&self, square: Square) -> Option<Piece> {
+        Piece::from_u8(self.board[square.row()][square.col()])
+    }
+
+    /*
+        Every piece on the board with its square, going along each rank from a8 down to h1
+    */
+    pub fn pieces(&self) -> impl Iterator<Item = (Square, Piece)> + '_ {
+        (BOARD_START..BOARD_END)
+            .flat_map(|row| (BOARD_START..BOARD_END).map(move |col| Square { row, col }))
+            .filter_map(move |square| Some((square, self.piece_at(square)?)))
+    }
+
+    /*
+        Every piece of one color on the board with its square, in the same order as pieces()
+    */
+    pub fn pieces_of(&self, color: PieceColor) -> impl Iterator<Item = (Square, Piece)> + '_ {
+        self.pieces().filter(move |(_, piece)| piece.color == color)
+    }
+
+    /*
+        The squares holding the given type of piece (ie KNIGHT) for one side
+    */
+    pub fn piece_list(&self, color: PieceColor, piece: u8) -> PieceList {
+        self.piece_lists[color as usize][piece as usize]
+    }
+
+    /*
+        Write the position out as a fen string, the inverse of board_from_fen
+    */
+    pub fn to_fen(&self) -> String {
+        let mut rows = vec![];
+        for row in BOARD_START..BOARD_END {
+            let mut fen_row = String::new();
+            let mut empty_squares = 0;
+            for col in BOARD_START..BOARD_END {
+                let square = self.board[row][col];
+                if is_empty(square) {
+                    empty_squares += 1;
+                    continue;
+                }
+                if empty_squares > 0 {
+                    fen_row += &empty_squares.to_string();
+                    empty_squares = 0;
+                }
+                fen_row += get_piece_character_simple(square);
+            }
+            if empty_squares > 0 {
+                fen_row += &empty_squares.to_string();
+            }
+            rows.push(fen_row);
+        }
+
+        let to_move = match self.to_move {
+            PieceColor::White => "w",
+            PieceColor::Black => "b",
+        };
+
+        // KQkq when the right castles with the outermost rook on that side of the king (as in
+        // X-FEN), otherwise the file of the rook (as in Shredder-FEN)
+        let mut castling = String::new();
+        let rights = self.castling_rights();
+        for (index, right) in "KQkq".chars().enumerate() {
+            if !rights[index] {
+                continue;
+            }
+            let file = self.castling_rook_files[index];
+            let (color, king_side) = castling_right_side(right);
+            if outermost_rook_file(&self.board, color, king_side) == Some(file) {
+                castling.push(right);
+            } else {
+                let file = (b'a' + file as u8) as char;
+                castling.push(match color {
+                    PieceColor::White => file.to_ascii_uppercase(),
+                    PieceColor::Black => file,
+                });
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let en_passant = match self.pawn_double_move {
+            Some(square) => square.to_string(),
+            None => "-".to_string(),
+        };
+
+        format!(
+            "{} {} {} {} {} {}",
+            rows.join("/"),
+            to_move,
+            castling,
+            en_passant,
+            self.half_move_clock,
+            self.full_move_clock
+        )
+    }
+
+    /*
+        Value of the pieces the side has on the board, not counting its king
+    */
+    pub fn material(&self, color: PieceColor) -> i32 {
+        let total = match color {
+            PieceColor::White => self.white_total_piece_value,
+            PieceColor::Black => self.black_total_piece_value,
+        };
+        total - PIECE_VALUES[KING as usize]
+    }
+
+    /*
+        Value of the knights, bishops, rooks and queens the side has on the board
+    */
+    pub fn non_pawn_material(&self, color: PieceColor) -> i32 {
+        let pawns = self.piece_list(color, PAWN).len() as i32;
+        self.material(color) - pawns * PIECE_VALUES[PAWN as usize]
+    }
+
+    /*
+        How far the game is from the endgame, from MAX_GAME_PHASE with every minor and major piece
+        on the board down to 0 with only kings and pawns left
+
+        Knights and bishops count 1, rooks 2 and queens 4, so extra promoted pieces can not push
+        the phase past MAX_GAME_PHASE
+    */
+    pub fn game_phase(&self) -> i32 {
+        let mut phase = 0;
+        for color in &[PieceColor::White, PieceColor::Black] {
+            for (piece, weight) in &[(KNIGHT, 1), (BISHOP, 1), (ROOK, 2), (QUEEN, 4)] {
+                phase += self.piece_list(*color, *piece).len() as i32 * weight;
+            }
+        }
+        phase.min(MAX_GAME_PHASE)
+    }
+}
+
+/*
+    Two boards are equal when they are the same position for the repetition rules, so the move
+    clocks and the move which led to the position are ignored, and the en passant square only counts
+    when a capture can be made
+*/
+impl PartialEq for BoardState {
+    fn eq(&self, other: &BoardState) -> bool {
+        self.board == other.board
+            && self.to_move == other.to_move
+            && self.white_king_side_castle == other.white_king_side_castle
+            && self.white_queen_side_castle == other.white_queen_side_castle
+            && self.black_king_side_castle == other.black_king_side_castle
+            && self.black_queen_side_castle == other.black_queen_side_castle
+            && self.held_castling_rook_files() == other.held_castling_rook_files()
+            && self.en_passant_capture_square() == other.en_passant_capture_square()
+    }
+}
+
+impl Eq for BoardState {}
+
+impl Hash for BoardState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.position_key());
+    }
+}
+
+/*
+    The board as the diagram from simple_board followed by its fen string
+*/
+impl fmt::Display for BoardState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.simple_board().trim_start(), self.to_fen())
+    }
+}
+
+// so boards compared by assert_eq are shown the same way in test failures
+impl fmt::Debug for BoardState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\n{}", self)
+    }
 }
 
 /*
     Parse the standard fen string notation (en.wikipedia.org/wiki/Forsyth–Edwards_Notation) and return a board state
 */
-pub fn board_from_fen(fen: &str) -> Result<BoardState, &str> {
+pub fn board_from_fen(fen: &str) -> Result<BoardState, &'static str> {
     let mut board = [[SENTINEL; 12]; 12];
     let mut fen = fen.to_string();
     trim_newline(&mut fen);
@@ -274,12 +868,12 @@ pub fn board_from_fen(fen: &str) -> Result<BoardState, &str> {
     let castling_privileges = fen_config[2];
     let en_passant = fen_config[3];
 
-    let half_move_clock = fen_config[4].parse::<u8>();
+    let half_move_clock = fen_config[4].parse::<u16>();
     if half_move_clock.is_err() {
         return Err("Could not parse fen string: Invalid half move value");
     }
 
-    let full_move_clock = fen_config[5].parse::<u8>();
+    let full_move_clock = fen_config[5].parse::<u16>();
     if full_move_clock.is_err() {
         return Err("Could not parse fen string: Invalid full move value");
     }
@@ -290,15 +884,17 @@ pub fn board_from_fen(fen: &str) -> Result<BoardState, &str> {
         return Err("Could not parse fen string: Invalid number of rows provided, 8 expected");
     }
 
-    let mut row: usize = BOARD_START;
     let mut col: usize = BOARD_START;
-    let mut white_king_location = (0, 0);
-    let mut black_king_location = (0, 0);
+    let no_king = Square::from_file_rank(0, 0).unwrap();
+    let mut white_king_location = no_king;
+    let mut black_king_location = no_king;
     let mut white_piece_values = 0;
     let mut black_piece_values = 0;
-    for fen_row in fen_rows {
+    let mut piece_lists = [[PieceList::default(); 7]; 2];
+    let mut piece_square_score = 0;
+    for (row, fen_row) in (BOARD_START..).zip(fen_rows) {
         for square in fen_row.chars() {
-            if square.is_digit(10) {
+            if square.is_ascii_digit() {
                 let square_skip_count = square.to_digit(10).unwrap() as usize;
                 if square_skip_count + col > BOARD_END {
                     return Err("Could not parse fen string: Index out of bounds");
@@ -312,16 +908,20 @@ pub fn board_from_fen(fen: &str) -> Result<BoardState, &str> {
                     Some(piece) => piece,
                     None => return Err("Could not parse fen string: Invalid character found"),
                 };
+                let color = get_color(board[row][col]).unwrap();
+                piece_lists[color as usize][(board[row][col] & PIECE_MASK) as usize]
+                    .insert((row, col));
+                piece_square_score += piece_square_value((row, col), board[row][col]);
 
                 if is_white(board[row][col]) {
                     white_piece_values += PIECE_VALUES[(board[row][col] & PIECE_MASK) as usize];
                     if is_king(board[row][col]) {
-                        white_king_location = (row, col);
+                        white_king_location = Square { row, col };
                     }
                 } else {
                     black_piece_values += PIECE_VALUES[(board[row][col] & PIECE_MASK) as usize];
                     if is_king(board[row][col]) {
-                        black_king_location = (row, col);
+                        black_king_location = Square { row, col };
                     }
                 }
                 col += 1;
@@ -330,20 +930,22 @@ pub fn board_from_fen(fen: &str) -> Result<BoardState, &str> {
         if col != BOARD_END {
             return Err("Could not parse fen string: Complete row was not specified");
         }
-        row += 1;
         col = BOARD_START;
     }
 
     // Deal with the en passant string
-    let mut en_passant_pos: Option<Point> = None;
+    let mut en_passant_pos: Option<Square> = None;
     if en_passant.len() != 2 {
         if en_passant != "-" {
             return Err("Could not parse fen string: En passant string not valid");
         }
     } else {
-        en_passant_pos = algebraic_pairs_to_board_position(en_passant);
+        en_passant_pos = Square::from_algebraic(en_passant);
     }
 
+    let (castling_rights, castling_rook_files) =
+        parse_castling_rights(castling_privileges, &board)?;
+
     Ok(BoardState {
         full_move_clock: full_move_clock.unwrap(),
         half_move_clock: half_move_clock.unwrap(),
@@ -352,17 +954,126 @@ pub fn board_from_fen(fen: &str) -> Result<BoardState, &str> {
         white_king_location,
         black_king_location,
         pawn_double_move: en_passant_pos,
-        white_king_side_castle: castling_privileges.find('K') != None,
-        white_queen_side_castle: castling_privileges.find('Q') != None,
-        black_king_side_castle: castling_privileges.find('k') != None,
-        black_queen_side_castle: castling_privileges.find('q') != None,
+        white_king_side_castle: castling_rights[0],
+        white_queen_side_castle: castling_rights[1],
+        black_king_side_castle: castling_rights[2],
+        black_queen_side_castle: castling_rights[3],
+        castling_rook_files,
         black_total_piece_value: black_piece_values,
         white_total_piece_value: white_piece_values,
         last_move: None,
+        piece_lists,
+        piece_square_score,
+        attacks: AttackMap::new(&board),
     })
 }
 
-fn get_piece_from_fen_string_char(piece: char) -> Option<u8> {
+/*
+    Parse the castling field of a fen string into the castling rights and the files of the rooks
+    they castle with, both in the order KQkq
+
+    Accepts KQkq, which castle with the outermost rook on that side of the king as in X-FEN, and the
+    files of the rooks (ie HAha) as in Shredder-FEN, so Chess960 positions can be loaded
+*/
+pub fn parse_castling_rights(
+    castling: &str,
+    board: &[[u8; 12]; 12],
+) -> Result<([bool; 4], [usize; 4]), &'static str> {
+    let mut rights = [false; 4];
+    let mut rook_files = DEFAULT_CASTLING_ROOK_FILES;
+    if castling == "-" {
+        return Ok((rights, rook_files));
+    }
+
+    for right in castling.chars() {
+        let (color, king_side, file) = match right {
+            'K' | 'Q' | 'k' | 'q' => {
+                let (color, king_side) = castling_right_side(right);
+                (
+                    color,
+                    king_side,
+                    outermost_rook_file(board, color, king_side),
+                )
+            }
+            'A'..='H' | 'a'..='h' => {
+                let (color, _) = castling_right_side(right);
+                let file = (right.to_ascii_lowercase() as u8 - b'a') as usize;
+                let king_file = back_rank_king_file(board, color).unwrap_or(4);
+                (color, file > king_file, Some(file))
+            }
+            _ => return Err("Could not parse fen string: Invalid castling rights"),
+        };
+
+        // a right without a rook keeps the standard file, and is left for validate_position to reject
+        let index = castling_right_index(color, king_side);
+        rights[index] = true;
+        rook_files[index] = file.unwrap_or(DEFAULT_CASTLING_ROOK_FILES[index]);
+    }
+    Ok((rights, rook_files))
+}
+
+/*
+    The color of a castling right and whether it is king side (only meaningful for KQkq)
+*/
+fn castling_right_side(right: char) -> (PieceColor, bool) {
+    let color = if right.is_ascii_uppercase() {
+        PieceColor::White
+    } else {
+        PieceColor::Black
+    };
+    (color, right.eq_ignore_ascii_case(&'k'))
+}
+
+/*
+    Index of a castling right in the order KQkq
+*/
+pub fn castling_right_index(color: PieceColor, king_side: bool) -> usize {
+    let index = match color {
+        PieceColor::White => 0,
+        PieceColor::Black => 2,
+    };
+    if king_side {
+        index
+    } else {
+        index + 1
+    }
+}
+
+/*
+    Row of the board array holding the first rank for a color
+*/
+pub fn back_rank_row(color: PieceColor) -> usize {
+    match color {
+        PieceColor::White => BOARD_END - 1,
+        PieceColor::Black => BOARD_START,
+    }
+}
+
+fn back_rank_king_file(board: &[[u8; 12]; 12], color: PieceColor) -> Option<usize> {
+    let row = back_rank_row(color);
+    (0..8).find(|file| board[row][BOARD_START + file] == color.as_mask() | KING)
+}
+
+/*
+    File of the rook furthest from the king on one side of it on the back rank, None if the king is
+    not on the back rank or there is no rook on that side
+*/
+fn outermost_rook_file(
+    board: &[[u8; 12]; 12],
+    color: PieceColor,
+    king_side: bool,
+) -> Option<usize> {
+    let row = back_rank_row(color);
+    let king_file = back_rank_king_file(board, color)?;
+    let is_rook = |file: &usize| board[row][BOARD_START + file] == color.as_mask() | ROOK;
+    if king_side {
+        (king_file + 1..8).rev().find(is_rook)
+    } else {
+        (0..king_file).find(is_rook)
+    }
+}
+
+pub fn get_piece_from_fen_string_char(piece: char) -> Option<u8> {
     match piece {
         'r' => Some(BLACK | ROOK),
         'n' => Some(BLACK | KNIGHT),
@@ -380,6 +1091,295 @@ fn get_piece_from_fen_string_char(piece: char) -> Option<u8> {
     }
 }
 
+/*
+    A reason a position could not have come from a legal game
+*/
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValidationIssue {
+    WrongKingCount(PieceColor, usize),
+    PawnOnBackRank(Square),
+    OpponentInCheck,
+    InvalidCastlingRights(char),
+    InvalidEnPassant(Square),
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationIssue::WrongKingCount(color, count) => {
+                write!(f, "{:?} has {} kings, exactly one expected", color, count)
+            }
+            ValidationIssue::PawnOnBackRank(square) => {
+                write!(f, "Pawn on {} is on the first or eighth rank", square)
+            }
+            ValidationIssue::OpponentInCheck => {
+                write!(f, "The side which is not to move is in check")
+            }
+            ValidationIssue::InvalidCastlingRights(right) => write!(
+                f,
+                "Castling right {} does not match the king and rook placement",
+                right
+            ),
+            ValidationIssue::InvalidEnPassant(square) => {
+                write!(f, "En passant capture on {} is not possible", square)
+            }
+        }
+    }
+}
+
+/*
+    Check a position for problems which mean it could never occur in a legal game
+
+    Checks for
+    1. Exactly one king per side
+    2. No pawns on the first or eighth rank
+    3. The side not to move is not in check
+    4. The king and rook are on their back rank for each castling right, with the rook on the
+       castling side of the king
+    5. The en passant square is behind a pawn that could have just double moved
+
+    Returns every issue found, a valid position returns an empty list
+*/
+pub fn validate_position(board: &BoardState) -> Vec<ValidationIssue> {
+    let mut issues = vec![];
+
+    let mut white_kings = 0;
+    let mut black_kings = 0;
+    for (square, piece) in board.pieces() {
+        match (piece.kind, piece.color) {
+            (PieceKind::King, PieceColor::White) => white_kings += 1,
+            (PieceKind::King, PieceColor::Black) => black_kings += 1,
+            (PieceKind::Pawn, _) if square.rank() == 0 || square.rank() == 7 => {
+                issues.push(ValidationIssue::PawnOnBackRank(square))
+            }
+            _ => {}
+        }
+    }
+    if white_kings != 1 {
+        issues.push(ValidationIssue::WrongKingCount(
+            PieceColor::White,
+            white_kings,
+        ));
+    }
+    if black_kings != 1 {
+        issues.push(ValidationIssue::WrongKingCount(
+            PieceColor::Black,
+            black_kings,
+        ));
+    }
+
+    let opponent = match board.to_move {
+        PieceColor::White => PieceColor::Black,
+        PieceColor::Black => PieceColor::White,
+    };
+    let opponent_kings = match opponent {
+        PieceColor::White => white_kings,
+        PieceColor::Black => black_kings,
+    };
+    if opponent_kings == 1 && is_check(board, opponent) {
+        issues.push(ValidationIssue::OpponentInCheck);
+    }
+
+    let rights = board.castling_rights();
+    for (index, right) in "KQkq".chars().enumerate() {
+        if !rights[index] {
+            continue;
+        }
+        let (color, king_side) = castling_right_side(right);
+        let row = back_rank_row(color);
+        let rook_col = BOARD_START + board.castling_rook_files[index];
+        let king_col = match back_rank_king_file(&board.board, color) {
+            Some(file) => BOARD_START + file,
+            None => {
+                issues.push(ValidationIssue::InvalidCastlingRights(right));
+                continue;
+            }
+        };
+        if board.board[row][rook_col] != color.as_mask() | ROOK
+            || (rook_col > king_col) != king_side
+        {
+            issues.push(ValidationIssue::InvalidCastlingRights(right));
+        }
+    }
+
+    if let Some(square) = board.pawn_double_move {
+        // the pawn which double moved belongs to the side that just moved
+        let (ep_row, pawn_row, start_row) = match board.to_move {
+            PieceColor::White => (BOARD_START + 2, BOARD_START + 3, BOARD_START + 1),
+            PieceColor::Black => (BOARD_END - 3, BOARD_END - 4, BOARD_END - 2),
+        };
+        if square.row() != ep_row
+            || board.board[pawn_row][square.col()] != opponent.as_mask() | PAWN
+            || !is_empty(board.board[square.row()][square.col()])
+            || !is_empty(board.board[start_row][square.col()])
+        {
+            issues.push(ValidationIssue::InvalidEnPassant(square));
+        }
+    }
+
+    issues
+}
+
+/*
+    Why a fen string was rejected by board_from_fen_strict
+*/
+#[derive(Debug, PartialEq, Eq)]
+pub enum FenError {
+    // the fen string could not be parsed, with the message from board_from_fen
+    Parse(&'static str),
+    // the fen string was parsed but describes a position which can not occur in a game
+    Invalid(Vec<ValidationIssue>),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::Parse(message) => write!(f, "{}", message),
+            FenError::Invalid(issues) => {
+                let issues: Vec<String> = issues.iter().map(|issue| issue.to_string()).collect();
+                write!(f, "{}", issues.join("\n"))
+            }
+        }
+    }
+}
+
+impl Error for FenError {}
+
+/*
+    Parse a fen string with board_from_fen_strict, so "...".parse::<BoardState>() only gives
+    positions the engine can play from
+*/
+impl FromStr for BoardState {
+    type Err = FenError;
+
+    fn from_str(fen: &str) -> Result<BoardState, FenError> {
+        board_from_fen_strict(fen)
+    }
+}
+
+/*
+    Parse a fen string like board_from_fen, but also reject positions which fail validate_position
+    (ie a missing king or a pawn on the back rank), which the engine can not play from
+*/
+pub fn board_from_fen_strict(fen: &str) -> Result<BoardState, FenError> {
+    let board = board_from_fen(fen).map_err(FenError::Parse)?;
+    let issues = validate_position(&board);
+    if !issues.is_empty() {
+        return Err(FenError::Invalid(issues));
+    }
+    Ok(board)
+}
+
+/*
+    Recompute the values which are kept up to date as moves are made, the position key and the
+    castling rights included, and panic if any of them disagree with the pieces on the board
+
+    Only enabled with the paranoid feature, where make_move runs it on every board it makes so
+    state corruption is caught on the move that caused it, whichever part of the engine played it
+*/
+#[cfg(feature = "paranoid")]
+pub fn assert_board_invariants(board: &BoardState) {
+    let mut white_piece_values = 0;
+    let mut black_piece_values = 0;
+    let mut piece_lists = [[PieceList::default(); 7]; 2];
+    let mut piece_square_score = 0;
+    for row in BOARD_START..BOARD_END {
+        for col in BOARD_START..BOARD_END {
+            let square = board.board[row][col];
+            if let Some(color) = get_color(square) {
+                piece_lists[color as usize][(square & PIECE_MASK) as usize].insert((row, col));
+            }
+            piece_square_score += piece_square_value((row, col), square);
+            let value = PIECE_VALUES[(square & PIECE_MASK) as usize];
+            if is_white(square) {
+                white_piece_values += value;
+                if is_king(square) {
+                    assert_eq!(
+                        board.white_king_location.point(),
+                        (row, col),
+                        "white king moved"
+                    );
+                }
+            } else if is_black(square) {
+                black_piece_values += value;
+                if is_king(square) {
+                    assert_eq!(
+                        board.black_king_location.point(),
+                        (row, col),
+                        "black king moved"
+                    );
+                }
+            }
+        }
+    }
+    assert_eq!(board.white_total_piece_value, white_piece_values);
+    assert_eq!(board.black_total_piece_value, black_piece_values);
+    assert_eq!(board.piece_lists, piece_lists, "piece lists out of sync");
+    assert_eq!(board.piece_square_score, piece_square_score);
+    assert_eq!(
+        board.attacks,
+        AttackMap::new(&board.board),
+        "attacks out of sync"
+    );
+
+    // each castling right needs its king still on the back rank and its rook on the file it
+    // castles with, on the castling side of the king
+    for (index, right) in "KQkq".chars().enumerate() {
+        if !board.castling_rights()[index] {
+            continue;
+        }
+        let (color, king_side) = castling_right_side(right);
+        let row = back_rank_row(color);
+        let king = match color {
+            PieceColor::White => board.white_king_location.point(),
+            PieceColor::Black => board.black_king_location.point(),
+        };
+        let rook_col = BOARD_START + board.castling_rook_files[index];
+        assert_eq!(
+            board.board[king.0][king.1],
+            color.as_mask() | KING,
+            "castling right {} without its king",
+            right
+        );
+        assert_eq!(king.0, row, "castling right {} with the king moved", right);
+        assert_eq!(
+            board.board[row][rook_col],
+            color.as_mask() | ROOK,
+            "castling right {} without its rook",
+            right
+        );
+        assert_eq!(
+            rook_col > king.1,
+            king_side,
+            "castling right {} with the rook on the wrong side",
+            right
+        );
+    }
+
+    // the key of the same position set up from scratch, so pieces, castling rights or an en
+    // passant square out of sync with the board change it
+    let fen = board.to_fen();
+    let rebuilt = board_from_fen(&fen).expect("board's fen does not parse");
+    assert_eq!(
+        board.position_key(),
+        rebuilt.position_key(),
+        "position key differs from {}",
+        fen
+    );
+
+    // make_move also plays pseudo-legal moves, ie to find out whether they leave the king in
+    // check, so the side which just moved being in check is down to the move, not the board
+    let mut issues = validate_position(board);
+    issues.retain(|issue| *issue != ValidationIssue::OpponentInCheck);
+    assert!(
+        issues.is_empty(),
+        "invalid position after {:?}: {:?}\n{}",
+        board.last_move,
+        issues,
+        board.simple_board()
+    );
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -526,30 +1526,30 @@ mod tests {
     fn correct_en_passant_privileges() {
         let b =
             board_from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e4 0 1").unwrap();
-        assert_eq!(b.pawn_double_move.unwrap().0, BOARD_START + 4);
-        assert_eq!(b.pawn_double_move.unwrap().1, BOARD_START + 4);
+        assert_eq!(b.pawn_double_move.unwrap().row(), BOARD_START + 4);
+        assert_eq!(b.pawn_double_move.unwrap().col(), BOARD_START + 4);
     }
 
     #[test]
     fn correct_en_passant_privileges_black() {
         let b =
             board_from_fen("rnbqkbnr/ppppppp1/8/7p/8/8/PPPPPPPP/RNBQKBNR w KQkq h5 0 1").unwrap();
-        assert_eq!(b.pawn_double_move.unwrap().0, BOARD_START + 3);
-        assert_eq!(b.pawn_double_move.unwrap().1, BOARD_START + 7);
+        assert_eq!(b.pawn_double_move.unwrap().row(), BOARD_START + 3);
+        assert_eq!(b.pawn_double_move.unwrap().col(), BOARD_START + 7);
     }
 
     #[test]
     fn correct_king_location() {
         let b = board_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
-        assert_eq!(b.black_king_location, (2, 6));
-        assert_eq!(b.white_king_location, (9, 6));
+        assert_eq!(b.black_king_location, Square::from_algebraic("e8").unwrap());
+        assert_eq!(b.white_king_location, Square::from_algebraic("e1").unwrap());
     }
 
     #[test]
     fn correct_king_location_two() {
         let b = board_from_fen("6rk/1b4np/5pp1/1p6/8/1P3NP1/1B3P1P/5RK1 w KQkq - 0 1").unwrap();
-        assert_eq!(b.black_king_location, (2, 9));
-        assert_eq!(b.white_king_location, (9, 8));
+        assert_eq!(b.black_king_location.point(), (2, 9));
+        assert_eq!(b.white_king_location.point(), (9, 8));
     }
 
     #[test]
@@ -603,6 +1603,340 @@ mod tests {
         assert_eq!(b.board[9][8], BLACK | BISHOP);
     }
 
+    // Position validation tests
+
+    #[test]
+    fn valid_positions() {
+        for fen in &[
+            DEFAULT_FEN_STRING,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+        ] {
+            let b = board_from_fen(fen).unwrap();
+            assert_eq!(validate_position(&b), vec![]);
+        }
+    }
+
+    #[test]
+    fn validate_king_count() {
+        let b = board_from_fen("8/8/8/8/8/8/8/4K2K w - - 0 1").unwrap();
+        let issues = validate_position(&b);
+        assert!(issues.contains(&ValidationIssue::WrongKingCount(PieceColor::White, 2)));
+        assert!(issues.contains(&ValidationIssue::WrongKingCount(PieceColor::Black, 0)));
+    }
+
+    #[test]
+    fn validate_pawns_on_back_rank() {
+        let b = board_from_fen("3pk3/8/8/8/8/8/8/4K2P w - - 0 1").unwrap();
+        let issues = validate_position(&b);
+        assert_eq!(
+            issues,
+            vec![
+                ValidationIssue::PawnOnBackRank(Square::from_algebraic("d8").unwrap()),
+                ValidationIssue::PawnOnBackRank(Square::from_algebraic("h1").unwrap())
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_opponent_in_check() {
+        let b = board_from_fen("4k3/8/8/8/8/8/8/4KR2 b - - 0 1").unwrap();
+        assert_eq!(validate_position(&b), vec![]);
+        let b = board_from_fen("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1").unwrap();
+        assert_eq!(
+            validate_position(&b),
+            vec![ValidationIssue::OpponentInCheck]
+        );
+    }
+
+    #[test]
+    fn validate_castling_rights() {
+        let b = board_from_fen("r3k3/8/8/8/8/8/8/4K2R w KQkq - 0 1").unwrap();
+        assert_eq!(
+            validate_position(&b),
+            vec![
+                ValidationIssue::InvalidCastlingRights('Q'),
+                ValidationIssue::InvalidCastlingRights('k')
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_en_passant() {
+        let b = board_from_fen("4k3/8/8/8/8/8/8/4K3 w - e6 0 1").unwrap();
+        assert_eq!(
+            validate_position(&b),
+            vec![ValidationIssue::InvalidEnPassant(
+                Square::from_algebraic("e6").unwrap()
+            )]
+        );
+        let b = board_from_fen("4k3/8/8/4p3/8/8/8/4K3 b - e6 0 1").unwrap();
+        assert_eq!(
+            validate_position(&b),
+            vec![ValidationIssue::InvalidEnPassant(
+                Square::from_algebraic("e6").unwrap()
+            )]
+        );
+    }
+
+    #[test]
+    fn fen_round_trip() {
+        for fen in &[
+            DEFAULT_FEN_STRING,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 12 40",
+        ] {
+            let b = board_from_fen(fen).unwrap();
+            assert_eq!(b.to_fen(), *fen);
+        }
+    }
+
+    #[test]
+    fn chess960_castling_rights() {
+        // Shredder-FEN rook files, written back as KQkq as they are the outermost rooks
+        let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        let b = board_from_fen(fen).unwrap();
+        assert_eq!(b.castling_rights(), [true; 4]);
+        assert_eq!(b.castling_rook_files, [7, 5, 7, 5]);
+        assert_eq!(
+            b.to_fen(),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+        );
+        assert!(board_from_fen(&b.to_fen()).unwrap() == b);
+
+        // X-FEN KQkq castle with the outermost rook, an inner rook needs its file
+        let b = board_from_fen("4k3/8/8/8/8/8/8/RR2K3 w Q - 0 1").unwrap();
+        assert_eq!(b.castling_rook_files[1], 0);
+        let b = board_from_fen("4k3/8/8/8/8/8/8/RR2K3 w B - 0 1").unwrap();
+        assert_eq!(b.castling_rook_files[1], 1);
+        assert_eq!(b.to_fen(), "4k3/8/8/8/8/8/8/RR2K3 w B - 0 1");
+        assert!(validate_position(&b).is_empty());
+
+        assert!(board_from_fen("4k3/8/8/8/8/8/8/4K2R w KX - 0 1").is_err());
+        // a rook file on the wrong side of the king
+        let b = board_from_fen("4k3/8/8/8/8/8/8/4K2R w A - 0 1").unwrap();
+        assert_eq!(
+            validate_position(&b),
+            vec![ValidationIssue::InvalidCastlingRights('Q')]
+        );
+    }
+
+    #[test]
+    fn display_board() {
+        let b = board_from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let display = b.to_string();
+        let lines: Vec<&str> = display.lines().collect();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "a b c d e f g h");
+        assert_eq!(lines[1], "        k        8");
+        assert_eq!(lines[8], "        K     R  1");
+        assert_eq!(lines[9], b.to_fen());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut b = board_from_fen(fen).unwrap();
+        b.last_move = Some("e2e4".to_string());
+        let json = serde_json::to_string(&b).unwrap();
+        assert_eq!(
+            json,
+            format!("{{\"fen\":\"{}\",\"last_move\":\"e2e4\"}}", fen)
+        );
+        let round_trip: BoardState = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_trip, b);
+        assert_eq!(round_trip.to_fen(), fen);
+        assert_eq!(round_trip.last_move, b.last_move);
+        assert_eq!(round_trip.piece_lists, b.piece_lists);
+        assert_eq!(round_trip.attacks, b.attacks);
+        // a board the engine can not play from is rejected, rather than loaded as it is
+        let no_king = r#"{"fen":"8/8/8/8/8/8/8/4K3 w - - 0 1","last_move":null}"#;
+        assert!(serde_json::from_str::<BoardState>(no_king).is_err());
+
+        assert_eq!(
+            serde_json::to_string(&PieceColor::White).unwrap(),
+            "\"White\""
+        );
+        assert!(serde_json::from_str::<Square>("\"i9\"").is_err());
+    }
+
+    #[test]
+    fn null_move() {
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2";
+        let mut b = board_from_fen(fen).unwrap();
+        let en_passant = b.make_null_move();
+        assert_eq!(
+            b.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2"
+        );
+        b.unmake_null_move(en_passant);
+        assert_eq!(b.to_fen(), fen);
+    }
+
+    #[test]
+    fn render_board() {
+        let b = board_from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let options = RenderOptions {
+            coordinates_both_sides: true,
+            ..RenderOptions::default()
+        };
+        let mut out = vec![];
+        b.write_board(&mut out, &options).unwrap();
+        let rendered = String::from_utf8(out).unwrap();
+        assert_eq!(rendered, b.render(&options));
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "  a b c d e f g h");
+        assert_eq!(lines[1], "8         k        8");
+        assert_eq!(lines[8], "1         K     R  1");
+        assert_eq!(lines[9], "  a b c d e f g h");
+
+        assert!(b.render(&RenderOptions::pretty()).contains('♜'));
+        assert_eq!(
+            b.simple_board(),
+            format!("\n{}", b.render(&RenderOptions::default()))
+        );
+    }
+
+    #[test]
+    fn squares() {
+        let e4 = Square::from_algebraic("e4").unwrap();
+        assert_eq!((e4.file(), e4.rank()), (4, 3));
+        assert_eq!(e4.point(), (6, 6));
+        assert_eq!(e4.to_string(), "e4");
+        assert_eq!(Square::from_file_rank(4, 3), Some(e4));
+        assert_eq!(Square::new(6, 6), Some(e4));
+
+        assert_eq!(Square::from_file_rank(0, 0).unwrap().to_string(), "a1");
+        assert_eq!(Square::from_file_rank(7, 7).unwrap().to_string(), "h8");
+        assert!(Square::from_file_rank(8, 0).is_none());
+        assert!(Square::new(1, 5).is_none());
+        assert!(Square::new(5, BOARD_END).is_none());
+        for name in &["i1", "a9", "a0", "ax", "e", "e44", "é"] {
+            assert!(Square::from_algebraic(name).is_none(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn pieces() {
+        for c in "PNBRQKpnbrqk".chars() {
+            let piece = Piece::from_fen_char(c).unwrap();
+            assert_eq!(piece.fen_char(), c);
+            assert_eq!(Piece::from_u8(piece.as_u8()), Some(piece));
+        }
+        assert_eq!(
+            Piece::from_u8(WHITE | KNIGHT),
+            Some(Piece::new(PieceColor::White, PieceKind::Knight))
+        );
+        assert_eq!(Piece::from_u8(EMPTY), None);
+        assert_eq!(Piece::from_u8(SENTINEL), None);
+        assert_eq!(Piece::from_fen_char('x'), None);
+
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let piece_at = |name| b.piece_at(Square::from_algebraic(name).unwrap());
+        assert_eq!(
+            piece_at("d8"),
+            Some(Piece::new(PieceColor::Black, PieceKind::Queen))
+        );
+        assert_eq!(piece_at("e4"), None);
+    }
+
+    #[test]
+    fn iterate_pieces() {
+        let b = board_from_fen("4k3/8/8/3p4/8/8/8/R3K3 w - - 0 1").unwrap();
+        let pieces: Vec<String> = b
+            .pieces()
+            .map(|(square, piece)| format!("{}{}", piece, square))
+            .collect();
+        assert_eq!(pieces, vec!["ke8", "pd5", "Ra1", "Ke1"]);
+
+        let white: Vec<Square> = b.pieces_of(PieceColor::White).map(|(s, _)| s).collect();
+        assert_eq!(white.len(), 2);
+        assert_eq!(b.pieces_of(PieceColor::Black).count(), 2);
+        assert_eq!(
+            board_from_fen(DEFAULT_FEN_STRING).unwrap().pieces().count(),
+            32
+        );
+    }
+
+    #[test]
+    fn piece_lists() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let knights: Vec<Point> = b.piece_list(PieceColor::White, KNIGHT).iter().collect();
+        assert_eq!(knights, vec![(9, 3), (9, 8)]);
+        assert_eq!(b.piece_list(PieceColor::Black, PAWN).len(), 8);
+        assert!(b.piece_list(PieceColor::Black, KING).contains((2, 6)));
+
+        let mut b = b;
+        b.set_square((9, 3), EMPTY);
+        b.set_square((7, 4), WHITE | KNIGHT);
+        b.set_square((2, 6), WHITE | KNIGHT);
+        let knights: Vec<Point> = b.piece_list(PieceColor::White, KNIGHT).iter().collect();
+        assert_eq!(knights, vec![(2, 6), (7, 4), (9, 8)]);
+        assert!(b.piece_list(PieceColor::Black, KING).is_empty());
+    }
+
+    #[test]
+    fn material_and_phase() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        assert_eq!(b.material(PieceColor::White), 4000);
+        assert_eq!(b.non_pawn_material(PieceColor::Black), 3200);
+        assert_eq!(b.game_phase(), MAX_GAME_PHASE);
+
+        let b = board_from_fen("4k3/pp6/8/8/8/8/3P4/R3K3 w - - 0 1").unwrap();
+        assert_eq!(b.material(PieceColor::White), 600);
+        assert_eq!(b.non_pawn_material(PieceColor::White), 500);
+        assert_eq!(b.material(PieceColor::Black), 200);
+        assert_eq!(b.non_pawn_material(PieceColor::Black), 0);
+        assert_eq!(b.game_phase(), 2);
+
+        // extra queens can not push the phase past the start of the game
+        let b = board_from_fen("QQQQkQQQ/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(b.game_phase(), MAX_GAME_PHASE);
+    }
+
+    #[test]
+    fn strict_fen_parsing() {
+        assert!(board_from_fen_strict(DEFAULT_FEN_STRING).is_ok());
+        assert_eq!(
+            board_from_fen_strict("this isn't a fen string").err(),
+            Some(FenError::Parse(
+                "Could not parse fen string: Invalid fen string"
+            ))
+        );
+
+        // no black king, which board_from_fen accepts
+        let fen = "8/8/8/8/8/8/8/4K3 w - - 0 1";
+        assert!(board_from_fen(fen).is_ok());
+        let err = board_from_fen_strict(fen).err().unwrap();
+        assert_eq!(
+            err,
+            FenError::Invalid(vec![ValidationIssue::WrongKingCount(PieceColor::Black, 0)])
+        );
+        assert_eq!(err.to_string(), "Black has 0 kings, exactly one expected");
+
+        let b: BoardState = DEFAULT_FEN_STRING.parse().unwrap();
+        assert_eq!(b.to_fen(), DEFAULT_FEN_STRING);
+        assert_eq!(
+            "8/8/8/8/8/8/8/4K3 w - - 0 1".parse::<BoardState>().err(),
+            Some(err)
+        );
+    }
+
+    #[cfg(feature = "paranoid")]
+    #[test]
+    #[should_panic(expected = "castling right K without its rook")]
+    fn invariants_check_castling_rights() {
+        let mut b = board_from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert_board_invariants(&b);
+        // the rook taken off the board without the castling right going with it
+        b.set_square((BOARD_END - 1, BOARD_END - 1), EMPTY);
+        assert_board_invariants(&b);
+    }
+
     #[test]
     #[should_panic]
     fn bad_fen_string() {
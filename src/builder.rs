@@ -0,0 +1,177 @@
+#![allow(dead_code)]
+pub use crate::board::*;
+
+/*
+    Build a position piece by piece rather than writing out a fen string, ie
+
+    BoardBuilder::new()
+        .piece("e1", WHITE | KING)
+        .piece("e8", BLACK | KING)
+        .side_to_move(PieceColor::Black)
+        .build()
+
+    The position is checked by validate_position when it is built
+*/
+pub struct BoardBuilder {
+    // the pieces placed so far, the rest of the state is set when the position is built
+    board: BoardState,
+    to_move: PieceColor,
+    castling: String,
+    en_passant: Option<Square>,
+    half_move_clock: u16,
+    full_move_clock: u16,
+    // the first mistake made while building, reported by build
+    error: Option<&'static str>,
+}
+
+impl BoardBuilder {
+    /*
+        An empty board with white to move and no castling rights
+    */
+    pub fn new() -> BoardBuilder {
+        BoardBuilder {
+            board: board_from_fen("8/8/8/8/8/8/8/8 w - - 0 1").unwrap(),
+            to_move: PieceColor::White,
+            castling: "-".to_string(),
+            en_passant: None,
+            half_move_clock: 0,
+            full_move_clock: 1,
+            error: None,
+        }
+    }
+
+    /*
+        Put a piece (ie WHITE | KING) on a square given by its algebraic name, replacing any piece
+        already there
+    */
+    pub fn piece(mut self, square: &str, piece: u8) -> BoardBuilder {
+        match (Square::from_algebraic(square), Piece::from_u8(piece)) {
+            (Some(square), Some(_)) => self.board.set_square(square.point(), piece),
+            (None, _) => self.fail("Invalid square"),
+            (_, None) => self.fail("Invalid piece"),
+        }
+        self
+    }
+
+    pub fn side_to_move(mut self, color: PieceColor) -> BoardBuilder {
+        self.to_move = color;
+        self
+    }
+
+    /*
+        The castling rights as written in a fen string (ie KQkq, HAha or -)
+    */
+    pub fn castling(mut self, rights: &str) -> BoardBuilder {
+        self.castling = rights.to_string();
+        self
+    }
+
+    pub fn en_passant(mut self, square: &str) -> BoardBuilder {
+        match Square::from_algebraic(square) {
+            Some(square) => self.en_passant = Some(square),
+            None => self.fail("Invalid en passant square"),
+        }
+        self
+    }
+
+    pub fn half_move_clock(mut self, clock: u16) -> BoardBuilder {
+        self.half_move_clock = clock;
+        self
+    }
+
+    pub fn full_move_clock(mut self, clock: u16) -> BoardBuilder {
+        self.full_move_clock = clock;
+        self
+    }
+
+    /*
+        The finished position, or why it could not be built, in the same way as
+        board_from_fen_strict
+    */
+    pub fn build(&self) -> Result<BoardState, FenError> {
+        if let Some(error) = self.error {
+            return Err(FenError::Parse(error));
+        }
+        let mut board = self.board.clone();
+        let (rights, rook_files) =
+            parse_castling_rights(&self.castling, &board.board).map_err(FenError::Parse)?;
+        board.white_king_side_castle = rights[0];
+        board.white_queen_side_castle = rights[1];
+        board.black_king_side_castle = rights[2];
+        board.black_queen_side_castle = rights[3];
+        board.castling_rook_files = rook_files;
+        board.to_move = self.to_move;
+        board.pawn_double_move = self.en_passant;
+        board.half_move_clock = self.half_move_clock;
+        board.full_move_clock = self.full_move_clock;
+
+        // going through the fen string sets the king locations and checks the position
+        board_from_fen_strict(&board.to_fen())
+    }
+
+    fn fail(&mut self, error: &'static str) {
+        self.error.get_or_insert(error);
+    }
+}
+
+impl Default for BoardBuilder {
+    fn default() -> BoardBuilder {
+        BoardBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_position() {
+        let b = BoardBuilder::new()
+            .piece("e1", WHITE | KING)
+            .piece("h1", WHITE | ROOK)
+            .piece("e8", BLACK | KING)
+            .piece("d4", BLACK | PAWN)
+            .piece("e4", WHITE | PAWN)
+            .side_to_move(PieceColor::Black)
+            .castling("K")
+            .en_passant("e3")
+            .full_move_clock(12)
+            .build()
+            .unwrap();
+        assert_eq!(b.to_fen(), "4k3/8/8/8/3pP3/8/8/4K2R b K e3 0 12");
+        assert_eq!(b.white_king_location.to_string(), "e1");
+        assert_eq!(b.black_king_location.to_string(), "e8");
+        assert!(b == board_from_fen(&b.to_fen()).unwrap());
+    }
+
+    #[test]
+    fn build_rejects_mistakes() {
+        let builder = BoardBuilder::new()
+            .piece("e1", WHITE | KING)
+            .piece("e8", BLACK | KING);
+        assert!(builder.build().is_ok());
+
+        assert_eq!(
+            BoardBuilder::new().piece("e9", WHITE | KING).build().err(),
+            Some(FenError::Parse("Invalid square"))
+        );
+        assert_eq!(
+            BoardBuilder::new().piece("e1", SENTINEL).build().err(),
+            Some(FenError::Parse("Invalid piece"))
+        );
+        // no rook to castle with
+        assert_eq!(
+            builder.castling("Q").build().err(),
+            Some(FenError::Invalid(vec![
+                ValidationIssue::InvalidCastlingRights('Q')
+            ]))
+        );
+        assert_eq!(
+            BoardBuilder::new().piece("e1", WHITE | KING).build().err(),
+            Some(FenError::Invalid(vec![ValidationIssue::WrongKingCount(
+                PieceColor::Black,
+                0
+            )]))
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const CHESS960_POSITIONS: u16 = 960;
+
+// Placements of the two knights among the five squares left after the bishops and queen
+const KNIGHT_PLACEMENTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/*
+    Generate the Chess960 starting position with the given number (0-959), using the standard
+    Scharnagl numbering where 518 is the classical starting position
+
+    Castling rights are written Shredder-FEN style using the files of the rooks (ie HAha)
+    Returns None if the index is out of range
+*/
+pub fn chess960_fen(index: u16) -> Option<String> {
+    if index >= CHESS960_POSITIONS {
+        return None;
+    }
+
+    let mut back_rank = [' '; 8];
+    let mut n = index as usize;
+
+    // light squared bishop on b, d, f or h and dark squared bishop on a, c, e or g
+    back_rank[(n % 4) * 2 + 1] = 'b';
+    n /= 4;
+    back_rank[(n % 4) * 2] = 'b';
+    n /= 4;
+
+    place_on_empty_square(&mut back_rank, n % 6, 'q');
+    n /= 6;
+
+    // place the second knight first so the index of the first one is not shifted
+    let knights = KNIGHT_PLACEMENTS[n];
+    place_on_empty_square(&mut back_rank, knights.1, 'n');
+    place_on_empty_square(&mut back_rank, knights.0, 'n');
+
+    // the king always ends up between the two rooks
+    place_on_empty_square(&mut back_rank, 0, 'r');
+    place_on_empty_square(&mut back_rank, 0, 'k');
+    place_on_empty_square(&mut back_rank, 0, 'r');
+
+    let black: String = back_rank.iter().collect();
+    let white = black.to_uppercase();
+    let mut rook_files = back_rank
+        .iter()
+        .enumerate()
+        .filter(|(_, &p)| p == 'r')
+        .map(|(i, _)| (b'a' + i as u8) as char);
+    let queen_side_rook = rook_files.next().unwrap();
+    let king_side_rook = rook_files.next().unwrap();
+
+    Some(format!(
+        "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {}{}{}{} - 0 1",
+        black,
+        white,
+        king_side_rook.to_ascii_uppercase(),
+        queen_side_rook.to_ascii_uppercase(),
+        king_side_rook,
+        queen_side_rook
+    ))
+}
+
+/*
+    Generate a random Chess960 starting position, returning its number along with the fen string
+*/
+pub fn random_chess960_fen() -> (u16, String) {
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64 ^ d.as_secs())
+        .unwrap_or(0);
+    let index = (seed % CHESS960_POSITIONS as u64) as u16;
+    (index, chess960_fen(index).unwrap())
+}
+
+/*
+    Put the piece on the nth empty square of the back rank
+*/
+fn place_on_empty_square(back_rank: &mut [char; 8], n: usize, piece: char) {
+    let square = back_rank
+        .iter_mut()
+        .filter(|square| **square == ' ')
+        .nth(n)
+        .unwrap();
+    *square = piece;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::board_from_fen;
+
+    #[test]
+    fn classical_position() {
+        assert_eq!(
+            chess960_fen(518).unwrap(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+        );
+    }
+
+    #[test]
+    fn first_and_last_positions() {
+        assert_eq!(
+            chess960_fen(0).unwrap(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1"
+        );
+        assert_eq!(
+            chess960_fen(959).unwrap(),
+            "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w CAca - 0 1"
+        );
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert!(chess960_fen(960).is_none());
+    }
+
+    #[test]
+    fn all_positions_unique_and_parse() {
+        let mut fens: Vec<String> = (0..CHESS960_POSITIONS)
+            .map(|i| chess960_fen(i).unwrap())
+            .collect();
+        // the rights are read back from the rook files, so each side can castle either way
+        for fen in &fens {
+            let board = board_from_fen(fen).unwrap();
+            assert_eq!(board.castling_rights(), [true; 4]);
+        }
+        fens.sort();
+        fens.dedup();
+        assert_eq!(fens.len(), CHESS960_POSITIONS as usize);
+    }
+}
@@ -0,0 +1,191 @@
+pub use crate::board::*;
+use std::io::{self, BufRead, Write};
+
+const EDITOR_HELP: &str = "Commands:
+  put <square> <piece>   place a piece using its fen letter, ie put e4 N
+  remove <square>        remove the piece on a square
+  clear                  remove every piece from the board
+  side <w|b>             set the side to move
+  castle <KQkq|->        set the castling rights, or the rook files (ie HAha) for Chess960
+  ep <square|->          set the en passant capture square
+  show                   print the board
+  fen                    print the fen string of the position
+  done                   validate the position and finish editing
+  help                   print this message";
+
+#[derive(Debug, PartialEq, Eq)]
+enum EditorAction {
+    Continue,
+    Show,
+    PrintFen,
+    Help,
+    Done,
+}
+
+/*
+    Interactively edit a position from the terminal, starting from the given board
+
+    Returns the finished position once it has been validated, or None if input ran out first
+*/
+pub fn edit_position(board: &BoardState, simple_print: bool) -> Option<BoardState> {
+    let mut board = board.clone();
+    let stdin = io::stdin();
+    println!("{}", EDITOR_HELP);
+    loop {
+        print!("> ");
+        io::stdout().flush().expect("flush failed");
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return None;
+        }
+
+        match handle_command(&mut board, line.trim()) {
+            Ok(EditorAction::Continue) => {}
+            Ok(EditorAction::Show) => {
+                if simple_print {
+                    board.simple_print_board();
+                } else {
+                    board.pretty_print_board();
+                }
+            }
+            Ok(EditorAction::PrintFen) => println!("{}", board.to_fen()),
+            Ok(EditorAction::Help) => println!("{}", EDITOR_HELP),
+            Ok(EditorAction::Done) => match finish_position(&board) {
+                Ok(b) => return Some(b),
+                Err(err) => println!("{}", err),
+            },
+            Err(err) => println!("{}", err),
+        }
+    }
+}
+
+/*
+    Apply a single editor command to the board
+*/
+fn handle_command(board: &mut BoardState, line: &str) -> Result<EditorAction, &'static str> {
+    let command: Vec<&str> = line.split_whitespace().collect();
+    if command.is_empty() {
+        return Ok(EditorAction::Continue);
+    }
+
+    match (command[0], command.len()) {
+        ("put", 3) => {
+            let square = parse_square(command[1])?;
+            let mut chars = command[2].chars();
+            let piece = match (chars.next(), chars.next()) {
+                (Some(c), None) => get_piece_from_fen_string_char(c),
+                _ => None,
+            };
+            board.set_square(square.point(), piece.ok_or("Unrecognized piece")?);
+        }
+        ("remove", 2) => {
+            let square = parse_square(command[1])?;
+            board.set_square(square.point(), EMPTY);
+        }
+        ("clear", 1) => {
+            for row in BOARD_START..BOARD_END {
+                for col in BOARD_START..BOARD_END {
+                    board.set_square((row, col), EMPTY);
+                }
+            }
+        }
+        ("side", 2) => {
+            board.to_move = match command[1] {
+                "w" => PieceColor::White,
+                "b" => PieceColor::Black,
+                _ => return Err("Side to move must be w or b"),
+            };
+        }
+        ("castle", 2) => {
+            let (rights, rook_files) = match parse_castling_rights(command[1], &board.board) {
+                Ok(castling) => castling,
+                Err(_) => {
+                    return Err("Castling rights must be a combination of KQkq, rook files or -")
+                }
+            };
+            board.white_king_side_castle = rights[0];
+            board.white_queen_side_castle = rights[1];
+            board.black_king_side_castle = rights[2];
+            board.black_queen_side_castle = rights[3];
+            board.castling_rook_files = rook_files;
+        }
+        ("ep", 2) => {
+            board.pawn_double_move = match command[1] {
+                "-" => None,
+                square => Some(parse_square(square)?),
+            };
+        }
+        ("show", 1) => return Ok(EditorAction::Show),
+        ("fen", 1) => return Ok(EditorAction::PrintFen),
+        ("help", 1) => return Ok(EditorAction::Help),
+        ("done", 1) => return Ok(EditorAction::Done),
+        _ => return Err("Unrecognized command, type help for a list of commands"),
+    }
+    Ok(EditorAction::Continue)
+}
+
+fn parse_square(square: &str) -> Result<Square, &'static str> {
+    Square::from_algebraic(square).ok_or("Invalid square")
+}
+
+/*
+    Check the edited position makes sense and rebuild it from its fen string, so
+    derived values such as the king locations and piece values are correct
+*/
+fn finish_position(board: &BoardState) -> Result<BoardState, String> {
+    board_from_fen_strict(&board.to_fen()).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_up_position() {
+        let mut b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        for command in &[
+            "clear", "put e1 K", "put e8 k", "put d6 P", "side b", "castle -",
+        ] {
+            assert_eq!(handle_command(&mut b, command), Ok(EditorAction::Continue));
+        }
+        assert_eq!(handle_command(&mut b, "done"), Ok(EditorAction::Done));
+        let b = finish_position(&b).unwrap();
+        assert_eq!(b.to_fen(), "4k3/8/3P4/8/8/8/8/4K3 b - - 0 1");
+        assert_eq!(b.white_king_location.to_string(), "e1");
+        assert_eq!(b.black_king_location.to_string(), "e8");
+    }
+
+    #[test]
+    fn remove_piece() {
+        let mut b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        handle_command(&mut b, "remove e2").unwrap();
+        assert_eq!(
+            b.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn bad_commands() {
+        let mut b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        assert!(handle_command(&mut b, "put z9 K").is_err());
+        assert!(handle_command(&mut b, "put e4 X").is_err());
+        assert!(handle_command(&mut b, "put e4").is_err());
+        assert!(handle_command(&mut b, "side x").is_err());
+        assert!(handle_command(&mut b, "castle KX").is_err());
+        assert!(handle_command(&mut b, "fly").is_err());
+    }
+
+    #[test]
+    fn invalid_positions_rejected() {
+        let mut b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        handle_command(&mut b, "remove e8").unwrap();
+        assert!(finish_position(&b).is_err());
+
+        let mut b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        handle_command(&mut b, "remove h1").unwrap();
+        assert!(finish_position(&b).is_err());
+        handle_command(&mut b, "castle Qkq").unwrap();
+        assert!(finish_position(&b).is_ok());
+    }
+}
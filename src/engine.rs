@@ -1,6 +1,16 @@
 pub use crate::board::*;
 pub use crate::move_generation::*;
+use crate::move_picker::{History, MovePicker};
+use crate::san::move_to_san;
+pub use crate::search_tree::*;
+use crate::see::static_exchange;
+pub use crate::transposition::*;
+use crate::zobrist::splitmix64;
 use std::cmp;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /*
     Evaluation function based on https://www.chessprogramming.org/Simplified_Evaluation_Function
@@ -8,6 +18,106 @@ use std::cmp;
 
 pub static PIECE_VALUES: [i32; 7] = [0, 100, 320, 330, 500, 900, 20000];
 
+// Score of being checkmated at the root, a mate found 'n' plies from the root scoring n less, so
+// the quickest mate scores the most
+pub const CHECKMATE_SCORE: i32 = 99999999;
+
+// Longest line from the root in plies a checkmate can be found at
+const MAX_MATE_PLY: i32 = 1024;
+
+// Scores at least this far from zero are checkmates, however far from the root they were found
+pub const MATE_THRESHOLD: i32 = CHECKMATE_SCORE - MAX_MATE_PLY;
+
+// Number of plies at the start of quiescence search in which quiet checks are also searched
+const QUIESCENCE_CHECK_PLIES: u8 = 1;
+
+// Half moves without a capture or pawn move after which a winning evaluation starts being scaled
+// towards NO_PROGRESS_ADVANTAGE, reaching it when the fifty-move rule would end the game
+const NO_PROGRESS_HALF_MOVES: i32 = 20;
+const FIFTY_MOVE_HALF_MOVES: i32 = 100;
+
+// Centipawns of advantage an evaluation keeps however long no progress has been made, so only
+// winning scores are scaled and ordinary maneuvering is evaluated as it is
+const NO_PROGRESS_ADVANTAGE: i32 = 200;
+
+// Centipawns a side loses for each attack by the other side's pieces on its king's square and the
+// squares next to it, with every minor and major piece on the board (see king_safety_value)
+const KING_ZONE_ATTACK_WEIGHT: i32 = 8;
+
+// Centipawns each square a side's pieces could move to is worth to it (see total_mobility)
+const MOBILITY_WEIGHT: i32 = 2;
+
+// Largest distance in centipawns from zero a draw is scored at when draw randomization is enabled
+const DRAW_RANDOMIZATION_RANGE: i32 = 2;
+
+// Extra room given to a capture in quiescence search before delta pruning discards it
+const DELTA_PRUNING_MARGIN: i32 = 200;
+
+// Captures which lose more than this once the other side takes back (by static exchange evaluation)
+// are not searched in quiescence search
+const QUIESCENCE_SEE_THRESHOLD: i32 = 0;
+
+// Below this much non-king material on the board delta pruning is disabled, as single captures start to decide the game
+const DELTA_PRUNING_MIN_MATERIAL: i32 = 1300;
+
+// Number of nodes searched between looking at the clock and the stop flag to see if the search has
+// to give up
+const TIME_CHECK_NODES: u64 = 1024;
+
+// Nodes a deterministic search is given for each millisecond of its move time, about what the
+// optimized build searches in that time
+const DETERMINISTIC_NODES_PER_MS: u64 = 500;
+
+// Deepest a node can be for reverse futility pruning, which gives up on a position whose static
+// evaluation beats beta by REVERSE_FUTILITY_MARGIN centipawns for each ply of depth left
+const REVERSE_FUTILITY_MAX_DEPTH: u8 = 3;
+const REVERSE_FUTILITY_MARGIN: i32 = 120;
+
+// Deepest a node can be for razoring, which checks with quiescence search whether a position
+// evaluated RAZORING_MARGIN centipawns below alpha for each ply of depth left can be given up on
+const RAZORING_MAX_DEPTH: u8 = 2;
+const RAZORING_MARGIN: i32 = 300;
+
+// Shallowest a node can be for ProbCut, which searches captures PROBCUT_REDUCTION plies less deep
+// to see if one beats beta by PROBCUT_MARGIN centipawns
+const PROBCUT_MIN_DEPTH: u8 = 5;
+const PROBCUT_REDUCTION: u8 = 4;
+const PROBCUT_MARGIN: i32 = 200;
+
+// Shallowest a principal variation node without a move from the transposition table can be for
+// internal iterative deepening, which finds a move to try first with a search IID_REDUCTION plies
+// less deep
+const IID_MIN_DEPTH: u8 = 4;
+const IID_REDUCTION: u8 = 2;
+
+// Shallowest a node can be for a singular extension, which searches the move from the transposition
+// table a ply deeper when a search of half the depth finds every other move at least
+// SINGULAR_MARGIN centipawns for each ply of depth left worse than the table's score
+const SINGULAR_MIN_DEPTH: u8 = 6;
+const SINGULAR_MARGIN: i32 = 8;
+// How much shallower than the node the table's search of it can be for its score to be trusted
+const SINGULAR_TABLE_DEPTH: u8 = 3;
+// Deepest ply a singular extension is made at, so a line of forced moves can not be extended forever
+const SINGULAR_MAX_PLY: usize = 32;
+
+// Depth from which iterative deepening searches a window around the score of the depth before
+const ASPIRATION_MIN_DEPTH: u8 = 4;
+// Distance in centipawns from the previous score to each side of the first aspiration window,
+// doubled each time the score falls outside it until the window is open beyond ASPIRATION_MAX_WINDOW
+const ASPIRATION_WINDOW: i32 = 25;
+const ASPIRATION_MAX_WINDOW: i32 = 1000;
+
+// Shallowest depth iterative deepening can find the best move obvious at, and the fraction (1 / n)
+// of the move time it then spends. An obvious move is a recapture on the square the last move
+// captured on which a search of half the depth finds EASY_RECAPTURE_MARGIN centipawns better than
+// every other move, or a move which has been the best for EASY_MOVE_STABLE_DEPTHS depths and is
+// EASY_MOVE_MARGIN centipawns better
+const EASY_MOVE_MIN_DEPTH: u8 = 4;
+const EASY_MOVE_TIME_DIVISOR: u32 = 4;
+const EASY_RECAPTURE_MARGIN: i32 = 150;
+const EASY_MOVE_STABLE_DEPTHS: u8 = 3;
+const EASY_MOVE_MARGIN: i32 = 300;
+
 static PAWN_WEIGHTS: [[i32; 8]; 8] = [
     [0, 0, 0, 0, 0, 0, 0, 0],
     [50, 50, 50, 50, 50, 50, 50, 50],
@@ -85,31 +195,58 @@ static KING_LATE_GAME: [[i32; 8]; 8] = [
     [-50, -30, -30, -30, -30, -30, -30, -50],
 ];
 
-fn get_pos_evaluation(row: usize, col: usize, board: &BoardState, color: PieceColor) -> i32 {
-    let piece = board.board[row][col] & PIECE_MASK;
-    let col = col - BOARD_START;
+/*
+    Row and column of the square in the weight tables, which are written from white's side
+*/
+fn weight_table_index(square: (usize, usize), color: PieceColor) -> (usize, usize) {
+    let col = square.1 - BOARD_START;
     let row = match color {
-        PieceColor::White => row - BOARD_START,
-        _ => 9 - row,
+        PieceColor::White => square.0 - BOARD_START,
+        _ => 9 - square.0,
     };
+    (row, col)
+}
 
-    match piece {
+/*
+    Positional value of a piece on a square, positive for white pieces and negative for black ones
+    BoardState keeps the sum of these up to date as pieces move
+
+    Kings are valued separately by get_evaluation, as their table changes as the game goes on
+*/
+pub fn piece_square_value(square: (usize, usize), piece: u8) -> i32 {
+    let color = match get_color(piece) {
+        Some(c) => c,
+        None => return 0,
+    };
+    let (row, col) = weight_table_index(square, color);
+    let value = match piece & PIECE_MASK {
         PAWN => PAWN_WEIGHTS[row][col],
         ROOK => ROOK_WEIGHTS[row][col],
         BISHOP => BISHOP_WEIGHTS[row][col],
         KNIGHT => KNIGHT_WEIGHTS[row][col],
-        KING => {
-            if board.full_move_clock > 30 {
-                KING_LATE_GAME[row][col]
-            } else {
-                KING_WEIGHTS[row][col]
-            }
-        }
         QUEEN => QUEEN_WEIGHTS[row][col],
+        KING => 0,
         _ => panic!("Could not recognize piece"),
+    };
+    match color {
+        PieceColor::White => value,
+        PieceColor::Black => -value,
     }
 }
 
+fn king_square_value(board: &BoardState, color: PieceColor) -> i32 {
+    let mut value = 0;
+    for square in board.piece_list(color, KING).iter() {
+        let (row, col) = weight_table_index(square, color);
+        value += if board.full_move_clock > 30 {
+            KING_LATE_GAME[row][col]
+        } else {
+            KING_WEIGHTS[row][col]
+        };
+    }
+    value
+}
+
 /*
     Return a number to represent how good a certain position is
 
@@ -118,126 +255,2365 @@ fn get_pos_evaluation(row: usize, col: usize, board: &BoardState, color: PieceCo
 pub fn get_evaluation(board: &BoardState) -> i32 {
     let mut evaluation = board.white_total_piece_value;
     evaluation -= board.black_total_piece_value;
-    for row in BOARD_START..BOARD_END {
-        for col in BOARD_START..BOARD_END {
-            let square = board.board[row][col];
-            if is_empty(square) {
-                continue;
+    evaluation += board.piece_square_score;
+    evaluation += king_square_value(board, PieceColor::White);
+    evaluation -= king_square_value(board, PieceColor::Black);
+    evaluation += mobility_value(board);
+    evaluation += king_safety_value(board, PieceColor::White);
+    evaluation -= king_safety_value(board, PieceColor::Black);
+    scale_for_no_progress(evaluation, board)
+}
+
+/*
+    A penalty, so never positive, for the other side's attacks on a color's king and the squares
+    around it, read from the board's attack tables
+    It shrinks with the game phase, as with fewer pieces left to attack with the king is safe to
+    come forward
+*/
+fn king_safety_value(board: &BoardState, color: PieceColor) -> i32 {
+    let opponent = match color {
+        PieceColor::White => PieceColor::Black,
+        PieceColor::Black => PieceColor::White,
+    };
+    let mut attacks = 0;
+    for (row, col) in board.piece_list(color, KING).iter() {
+        for zone_row in row - 1..=row + 1 {
+            for zone_col in col - 1..=col + 1 {
+                if is_outside_board(board.board[zone_row][zone_col]) {
+                    continue;
+                }
+                attacks += board.attacks.count(opponent, (zone_row, zone_col)) as i32;
             }
+        }
+    }
+    -attacks * KING_ZONE_ATTACK_WEIGHT * board.game_phase() / MAX_GAME_PHASE
+}
 
-            if get_color(square) == Some(PieceColor::White) {
-                evaluation += get_pos_evaluation(row, col, board, PieceColor::White);
-            } else {
-                evaluation -= get_pos_evaluation(row, col, board, PieceColor::Black);
+/*
+    How much more freely white's pieces can move than black's, positive when white's can go to more
+    squares
+*/
+fn mobility_value(board: &BoardState) -> i32 {
+    let white = total_mobility(board, PieceColor::White) as i32;
+    let black = total_mobility(board, PieceColor::Black) as i32;
+    (white - black) * MOBILITY_WEIGHT
+}
+
+/*
+    Shrink a winning evaluation towards a draw once many moves have been played without a capture
+    or pawn move, so advantages which can not be made into progress (fortresses) are not overvalued
+    and the engine prefers lines that change the position while it is winning
+    Only the advantage beyond NO_PROGRESS_ADVANTAGE is shrunk, so a position which is not won is
+    evaluated the same however long it has been maneuvered in
+*/
+fn scale_for_no_progress(evaluation: i32, board: &BoardState) -> i32 {
+    let half_moves = board.half_move_clock as i32;
+    if half_moves <= NO_PROGRESS_HALF_MOVES || evaluation.abs() <= NO_PROGRESS_ADVANTAGE {
+        return evaluation;
+    }
+    let remaining = cmp::max(FIFTY_MOVE_HALF_MOVES - half_moves, 0);
+    let excess = evaluation.abs() - NO_PROGRESS_ADVANTAGE;
+    let scaled = excess * remaining / (FIFTY_MOVE_HALF_MOVES - NO_PROGRESS_HALF_MOVES);
+    evaluation.signum() * (NO_PROGRESS_ADVANTAGE + scaled)
+}
+
+/*
+    Settings which change how the search scores positions and how long it runs
+*/
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchOptions {
+    // When set, draws are scored as a small value around zero derived from this seed and the
+    // position rather than exactly zero, so the engine does not shuffle between equal positions
+    pub draw_randomization_seed: Option<u64>,
+    // When set, iterative deepening stops going deeper once this much time has been spent on a move
+    pub move_time: Option<Duration>,
+    // When set, iterative deepening stops going deeper once the search has visited this many nodes,
+    // giving up the depth it is searching then
+    pub node_limit: Option<u64>,
+    // When set, iterative deepening only looks for a checkmate by the side to move in at most this
+    // many moves, going no deeper than such a mate can be and stopping once one is found
+    pub mate_limit: Option<u8>,
+    // When set, the size of the transposition table in megabytes, otherwise DEFAULT_HASH_MEGABYTES
+    pub hash_megabytes: Option<usize>,
+    // When set, the number of best lines iterative deepening finds, each with a different first
+    // move, otherwise only the best line is found
+    pub multi_pv: Option<usize>,
+    // Centipawns a draw is scored below an equal position for the side the search is finding a
+    // move for, so it avoids draws against weaker opponents, or seeks them when negative
+    pub contempt: i32,
+    // When set, the search never looks at the clock, so the same position, options and game
+    // history always give the same move, score and node count: the move time is searched as a node
+    // limit of DETERMINISTIC_NODES_PER_MS nodes for each millisecond instead
+    pub deterministic: bool,
+}
+
+impl SearchOptions {
+    /*
+        An empty transposition table of the size given by the options
+    */
+    pub fn new_table(&self) -> Arc<TranspositionTable> {
+        let megabytes = self.hash_megabytes.unwrap_or(DEFAULT_HASH_MEGABYTES);
+        Arc::new(TranspositionTable::new(megabytes))
+    }
+
+    /*
+        The options a search runs with, a deterministic one's move time being made into a node
+        limit (or lowering the one it has)
+    */
+    fn for_search(&self) -> SearchOptions {
+        let mut options = *self;
+        if let Some(move_time) = options.move_time.filter(|_| options.deterministic) {
+            let nodes = (move_time.as_millis() as u64).saturating_mul(DETERMINISTIC_NODES_PER_MS);
+            options.node_limit = Some(options.node_limit.map_or(nodes, |limit| limit.min(nodes)));
+            options.move_time = None;
+        }
+        options
+    }
+}
+
+/*
+    The score given to a drawn position
+
+    The randomized score mixes the seed into the position's key, so the same seed gives the same
+    scores on every build
+*/
+fn draw_score(board: &BoardState, options: &SearchOptions) -> i32 {
+    match options.draw_randomization_seed {
+        None => 0,
+        Some(seed) => {
+            let mix = splitmix64(board.position_key() ^ splitmix64(seed));
+            let range = 2 * DRAW_RANDOMIZATION_RANGE as u64 + 1;
+            (mix % range) as i32 - DRAW_RANDOMIZATION_RANGE
+        }
+    }
+}
+
+/*
+    One of the best lines found by a search, and its score
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchLine {
+    pub score: i32,
+    // how the score relates to the line's true score, from white's point of view as in the table:
+    // a mate search which found no mate only knows the line is worse than the mates it looked for
+    pub bound: Bound,
+    // the moves the search expects to be played, starting with the best move
+    pub pv: Vec<Move>,
+}
+
+/*
+    Why iterative deepening found the best move obvious, and spent only part of the move time on it
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EasyMove {
+    // a recapture on the square the last move captured on, far better than any other move
+    Recapture,
+    // the best move for the last few depths, far better than any other move
+    Stable,
+}
+
+/*
+    A line iterative deepening found at a depth it finished searching
+*/
+pub struct DepthReport<'a> {
+    pub depth: u8,
+    // deepest ply from the root the search of the depth reached, counting quiescence search and
+    // extensions
+    pub seldepth: usize,
+    // positions visited and time spent by the search so far, over every depth
+    pub nodes: u64,
+    pub time: Duration,
+    // permille of the transposition table used by the search (see TranspositionTable::hashfull)
+    pub hashfull: usize,
+    // 1 for the best line, 2 for the second best and so on
+    pub multipv: usize,
+    pub line: &'a SearchLine,
+}
+
+/*
+    The rate a search visited positions at, 0 if no time was measured
+*/
+pub fn nodes_per_second(nodes: u64, time: Duration) -> u64 {
+    match time.as_micros() {
+        0 => 0,
+        micros => (nodes as u128 * 1_000_000 / micros) as u64,
+    }
+}
+
+// Called with what iterative deepening found each time it finishes a depth
+pub type DepthCallback = Box<dyn FnMut(&DepthReport)>;
+
+/*
+    A move the search is about to search at the root
+*/
+pub struct RootMoveReport {
+    pub depth: u8,
+    pub mov: Move,
+    // 1 for the first move searched at the root, 2 for the second and so on
+    pub number: usize,
+}
+
+// Called with each move the search starts searching at the root
+pub type RootMoveCallback = Box<dyn FnMut(&RootMoveReport)>;
+
+/*
+    Counts of how well the search's move ordering and pruning worked, kept over every depth, for
+    tuning them
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    // nodes of the main search which had a beta cutoff, and those where the first move caused it
+    pub cutoffs: u64,
+    pub first_move_cutoffs: u64,
+    // positions looked up in the transposition table, and those it had an entry for
+    pub table_probes: u64,
+    pub table_hits: u64,
+    // moves searched with a null window narrower than their node's, and those which had to be
+    // searched again with the full window
+    pub null_window_searches: u64,
+    pub re_searches: u64,
+    // nodes given up on by reverse futility pruning, razoring or ProbCut
+    pub pruned: u64,
+}
+
+impl SearchStats {
+    /*
+        Percentage of the nodes with a beta cutoff in which the first move caused it
+    */
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        percentage(self.first_move_cutoffs, self.cutoffs)
+    }
+
+    pub fn table_hit_rate(&self) -> f64 {
+        percentage(self.table_hits, self.table_probes)
+    }
+
+    /*
+        Percentage of the null window searches which had to be searched again
+    */
+    pub fn re_search_rate(&self) -> f64 {
+        percentage(self.re_searches, self.null_window_searches)
+    }
+}
+
+fn percentage(part: u64, whole: u64) -> f64 {
+    match whole {
+        0 => 0.0,
+        _ => part as f64 * 100.0 / whole as f64,
+    }
+}
+
+/*
+    Everything a search carries along with it while it runs
+*/
+pub struct SearchState {
+    pub options: SearchOptions,
+    // When set, the nodes visited by the search are recorded so it can be inspected afterwards
+    pub tree: Option<SearchTree>,
+    // Number of positions visited by the search, counting quiescence search
+    pub nodes: u64,
+    // Number of moves made from the position the search started from to the one being searched
+    ply: usize,
+    // Deepest ply reached by the depth iterative deepening searched last, counting quiescence
+    // search and extensions (the selective depth)
+    pub seldepth: usize,
+    // The last two quiet moves which caused a cutoff at each ply, tried early by the other
+    // positions searched at that ply
+    killers: Vec<[Option<Move>; 2]>,
+    // How often each quiet move has caused a cutoff, for ordering the quiet moves
+    history: History,
+    // When set, the next node searched skips this move, to see how the other moves compare to it
+    excluded_move: Option<Move>,
+    // Moves skipped at the root, the first moves of the lines already found at this depth
+    root_excluded: Vec<Move>,
+    // Keys of the positions played in the game before the root, then of the positions on the path
+    // from the root to the one being searched, for finding repetitions
+    keys: Vec<u64>,
+    // The best line found from the node being searched at each ply, made of the best move there and
+    // the line from the node after it (a triangular principal variation table)
+    pv: Vec<Vec<Move>>,
+    // The best lines found by the deepest search iterative deepening finished, best first
+    pub lines: Vec<SearchLine>,
+    // When set, called by iterative deepening with each line it finds at a depth
+    pub on_depth: Option<DepthCallback>,
+    // When set, called with each move as the search starts searching it at the root
+    pub on_root_move: Option<RootMoveCallback>,
+    // When set, the search gives up once this time has passed
+    pub deadline: Option<Instant>,
+    // The square the move played before the root captured on, for finding recaptures
+    pub last_capture: Option<Square>,
+    // Set by iterative deepening when it found the best move obvious and cut its move time short
+    pub easy_move: Option<EasyMove>,
+    // Set by another thread to make the search give up, ie when a GUI asks it to stop
+    pub stop: Arc<AtomicBool>,
+    // Whether the stop flag and node limit are looked at, which iterative deepening only does once
+    // it has a move
+    interruptible: bool,
+    // Set once the search has given up, the results of an unfinished search are not to be used
+    pub stopped: bool,
+    // Draws scored so far, so a node can tell whether its score depends on one
+    draws: u64,
+    pub stats: SearchStats,
+    // Positions already searched, kept between searches and shareable with other searches
+    pub table: Arc<TranspositionTable>,
+}
+
+impl SearchState {
+    /*
+        A search with a transposition table of its own, sized by the options
+    */
+    pub fn new(options: SearchOptions) -> SearchState {
+        SearchState::with_table(options, options.new_table())
+    }
+
+    /*
+        A search using an existing transposition table, ie one kept from the previous move of a
+        game, rather than starting from an empty one
+    */
+    pub fn with_table(options: SearchOptions, table: Arc<TranspositionTable>) -> SearchState {
+        SearchState {
+            options: options.for_search(),
+            tree: None,
+            nodes: 0,
+            ply: 0,
+            seldepth: 0,
+            killers: Vec::new(),
+            history: History::default(),
+            excluded_move: None,
+            root_excluded: Vec::new(),
+            keys: Vec::new(),
+            pv: Vec::new(),
+            lines: Vec::new(),
+            on_depth: None,
+            on_root_move: None,
+            deadline: None,
+            last_capture: None,
+            easy_move: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            interruptible: true,
+            stopped: false,
+            draws: 0,
+            stats: SearchStats::default(),
+            table,
+        }
+    }
+
+    /*
+        Whether the search has run past its deadline or node limit or been told to stop, the clock
+        and the stop flag are only read every TIME_CHECK_NODES nodes
+    */
+    fn out_of_time(&mut self) -> bool {
+        if !self.stopped && self.interruptible {
+            let limit = self.options.node_limit;
+            self.stopped = limit.is_some_and(|nodes| self.nodes > nodes);
+        }
+        if !self.stopped && self.nodes.is_multiple_of(TIME_CHECK_NODES) {
+            let past_deadline = self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline);
+            self.stopped = past_deadline || self.stop_requested();
+        }
+        self.stopped
+    }
+
+    fn stop_requested(&self) -> bool {
+        self.interruptible && self.stop.load(Ordering::Relaxed)
+    }
+
+    /*
+        Give the keys (see BoardState::position_key) of the positions played in the game before
+        the one searched from, oldest first, so the search knows which moves repeat them
+    */
+    pub fn set_game_history(&mut self, keys: &[u64]) {
+        self.keys = keys.to_vec();
+    }
+
+    /*
+        The score of a draw in the position being searched, with the contempt counted against the
+        side to move at the root, which the search is finding a move for
+    */
+    fn draw(&mut self, board: &BoardState) -> i32 {
+        self.draws += 1;
+        let root_to_move = if self.ply.is_multiple_of(2) {
+            board.to_move
+        } else {
+            match board.to_move {
+                PieceColor::White => PieceColor::Black,
+                PieceColor::Black => PieceColor::White,
             }
+        };
+        let contempt = match root_to_move {
+            PieceColor::White => -self.options.contempt,
+            PieceColor::Black => self.options.contempt,
+        };
+        draw_score(board, &self.options) + contempt
+    }
+
+    /*
+        Whether the position being searched, the last on the path, is a repetition of an earlier
+        position of the game or the search, with the same side to move
+        Nothing before the last capture or pawn move can repeat, so the search goes back no further
+    */
+    fn is_repetition(&self, board: &BoardState) -> bool {
+        let key = match self.keys.last() {
+            Some(key) => *key,
+            None => return false,
+        };
+        let earliest = self
+            .keys
+            .len()
+            .saturating_sub(board.half_move_clock as usize + 1);
+        self.keys[earliest..self.keys.len() - 1]
+            .iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
+            .any(|k| *k == key)
+    }
+
+    /*
+        The best line found by the deepest search iterative deepening finished
+    */
+    pub fn principal_variation(&self) -> &[Move] {
+        self.lines.first().map_or(&[], |line| &line.pv)
+    }
+
+    /*
+        Forget the line from the node being searched, at the start of searching it
+    */
+    fn clear_pv(&mut self) {
+        if self.pv.len() <= self.ply {
+            self.pv.resize(self.ply + 1, Vec::new());
+        }
+        self.pv[self.ply].clear();
+    }
+
+    /*
+        Make a move the best found at the node being searched, followed by the line from the node
+        it leads to, which was the last searched at the next ply
+    */
+    fn update_pv(&mut self, mov: Move) {
+        let (lines, next) = self.pv.split_at_mut(self.ply + 1);
+        let line = &mut lines[self.ply];
+        line.clear();
+        line.push(mov);
+        if let Some(next) = next.first() {
+            line.extend_from_slice(next);
+        }
+    }
+
+    fn killers(&self) -> [Option<Move>; 2] {
+        self.killers.get(self.ply).copied().unwrap_or([None, None])
+    }
+
+    fn add_killer(&mut self, mov: Move) {
+        let index = self.ply;
+        if self.killers.len() <= index {
+            self.killers.resize(index + 1, [None, None]);
+        }
+        let killers = &mut self.killers[index];
+        if killers[0] != Some(mov) {
+            killers[1] = killers[0];
+            killers[0] = Some(mov);
         }
     }
-    evaluation
 }
 
 /*
-    Run a standard alpha beta search to try and find the best move searching up to 'depth'
-    Orders moves by piece value to attempt to improve search efficiency
+    Run an alpha beta search to try and find the best move searching up to 'depth'
+    Moves are tried in the order given by MovePicker to attempt to improve search efficiency
+
+    This is a principal variation search: the first move at each node is expected to be the best,
+    so it is searched with the full window and the rest only with a null window, which proves
+    cheaply that they are no better. A move which turns out better is searched again with the full
+    window to find its score
 */
 pub fn alpha_beta_search(
+    board: &BoardState,
+    depth: u8,
+    alpha: i32,
+    beta: i32,
+    maximizing_player: PieceColor,
+    search: &mut SearchState,
+) -> (Option<BoardState>, i32) {
+    // a search which has given up visits no more positions, so none are counted
+    if search.stopped {
+        return (None, 0);
+    }
+    search.nodes += 1;
+    search.seldepth = search.seldepth.max(search.ply);
+    if search.out_of_time() {
+        return (None, 0);
+    }
+    if let Some(tree) = search.tree.as_mut() {
+        tree.enter(board.last_move.clone());
+    }
+    search.keys.push(board.position_key());
+    let (result, outcome) =
+        search_node(board, depth, alpha, beta, maximizing_player, false, search);
+    search.keys.pop();
+    if let Some(tree) = search.tree.as_mut() {
+        tree.exit(result.1, alpha, beta, outcome);
+    }
+    result
+}
+
+/*
+    Search a position already entered by alpha_beta_search, or by search_again, which sets
+    'searched_again' so the root's moves are only reported by the search of the root itself
+*/
+fn search_node(
     board: &BoardState,
     depth: u8,
     mut alpha: i32,
     mut beta: i32,
     maximizing_player: PieceColor,
-) -> (Option<BoardState>, i32) {
+    searched_again: bool,
+    search: &mut SearchState,
+) -> ((Option<BoardState>, i32), NodeOutcome) {
+    // draws scored before the node, to tell whether its own score depends on one
+    let draws = search.draws;
+
+    // a search without some of the moves, one being checked for a singular extension or the first
+    // moves of the lines already found at the root, says nothing about the position itself, so it
+    // neither uses nor stores the table's score, and is not pruned
+    let excluded = search.excluded_move.take();
+    let root = search.ply == 0;
+    let partial = excluded.is_some() || (root && !search.root_excluded.is_empty());
+    search.clear_pv();
+
+    // a position repeated after the root is scored as a draw, as the moves which led back to it can
+    // be played again until the repetition rule ends the game
+    if !root && search.is_repetition(board) {
+        let draw = search.draw(board);
+        return ((None, draw), NodeOutcome::Repetition);
+    }
+
+    // the fifty-move rule ends the game once the clock reaches the limit, unless the move which
+    // reached it gave checkmate
+    if !root && board.half_move_clock as i32 >= FIFTY_MOVE_HALF_MOVES {
+        if let GameStatus::Checkmate(_) = board.status() {
+            let score = mated_score(maximizing_player, search.ply);
+            return ((None, score), NodeOutcome::Checkmate);
+        }
+        let draw = search.draw(board);
+        return ((None, draw), NodeOutcome::FiftyMoveRule);
+    }
     if depth == 0 {
-        return (None, get_evaluation(board));
+        let evaluation = quiescence_search(board, alpha, beta, maximizing_player, 0, search);
+        return ((None, evaluation), NodeOutcome::Horizon);
     }
 
-    let mut moves = generate_moves(board);
+    // the position's key was worked out when it was added to the path, which it is the last of
+    let key = *search
+        .keys
+        .last()
+        .expect("position being searched is on the path");
 
-    if moves.is_empty() {
-        // here we add the depths to encourage faster checkmates
-        if maximizing_player == PieceColor::White {
-            if is_check(board, PieceColor::White) {
-                return (None, -99999999 - depth as i32); // checkmate
+    // a search of the position at least as deep may already have found its score, otherwise its
+    // best move is tried first. The root has to give a move to play, so it only uses an entry with
+    // one, as an entry left by an earlier search can bound its score without a move
+    let entry = search.table.probe(key);
+    search.stats.table_probes += 1;
+    search.stats.table_hits += entry.is_some() as u64;
+    if let Some(entry) = entry.filter(|e| e.depth >= depth && !partial) {
+        let score = entry.score(search.ply);
+        let best_move = entry.best_move.filter(|m| is_move_legal(board, *m));
+        let cutoff = match entry.bound {
+            Bound::Exact => true,
+            Bound::Lower => score >= beta,
+            Bound::Upper => score <= alpha,
+        };
+        if cutoff && (best_move.is_some() || !root) {
+            if let Some(mov) = best_move {
+                search.pv[search.ply].push(mov);
             }
-        } else if is_check(board, PieceColor::Black) {
-            return (None, 99999999 + depth as i32); // checkmate
+            let next_board = best_move.map(|m| make_move(board, &m));
+            return ((next_board, score), NodeOutcome::TableHit);
         }
-        return (None, 0); // stalemate
     }
 
-    let mut best_move = None;
-    if maximizing_player == PieceColor::White {
-        moves.sort_by(|a, b| piece_value_differential(b).cmp(&piece_value_differential(a)));
-        let mut best_val = i32::MIN;
-        for board in moves {
-            let evaluation = alpha_beta_search(&board, depth - 1, alpha, beta, PieceColor::Black);
-            if evaluation.1 > best_val {
-                best_val = evaluation.1;
-                best_move = Some(board);
+    // a position evaluated well enough beyond beta that a shallow search will not bring it back is
+    // given up on straight away (reverse futility pruning), except on the principal variation, where
+    // a score is needed, and in check, where the static evaluation says little
+    let checked = in_check(board);
+    let pv_node = alpha.saturating_add(1) < beta;
+    let prunable = !pv_node && !checked && !partial;
+    if prunable && depth <= REVERSE_FUTILITY_MAX_DEPTH {
+        let evaluation = get_evaluation(board);
+        let margin = REVERSE_FUTILITY_MARGIN * depth as i32;
+        let futile = match maximizing_player {
+            PieceColor::White => beta.abs() < MATE_THRESHOLD && evaluation - margin >= beta,
+            PieceColor::Black => alpha.abs() < MATE_THRESHOLD && evaluation + margin <= alpha,
+        };
+        if futile {
+            search.stats.pruned += 1;
+            return ((None, evaluation), NodeOutcome::Pruned);
+        }
+
+        // a position evaluated far enough below alpha is unlikely to be saved by anything but a
+        // capture, so it is given up on if quiescence search agrees it fails low (razoring)
+        let margin = RAZORING_MARGIN * depth as i32;
+        let hopeless = depth <= RAZORING_MAX_DEPTH
+            && match maximizing_player {
+                PieceColor::White => alpha.abs() < MATE_THRESHOLD && evaluation + margin <= alpha,
+                PieceColor::Black => beta.abs() < MATE_THRESHOLD && evaluation - margin >= beta,
+            };
+        if hopeless {
+            let evaluation = quiescence_search(board, alpha, beta, maximizing_player, 0, search);
+            let fails_low = match maximizing_player {
+                PieceColor::White => evaluation <= alpha,
+                PieceColor::Black => evaluation >= beta,
+            };
+            if fails_low {
+                search.stats.pruned += 1;
+                return ((None, evaluation), NodeOutcome::Pruned);
             }
-            alpha = cmp::max(alpha, evaluation.1);
-            if beta <= alpha {
+        }
+    }
+
+    // a capture which beats beta by a margin in a much shallower search is very likely to beat
+    // beta in a full depth one, so the position is given up on (ProbCut)
+    let raised = match maximizing_player {
+        PieceColor::White => beta.saturating_add(PROBCUT_MARGIN),
+        PieceColor::Black => alpha.saturating_sub(PROBCUT_MARGIN),
+    };
+    if prunable && depth >= PROBCUT_MIN_DEPTH && raised.abs() < MATE_THRESHOLD {
+        let mut captures = generate_captures(board);
+        captures.retain(|m| !loses_material(board, m));
+        captures.sort_by_cached_key(|m| cmp::Reverse(mvv_lva(board, m)));
+        let window = match maximizing_player {
+            PieceColor::White => (raised - 1, raised),
+            PieceColor::Black => (raised, raised + 1),
+        };
+        for mov in captures {
+            let next_board = make_move(board, &mov);
+            search.ply += 1;
+            let evaluation = alpha_beta_search(
+                &next_board,
+                depth - PROBCUT_REDUCTION,
+                window.0,
+                window.1,
+                next_board.to_move,
+                search,
+            );
+            search.ply -= 1;
+            if search.stopped {
                 break;
             }
+            let refutes = match maximizing_player {
+                PieceColor::White => evaluation.1 >= raised,
+                PieceColor::Black => evaluation.1 <= raised,
+            };
+            if refutes {
+                search.stats.pruned += 1;
+                return ((None, evaluation.1), NodeOutcome::Pruned);
+            }
         }
-        (best_move, best_val)
-    } else {
-        moves.sort_by(|a, b| piece_value_differential(a).cmp(&piece_value_differential(b)));
-        let mut best_val = i32::MAX;
-        for board in moves {
-            let evaluation = alpha_beta_search(&board, depth - 1, alpha, beta, PieceColor::White);
+    }
+
+    // the order of the moves matters most on the principal variation, so when the table has no move
+    // to try first there, a shallower search finds one (internal iterative deepening)
+    let mut entry = entry;
+    let has_move = entry.is_some_and(|e| e.best_move.is_some());
+    if pv_node && !has_move && !partial && depth >= IID_MIN_DEPTH {
+        let reduced = depth - IID_REDUCTION;
+        search_again(board, reduced, alpha, beta, maximizing_player, None, search);
+        entry = search.table.probe(key);
+    }
+
+    // when the table's move is much better than every other move, in a search of half the depth
+    // with a window just below the table's score, it is searched a ply deeper (singular extension)
+    let mut singular_move = None;
+    if let Some(entry) = entry.filter(|e| e.best_move.is_some() && !partial) {
+        let score = entry.score(search.ply);
+        let margin = SINGULAR_MARGIN * depth as i32;
+        // the table's score has to be at least what the move is worth to the side to move
+        let (window, at_least) = match maximizing_player {
+            PieceColor::White => (
+                (score - margin - 1, score - margin),
+                entry.bound != Bound::Upper,
+            ),
+            PieceColor::Black => (
+                (score + margin, score + margin + 1),
+                entry.bound != Bound::Lower,
+            ),
+        };
+        if at_least
+            && depth >= SINGULAR_MIN_DEPTH
+            && entry.depth + SINGULAR_TABLE_DEPTH >= depth
+            && score.abs() < MATE_THRESHOLD
+            && search.ply < SINGULAR_MAX_PLY
+        {
+            let verification = search_again(
+                board,
+                (depth - 1) / 2,
+                window.0,
+                window.1,
+                maximizing_player,
+                entry.best_move,
+                search,
+            );
+            let singular = match maximizing_player {
+                PieceColor::White => verification.1 < window.1,
+                PieceColor::Black => verification.1 > window.0,
+            };
+            if singular && !search.stopped {
+                singular_move = entry.best_move;
+            }
+        }
+    }
+
+    let (alpha_start, beta_start) = (alpha, beta);
+    let mut moves = MovePicker::new(board, entry.and_then(|e| e.best_move), search.killers());
+    let mut best_move: Option<(Move, BoardState)> = None;
+    let mut outcome = NodeOutcome::Searched;
+    let mut best_val = match maximizing_player {
+        PieceColor::White => i32::MIN,
+        PieceColor::Black => i32::MAX,
+    };
+    let mut searched_any = false;
+    let mut move_number = 0;
+    while let Some(mov) = moves.next_move(&search.history) {
+        if Some(mov) == excluded || (root && search.root_excluded.contains(&mov)) {
+            continue;
+        }
+        let child_depth = if Some(mov) == singular_move {
+            depth
+        } else {
+            depth - 1
+        };
+        let window = if !searched_any {
+            (alpha, beta)
+        } else if maximizing_player == PieceColor::White {
+            (alpha, alpha.saturating_add(1))
+        } else {
+            (beta.saturating_sub(1), beta)
+        };
+        searched_any = true;
+        move_number += 1;
+        if root && !searched_again && excluded.is_none() {
+            if let Some(on_root_move) = search.on_root_move.as_mut() {
+                on_root_move(&RootMoveReport {
+                    depth,
+                    mov,
+                    number: move_number,
+                });
+            }
+        }
+        let next_board = make_move(board, &mov);
+        search.ply += 1;
+        let mut evaluation = alpha_beta_search(
+            &next_board,
+            child_depth,
+            window.0,
+            window.1,
+            next_board.to_move,
+            search,
+        );
+        let null_window = window != (alpha, beta);
+        search.stats.null_window_searches += null_window as u64;
+        if !search.stopped && null_window && alpha < evaluation.1 && evaluation.1 < beta {
+            search.stats.re_searches += 1;
+            evaluation = alpha_beta_search(
+                &next_board,
+                child_depth,
+                alpha,
+                beta,
+                next_board.to_move,
+                search,
+            );
+        }
+        search.ply -= 1;
+        if search.stopped {
+            break;
+        }
+        if maximizing_player == PieceColor::White {
+            if evaluation.1 > best_val {
+                best_val = evaluation.1;
+                best_move = Some((mov, next_board));
+                search.update_pv(mov);
+            }
+            alpha = cmp::max(alpha, evaluation.1);
+        } else {
             if evaluation.1 < best_val {
                 best_val = evaluation.1;
-                best_move = Some(board);
+                best_move = Some((mov, next_board));
+                search.update_pv(mov);
             }
             beta = cmp::min(beta, evaluation.1);
-            if beta <= alpha {
-                break;
+        }
+        if beta <= alpha {
+            if !mov.is_noisy() {
+                search.add_killer(mov);
+                search.history.add_cutoff(board.to_move, mov, depth);
             }
+            search.stats.cutoffs += 1;
+            search.stats.first_move_cutoffs += (move_number == 1) as u64;
+            outcome = NodeOutcome::BetaCutoff;
+            break;
         }
-        (best_move, best_val)
     }
-}
 
-fn piece_value_differential(board: &BoardState) -> i32 {
-    board.white_total_piece_value - board.black_total_piece_value
+    if !searched_any {
+        // with the only moves skipped, the other moves (there are none) are worse than any score
+        if partial {
+            let score = match maximizing_player {
+                PieceColor::White => alpha,
+                PieceColor::Black => beta,
+            };
+            return ((None, score), NodeOutcome::Searched);
+        }
+        if checked {
+            let score = mated_score(maximizing_player, search.ply);
+            return ((None, score), NodeOutcome::Checkmate);
+        }
+        let draw = search.draw(board);
+        return ((None, draw), NodeOutcome::Stalemate);
+    }
+
+    // with contempt a draw's score depends on which side is to move at the root, so a score which
+    // depends on one is not kept for other searches, which can be for the other side
+    let draw_free = search.draws == draws || search.options.contempt == 0;
+    if !search.stopped && !partial && draw_free {
+        // a search which failed low for the side to move has no idea which move is best, but one
+        // which beat the side's bound keeps the move, ie the refutation found by a cutoff
+        let improved = match maximizing_player {
+            PieceColor::White => best_val > alpha_start,
+            PieceColor::Black => best_val < beta_start,
+        };
+        let table_move = best_move.as_ref().map(|m| m.0).filter(|_| improved);
+        let bound = if best_val <= alpha_start {
+            Bound::Upper
+        } else if best_val >= beta_start {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        search
+            .table
+            .store(key, depth, search.ply, best_val, bound, table_move);
+    }
+    ((best_move.map(|m| m.1), best_val), outcome)
 }
 
 /*
-    Play a game in the terminal where the engine plays against itself
+    Search the position being searched again, ie less deep to find a move to try first, without it
+    counting as another position on the path from the root, so the positions it is compared with
+    for repetitions and the search tree stay as they are
+    'excluded' is a move the search skips, which it takes straight away so it can not be left for
+    the next node searched
 */
-pub fn play_game_against_self(b: &BoardState, depth: u8, max_moves: u8, simple_print: bool) {
-    let mut board = b.clone();
+fn search_again(
+    board: &BoardState,
+    depth: u8,
+    alpha: i32,
+    beta: i32,
+    maximizing_player: PieceColor,
+    excluded: Option<Move>,
+    search: &mut SearchState,
+) -> (Option<BoardState>, i32) {
+    search.excluded_move = excluded;
+    search_node(board, depth, alpha, beta, maximizing_player, true, search).0
+}
 
-    let show_board = |simple_print: bool, b: &BoardState| {
-        if simple_print {
-            b.simple_print_board()
-        } else {
-            b.pretty_print_board()
+/*
+    Search to depth 1, then 2, then 3 and so on up to 'max_depth', returning the best move and
+    score of the deepest search which finished, and the depth it reached
+
+    When the options give a move time, no deeper search is started once it has passed, and the
+    search running then is given up, as it is once the options' node limit is passed or when
+    another thread sets the search's stop flag. Depth 1 always finishes so there is a move to play.
+    A best move found obvious (see easy_move) is played once a fraction of the move time is spent.
+    Killer moves, history and the transposition table are kept from one depth to the next, so each
+    search is ordered by the ones before it
+
+    When the options give a mate limit, only checkmates within it are looked for: each depth is
+    searched with a window which everything slower than them falls below, and the search stops once
+    one is found
+
+    When the options ask for several lines (multi pv), each depth is searched once for each line,
+    every search skipping the first moves of the lines found before it at that depth
+*/
+pub fn iterative_deepening_search(
+    board: &BoardState,
+    max_depth: u8,
+    search: &mut SearchState,
+) -> ((Option<BoardState>, i32), u8) {
+    let start = Instant::now();
+    search.table.new_search();
+    search.lines.clear();
+    let line_count = (search.options.multi_pv.unwrap_or(1))
+        .min(generate_moves(board).len())
+        .max(1);
+    let mate_limit = search.options.mate_limit.map(|moves| moves.max(1));
+    let (max_depth, window) = match mate_limit {
+        // mating in n moves takes 2n - 1 plies
+        Some(moves) => {
+            let plies = moves.saturating_mul(2) - 1;
+            let slowest = CHECKMATE_SCORE - plies as i32;
+            let window = match board.to_move {
+                PieceColor::White => (slowest - 1, i32::MAX),
+                PieceColor::Black => (i32::MIN, -slowest + 1),
+            };
+            (plies, window)
         }
+        None => (max_depth, (i32::MIN, i32::MAX)),
     };
+    let mut best = (None, 0);
+    let mut depth_reached = 0;
+    search.easy_move = None;
+    // the best move of the depths searched so far, and how many depths in a row it has been best
+    let mut best_move = None;
+    let mut stable_depths = 0;
+    for depth in 1..=max_depth.max(1) {
+        search.deadline = match search.options.move_time {
+            Some(move_time) if depth > 1 && search.easy_move.is_some() => {
+                Some(start + move_time / EASY_MOVE_TIME_DIVISOR)
+            }
+            Some(move_time) if depth > 1 => Some(start + move_time),
+            _ => None,
+        };
+        search.interruptible = depth > 1;
+        let past_deadline = search
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        let past_node_limit =
+            (search.options.node_limit).is_some_and(|nodes| search.nodes >= nodes);
+        if past_deadline || (search.interruptible && past_node_limit) || search.stop_requested() {
+            break;
+        }
+        let mut result = (None, 0);
+        let mut lines = Vec::with_capacity(line_count);
+        search.seldepth = 0;
+        search.root_excluded.clear();
+        while lines.len() < line_count {
+            let line_result = match search.lines.get(lines.len()) {
+                Some(line) if depth >= ASPIRATION_MIN_DEPTH && mate_limit.is_none() => {
+                    aspiration_search(board, depth, line.score, search)
+                }
+                _ => alpha_beta_search(board, depth, window.0, window.1, board.to_move, search),
+            };
+            if search.stopped {
+                break;
+            }
+            let mut pv = search.pv[0].clone();
+            extend_from_table(board, &mut pv, depth, &search.table);
+            search.root_excluded.extend(pv.first());
+            let score = line_result.1;
+            let bound = if score <= window.0 {
+                Bound::Upper
+            } else if score >= window.1 {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            if lines.is_empty() {
+                result = line_result;
+            }
+            lines.push(SearchLine { score, bound, pv });
+        }
+        search.root_excluded.clear();
+        if search.stopped {
+            break;
+        }
+        best = result;
+        depth_reached = depth;
+        search.lines = lines;
+        if let Some(on_depth) = search.on_depth.as_mut() {
+            let (time, hashfull) = (start.elapsed(), search.table.hashfull());
+            for (index, line) in search.lines.iter().enumerate() {
+                on_depth(&DepthReport {
+                    depth,
+                    seldepth: search.seldepth,
+                    nodes: search.nodes,
+                    time,
+                    hashfull,
+                    multipv: index + 1,
+                    line,
+                });
+            }
+        }
+        let mates = match board.to_move {
+            PieceColor::White => best.1 >= MATE_THRESHOLD,
+            PieceColor::Black => best.1 <= -MATE_THRESHOLD,
+        };
+        if mate_limit.is_some() && mates {
+            break;
+        }
 
-    show_board(simple_print, &board);
-    while board.full_move_clock < max_moves {
-        let res = alpha_beta_search(&board, depth, i32::MIN, i32::MAX, board.to_move);
-        if res.0.is_some() {
-            board = res.0.unwrap().clone();
+        // there is only time to save when the options give a move time, and a mate search or one
+        // for several lines has to go on whatever the best move is
+        let depth_best = search.lines[0].pv.first().copied();
+        stable_depths = if depth_best == best_move {
+            stable_depths + 1
         } else {
-            break;
+            1
+        };
+        best_move = depth_best;
+        let saves_time = search.options.move_time.is_some() && mate_limit.is_none();
+        if saves_time && line_count == 1 && search.easy_move.is_none() {
+            if let Some(mov) = best_move.filter(|_| depth >= EASY_MOVE_MIN_DEPTH) {
+                search.easy_move = easy_move(board, depth, mov, best.1, stable_depths, search);
+            }
         }
-        show_board(simple_print, &board);
     }
+    search.deadline = None;
+    search.interruptible = true;
+    search.stopped = false;
+    (best, depth_reached)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    #[test]
-    fn right_values() {
-        assert_eq!(PIECE_VALUES[PAWN as usize], 100);
-        assert_eq!(PIECE_VALUES[KNIGHT as usize], 320);
-        assert_eq!(PIECE_VALUES[BISHOP as usize], 330);
+/*
+    Whether the best move found by a search of 'depth' is obvious: a recapture on the square the
+    last move captured on, or a move which has been the best for 'stable_depths' depths in a row,
+    which a search of half the depth without it finds far better than every other move
+*/
+fn easy_move(
+    board: &BoardState,
+    depth: u8,
+    mov: Move,
+    score: i32,
+    stable_depths: u8,
+    search: &mut SearchState,
+) -> Option<EasyMove> {
+    let (easy, margin) = if mov.is_capture() && Some(mov.to) == search.last_capture {
+        (EasyMove::Recapture, EASY_RECAPTURE_MARGIN)
+    } else if stable_depths >= EASY_MOVE_STABLE_DEPTHS {
+        (EasyMove::Stable, EASY_MOVE_MARGIN)
+    } else {
+        return None;
+    };
+    if score.abs() >= MATE_THRESHOLD {
+        return None;
+    }
+    // a null window the margin below the score for the side to move, which the other moves all
+    // fail low on when the move is that much better
+    let window = match board.to_move {
+        PieceColor::White => (score - margin - 1, score - margin),
+        PieceColor::Black => (score + margin, score + margin + 1),
+    };
+    search.excluded_move = Some(mov);
+    let others = alpha_beta_search(board, depth / 2, window.0, window.1, board.to_move, search);
+    let worse = match board.to_move {
+        PieceColor::White => others.1 < window.1,
+        PieceColor::Black => others.1 > window.0,
+    };
+    Some(easy).filter(|_| worse && !search.stopped)
+}
+
+/*
+    The square the move from 'before' to 'after' captured on, where it can be recaptured, None
+    when it captured nothing
+*/
+pub fn capture_square(before: &BoardState, after: &BoardState) -> Option<Square> {
+    if after.pieces().count() >= before.pieces().count() {
+        return None;
+    }
+    let last_move = after.last_move.as_ref()?;
+    Square::from_algebraic(last_move.get(2..4)?)
+}
+
+/*
+    Continue a line cut short by a table cutoff with the best moves the table has for the
+    positions after it, up to 'length' moves, checking each move is legal as two positions can
+    share a key
+*/
+fn extend_from_table(
+    board: &BoardState,
+    line: &mut Vec<Move>,
+    length: u8,
+    table: &TranspositionTable,
+) {
+    let mut board = board.clone();
+    for mov in line.iter() {
+        board = make_move(&board, mov);
+    }
+    while line.len() < length as usize {
+        let mov = match table.probe(board.position_key()).and_then(|e| e.best_move) {
+            Some(mov) if is_move_legal(&board, mov) => mov,
+            _ => break,
+        };
+        board = make_move(&board, &mov);
+        line.push(mov);
+    }
+}
+
+/*
+    Search with a narrow window around the score expected from the search of the depth before
+    (an aspiration window), which cuts off more than a full window when the score hardly changes
+
+    A score outside the window only bounds the true score, so the side it fell outside is widened
+    and the position searched again, until the score lands inside
+*/
+fn aspiration_search(
+    board: &BoardState,
+    depth: u8,
+    expected: i32,
+    search: &mut SearchState,
+) -> (Option<BoardState>, i32) {
+    let mut delta = ASPIRATION_WINDOW;
+    let mut alpha = expected.saturating_sub(delta);
+    let mut beta = expected.saturating_add(delta);
+    loop {
+        let result = alpha_beta_search(board, depth, alpha, beta, board.to_move, search);
+        if search.stopped {
+            return result;
+        }
+        delta = delta.saturating_mul(2);
+        let open = delta > ASPIRATION_MAX_WINDOW;
+        if result.1 <= alpha && alpha != i32::MIN {
+            alpha = if open {
+                i32::MIN
+            } else {
+                result.1.saturating_sub(delta)
+            };
+        } else if result.1 >= beta && beta != i32::MAX {
+            beta = if open {
+                i32::MAX
+            } else {
+                result.1.saturating_add(delta)
+            };
+        } else {
+            return result;
+        }
+    }
+}
+
+/*
+    Search captures and promotions from the given position until it is quiet, so the
+    evaluation is never taken in the middle of an exchange
+
+    'ply' counts the plies of quiescence search so far, 'search' the plies from the root which
+    checkmates are scored by
+
+    In the first QUIESCENCE_CHECK_PLIES plies quiet checking moves are searched as well, and
+    a side in check must find an evasion rather than standing pat, so short mates just beyond
+    the horizon are seen
+
+    Captures which could not bring the score back to alpha, even if the captured piece was
+    won for free, are skipped (delta pruning) unless the game is in a late endgame
+*/
+pub fn quiescence_search(
+    board: &BoardState,
+    mut alpha: i32,
+    mut beta: i32,
+    maximizing_player: PieceColor,
+    ply: u8,
+    search: &mut SearchState,
+) -> i32 {
+    if search.stopped {
+        return 0;
+    }
+    search.nodes += 1;
+    search.seldepth = search.seldepth.max(search.ply);
+    if search.out_of_time() {
+        return 0;
+    }
+    let in_check = ply <= QUIESCENCE_CHECK_PLIES && in_check(board);
+    let search_checks = ply < QUIESCENCE_CHECK_PLIES;
+    // quiet moves are only needed as evasions or checks, otherwise the captures are enough
+    let moves = if in_check {
+        generate_moves(board)
+    } else if search_checks {
+        let mut moves = generate_captures(board);
+        moves.extend(generate_quiet_checks(board));
+        moves
+    } else {
+        generate_captures(board)
+    };
+    if in_check && moves.is_empty() {
+        return mated_score(maximizing_player, search.ply);
+    }
+
+    // there is no standing pat when in check, every evasion has to be searched
+    let stand_pat = get_evaluation(board);
+    let mut best_val = stand_pat;
+    if in_check {
+        best_val = match maximizing_player {
+            PieceColor::White => i32::MIN,
+            PieceColor::Black => i32::MAX,
+        };
+    } else if maximizing_player == PieceColor::White {
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = cmp::max(alpha, stand_pat);
+    } else {
+        if stand_pat <= alpha {
+            return stand_pat;
+        }
+        beta = cmp::min(beta, stand_pat);
+    }
+
+    // captures which lose material are skipped, but an evasion has to be searched whatever it loses
+    let mut moves: Vec<Move> = moves
+        .into_iter()
+        .filter(|mov| in_check || !loses_material(board, mov))
+        .collect();
+    moves.sort_by_cached_key(|m| cmp::Reverse(mvv_lva(board, m)));
+    let moves: Vec<(BoardState, i32)> = moves
+        .into_iter()
+        .map(|mov| (make_move(board, &mov), material_won(board, &mov)))
+        .collect();
+
+    // only captures are delta pruned, quiet checks and evasions are always searched
+    let delta_pruning = !in_check && !is_late_endgame(board);
+    if maximizing_player == PieceColor::White {
+        for (board, gain) in moves {
+            if delta_pruning && gain > 0 && stand_pat + gain + DELTA_PRUNING_MARGIN <= alpha {
+                continue;
+            }
+            search.ply += 1;
+            let evaluation =
+                quiescence_search(&board, alpha, beta, PieceColor::Black, ply + 1, search);
+            search.ply -= 1;
+            best_val = cmp::max(best_val, evaluation);
+            alpha = cmp::max(alpha, evaluation);
+            if beta <= alpha {
+                break;
+            }
+        }
+    } else {
+        for (board, gain) in moves {
+            if delta_pruning && gain > 0 && stand_pat - gain - DELTA_PRUNING_MARGIN >= beta {
+                continue;
+            }
+            search.ply += 1;
+            let evaluation =
+                quiescence_search(&board, alpha, beta, PieceColor::White, ply + 1, search);
+            search.ply -= 1;
+            best_val = cmp::min(best_val, evaluation);
+            beta = cmp::min(beta, evaluation);
+            if beta <= alpha {
+                break;
+            }
+        }
+    }
+    best_val
+}
+
+/*
+    The score of a side being checkmated 'ply' moves from the root of the search
+*/
+fn mated_score(color: PieceColor, ply: usize) -> i32 {
+    let score = CHECKMATE_SCORE - ply as i32;
+    match color {
+        PieceColor::White => -score,
+        PieceColor::Black => score,
+    }
+}
+
+/*
+    The number of moves (not plies) until a checkmate with a checkmate score, positive when white
+    gives the mate and negative when black does, None for any other score
+*/
+pub fn moves_to_mate(score: i32) -> Option<i32> {
+    if score.abs() < MATE_THRESHOLD {
+        return None;
+    }
+    let moves = (CHECKMATE_SCORE - score.abs() + 1) / 2;
+    Some(if score > 0 { moves } else { -moves })
+}
+
+/*
+    The material the side to move wins by playing a move
+    This counts both the captured piece and any promotion
+*/
+pub fn material_won(board: &BoardState, mov: &Move) -> i32 {
+    let captured = if mov.flags & MOVE_EN_PASSANT != 0 {
+        PAWN
+    } else {
+        board.board[mov.to.row()][mov.to.col()] & PIECE_MASK
+    };
+    let mut gain = PIECE_VALUES[captured as usize];
+    if let Some(kind) = mov.promotion {
+        gain += PIECE_VALUES[kind.as_bits() as usize] - PIECE_VALUES[PAWN as usize];
+    }
+    gain
+}
+
+/*
+    Score for trying captures and promotions in order, most valuable victim (counting the
+    promotion) first, and with the same victim the least valuable attacker first
+
+    The material won is weighted by the value of a king, which no attacker is worth more than, so
+    the victim always decides the order before the attacker. Quiet moves score below every capture
+*/
+pub fn mvv_lva(board: &BoardState, mov: &Move) -> i32 {
+    let attacker = board.board[mov.from.row()][mov.from.col()] & PIECE_MASK;
+    material_won(board, mov) * PIECE_VALUES[KING as usize] - PIECE_VALUES[attacker as usize]
+}
+
+/*
+    Whether a capture or promotion loses material to the recaptures which follow it
+    A capture of a piece worth at least as much as the capturing piece can never lose material, so
+    the exchange is only worked out for the others
+*/
+fn loses_material(board: &BoardState, mov: &Move) -> bool {
+    if !mov.is_noisy() {
+        return false;
+    }
+    let attacker = board.board[mov.from.row()][mov.from.col()] & PIECE_MASK;
+    if mov.promotion.is_none() && PIECE_VALUES[attacker as usize] <= material_won(board, mov) {
+        return false;
+    }
+    static_exchange(board, mov) < -QUIESCENCE_SEE_THRESHOLD
+}
+
+/*
+    Kings aside, only a few minor pieces or a rook are left on the board
+*/
+fn is_late_endgame(board: &BoardState) -> bool {
+    board.material(PieceColor::White) + board.material(PieceColor::Black)
+        < DELTA_PRUNING_MIN_MATERIAL
+}
+
+/*
+    The only legal move in the position, if there is exactly one, which can be played
+    straight away without searching
+*/
+pub fn forced_move(board: &BoardState) -> Option<BoardState> {
+    let mut moves = generate_child_boards(board);
+    if moves.len() == 1 {
+        moves.pop()
+    } else {
+        None
+    }
+}
+
+/*
+    Search the position to the given depth, recording the first 'tree_depth' plies of the
+    search (up to 'tree_nodes' nodes) and returning them as a graphviz DOT graph
+*/
+pub fn search_tree_dot(
+    board: &BoardState,
+    depth: u8,
+    tree_depth: usize,
+    tree_nodes: usize,
+    options: &SearchOptions,
+) -> String {
+    let mut search = SearchState::new(*options);
+    search.tree = Some(SearchTree::new(tree_depth, tree_nodes));
+    alpha_beta_search(board, depth, i32::MIN, i32::MAX, board.to_move, &mut search);
+    search.tree.unwrap().to_dot()
+}
+
+/*
+    Play a game in the terminal where the engine plays against itself
+*/
+pub fn play_game_against_self(
+    b: &BoardState,
+    depth: u8,
+    max_moves: u16,
+    simple_print: bool,
+    options: &SearchOptions,
+    mut jsonl_log: Option<&mut dyn Write>,
+) {
+    let mut board = b.clone();
+
+    let show_board = |simple_print: bool, b: &BoardState| {
+        if simple_print {
+            b.simple_print_board()
+        } else {
+            b.pretty_print_board()
+        }
+    };
+
+    show_board(simple_print, &board);
+    let mut ply = 1;
+    let table = options.new_table();
+    let mut history = vec![];
+    let mut last_capture = None;
+    while board.full_move_clock < max_moves && board.status() == GameStatus::Ongoing {
+        let mut search = SearchState::with_table(*options, table.clone());
+        search.set_game_history(&history);
+        search.last_capture = last_capture;
+        let start = Instant::now();
+        let (res, depth_reached) = iterative_deepening_search(&board, depth, &mut search);
+        let next_board = match res.0 {
+            Some(b) => b,
+            None => break,
+        };
+
+        if let Some(log) = jsonl_log.as_mut() {
+            let record = move_record_json(
+                ply,
+                &board,
+                &next_board,
+                res.1,
+                depth_reached,
+                search.nodes,
+                start.elapsed(),
+                options.move_time.map(|t| t.saturating_sub(start.elapsed())),
+            );
+            writeln!(log, "{}", record).expect("write failed");
+        }
+
+        history.push(board.position_key());
+        last_capture = capture_square(&board, &next_board);
+        board = next_board;
+        ply += 1;
+        show_board(simple_print, &board);
+    }
+
+    let status = board.status();
+    if status != GameStatus::Ongoing {
+        println!("Game over: {}", status);
+    }
+}
+
+/*
+    A single line JSON record describing a move played by the engine, for machine readable game logs
+
+    The fen is the position before the move, the move is given in both coordinate notation and SAN,
+    and eval is from white's point of view. The time left is what the side which moved had on its
+    clock once the move was found, null when it plays without a clock
+*/
+#[allow(clippy::too_many_arguments)]
+pub fn move_record_json(
+    ply: u32,
+    board: &BoardState,
+    next_board: &BoardState,
+    evaluation: i32,
+    depth: u8,
+    nodes: u64,
+    elapsed: Duration,
+    time_left: Option<Duration>,
+) -> String {
+    let mov = next_board.last_move.as_deref().unwrap_or("");
+    let san = Move::from_uci(board, mov)
+        .map(|m| move_to_san(board, m))
+        .unwrap_or_default();
+    let time_left = match time_left {
+        Some(time_left) => time_left.as_millis().to_string(),
+        None => "null".to_string(),
+    };
+    format!(
+        "{{\"ply\":{},\"fen\":{},\"move\":{},\"san\":{},\"eval\":{},\"depth\":{},\"nodes\":{},\"time_ms\":{},\"time_left_ms\":{}}}",
+        ply,
+        json_string(&board.to_fen()),
+        json_string(mov),
+        json_string(&san),
+        evaluation,
+        depth,
+        nodes,
+        elapsed.as_millis(),
+        time_left
+    )
+}
+
+/*
+    A string as a quoted JSON string, escaping the quotes, backslashes and control characters
+    which can not appear in one as they are
+*/
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn right_values() {
+        assert_eq!(PIECE_VALUES[PAWN as usize], 100);
+        assert_eq!(PIECE_VALUES[KNIGHT as usize], 320);
+        assert_eq!(PIECE_VALUES[BISHOP as usize], 330);
         assert_eq!(PIECE_VALUES[ROOK as usize], 500);
         assert_eq!(PIECE_VALUES[QUEEN as usize], 900);
         assert_eq!(PIECE_VALUES[KING as usize], 20000);
     }
+
+    #[test]
+    fn only_legal_move_is_forced() {
+        // the king in the corner has to take the rook next to it
+        let b = board_from_fen("k7/8/8/8/8/8/1r6/K7 w - - 0 1").unwrap();
+        assert_eq!(forced_move(&b).unwrap().last_move.unwrap(), "a1b2");
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        assert!(forced_move(&b).is_none());
+    }
+
+    #[test]
+    fn captures_ordered_by_victim_then_attacker() {
+        let b = board_from_fen("3rk3/4P3/8/1q1r4/2P1b3/2N2Q2/8/4K3 w - - 0 1").unwrap();
+        let order = |uci: &str| mvv_lva(&b, &Move::from_uci(&b, uci).unwrap());
+        // taking the rook with a promotion beats taking the queen
+        assert!(order("e7d8q") > order("c4b5"));
+        assert!(order("c4b5") > order("c3b5"));
+        assert!(order("c3b5") > order("c4d5"));
+        assert!(order("c4d5") > order("c3d5"));
+        assert!(order("c3d5") > order("c3e4"));
+        assert!(order("c3e4") > order("f3e4"));
+        // every capture goes before a quiet move
+        assert!(order("f3e4") > order("c4c5"));
+        assert!(order("c4c5") > order("e1e2"));
+    }
+
+    #[test]
+    fn reverse_futility_pruning() {
+        // a queen up, so far beyond beta that searching on will not bring white back down to it
+        let b = board_from_fen("4k3/8/8/8/8/8/8/Q3K3 w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let res = alpha_beta_search(&b, 2, 0, 1, b.to_move, &mut search);
+        assert_eq!(res.1, get_evaluation(&b));
+        assert_eq!(search.nodes, 1);
+
+        // the same for black, not on the principal variation, nor too deep, nor in check
+        let pruned = |depth: u8, alpha: i32, beta: i32, fen: &str| {
+            let b = board_from_fen(fen).unwrap();
+            let mut search = SearchState::new(SearchOptions::default());
+            alpha_beta_search(&b, depth, alpha, beta, b.to_move, &mut search);
+            search.nodes == 1
+        };
+        assert!(pruned(3, -1, 0, "4k2q/8/8/8/8/8/8/4K3 b - - 0 1"));
+        assert!(!pruned(3, -100, 0, "4k2q/8/8/8/8/8/8/4K3 b - - 0 1"));
+        assert!(!pruned(4, -1, 0, "4k2q/8/8/8/8/8/8/4K3 b - - 0 1"));
+        assert!(!pruned(3, -1, 0, "4k2q/8/8/8/8/8/8/4R1K1 b - - 0 1"));
+    }
+
+    #[test]
+    fn razoring() {
+        let searched = |fen: &str, alpha: i32, beta: i32| {
+            let b = board_from_fen(fen).unwrap();
+            let mut search = SearchState::new(SearchOptions::default());
+            let res = alpha_beta_search(&b, 2, alpha, beta, b.to_move, &mut search);
+            (res.1, search.nodes)
+        };
+        // a queen down, with nothing to take, is given up on after quiescence search agrees
+        let fen = "4k2q/8/8/8/8/8/8/4K3 w - - 0 1";
+        let b = board_from_fen(fen).unwrap();
+        // the node and the quiescence search of it
+        assert_eq!(searched(fen, 0, 1), (get_evaluation(&b), 2));
+        assert_eq!(searched("4k3/8/8/8/8/8/8/Q3K3 b - - 0 1", -1, 0).1, 2);
+        // the queen can be taken, so quiescence search does not agree and the node is searched
+        assert!(searched("4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1", 0, 1).1 > 3);
+    }
+
+    #[test]
+    fn probcut() {
+        let outcome = |fen: &str, depth: u8, alpha: i32, beta: i32| {
+            let b = board_from_fen(fen).unwrap();
+            let mut search = SearchState::new(SearchOptions::default());
+            search.tree = Some(SearchTree::new(0, 1));
+            alpha_beta_search(&b, depth, alpha, beta, b.to_move, &mut search);
+            search.tree.unwrap().nodes[0].outcome
+        };
+        // taking the queen beats beta by far more than the margin even in a shallow search
+        let fen = "4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1";
+        assert_eq!(outcome(fen, 5, 0, 1), NodeOutcome::Pruned);
+        let fen = "4k3/3r4/8/8/3Q4/8/8/4K3 b - - 0 1";
+        assert_eq!(outcome(fen, 5, -1, 0), NodeOutcome::Pruned);
+        // not on the principal variation or at shallower depths
+        assert_ne!(outcome(fen, 5, -1000, 0), NodeOutcome::Pruned);
+        assert_ne!(outcome(fen, 4, -1, 0), NodeOutcome::Pruned);
+    }
+
+    #[test]
+    fn internal_iterative_deepening() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        // the move tried first at the root
+        let first_move = |depth: u8, alpha: i32, beta: i32| {
+            let mut search = SearchState::new(SearchOptions::default());
+            search.tree = Some(SearchTree::new(1, 100));
+            let reports = Arc::new(Mutex::new(vec![]));
+            let reported = reports.clone();
+            search.on_root_move = Some(Box::new(move |report| {
+                let report = (report.depth, report.mov, report.number);
+                reported.lock().unwrap().push(report);
+            }));
+            alpha_beta_search(&b, depth, alpha, beta, b.to_move, &mut search);
+            // searching the root again adds no position to the tree
+            let tree = search.tree.unwrap();
+            assert!(tree.nodes[1..].iter().all(|n| n.last_move.is_some()));
+            // and reports none of its moves, only the search of the root itself does
+            let reports = reports.lock().unwrap().clone();
+            for (index, (report_depth, _, number)) in reports.iter().enumerate() {
+                assert_eq!(*report_depth, depth);
+                assert_eq!(*number, index + 1);
+            }
+            reports[0].1
+        };
+        // the root is searched again less deep before any of its moves, which finds the move to
+        // try first
+        let mut search = SearchState::new(SearchOptions::default());
+        let shallow = alpha_beta_search(&b, 2, i32::MIN, i32::MAX, b.to_move, &mut search);
+        let best = shallow.0.unwrap().last_move.unwrap();
+        let unordered = first_move(1, i32::MIN, i32::MAX);
+        assert_ne!(unordered.to_uci(), best);
+        assert_eq!(first_move(4, i32::MIN, i32::MAX).to_uci(), best);
+        // but not when it is shallow, or off the principal variation
+        assert_eq!(first_move(3, i32::MIN, i32::MAX), unordered);
+        assert_eq!(first_move(4, 0, 1), unordered);
+    }
+
+    #[test]
+    fn excluded_moves_skipped() {
+        // with the only move skipped the search fails low, rather than finding a checkmate
+        let b = board_from_fen("k7/8/8/8/8/8/1r6/K7 w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        search.excluded_move = Some(Move::from_uci(&b, "a1b2").unwrap());
+        let res = alpha_beta_search(&b, 2, -50, -49, b.to_move, &mut search);
+        assert_eq!(res.1, -50);
+        assert_eq!(search.excluded_move, None);
+        assert_eq!(search.table.probe(b.position_key()), None);
+
+        // without taking the queen white is a rook down rather than a queen up
+        let b = board_from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        let take = Move::from_uci(&b, "d2d5").unwrap();
+        let full = alpha_beta_search(&b, 2, i32::MIN, i32::MAX, b.to_move, &mut search);
+        search.excluded_move = Some(take);
+        let res = alpha_beta_search(&b, 2, i32::MIN, i32::MAX, b.to_move, &mut search);
+        assert_eq!(full.0.unwrap().last_move.unwrap(), "d2d5");
+        assert_ne!(res.0.unwrap().last_move.unwrap(), "d2d5");
+        assert!(res.1 < full.1 - 500);
+    }
+
+    #[test]
+    fn singular_verification_adds_no_position() {
+        let b =
+            board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        search.tree = Some(SearchTree::new(1, 10000));
+        iterative_deepening_search(&b, 6, &mut search);
+        // every node below a root is reached by a move, the root searched again with its table
+        // move skipped is not a position of its own
+        let tree = search.tree.unwrap();
+        assert!(tree
+            .nodes
+            .iter()
+            .all(|n| n.parent.is_none() || n.last_move.is_some()));
+        assert_eq!(search.excluded_move, None);
+    }
+
+    #[test]
+    fn losing_captures_found() {
+        let b = board_from_fen("4k3/8/4p3/3p4/2P5/8/3Q4/4K3 w - - 0 1").unwrap();
+        let loses = |uci: &str| loses_material(&b, &Move::from_uci(&b, uci).unwrap());
+        assert!(loses("d2d5"));
+        assert!(!loses("c4d5"));
+        assert!(!loses("d2d3"));
+    }
+
+    #[test]
+    fn quiescence_sees_recapture() {
+        // Qxd5 wins a pawn at depth 1 but loses the queen to exd5
+        let b = board_from_fen("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let res = alpha_beta_search(&b, 1, i32::MIN, i32::MAX, b.to_move, &mut search);
+        assert_ne!(res.0.unwrap().last_move.unwrap(), "d1d5");
+    }
+
+    #[test]
+    fn quiescence_quiet_position_is_static_eval() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let eval = quiescence_search(&b, i32::MIN, i32::MAX, b.to_move, 0, &mut search);
+        assert_eq!(eval, get_evaluation(&b));
+    }
+
+    #[test]
+    fn quiescence_finds_quiet_mate() {
+        // Ra8# is not a capture, it is only found because quiet checks are searched
+        let b = board_from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let eval = quiescence_search(&b, i32::MIN, i32::MAX, b.to_move, 0, &mut search);
+        assert_eq!(eval, CHECKMATE_SCORE - 1);
+        assert_eq!(moves_to_mate(eval), Some(1));
+    }
+
+    #[test]
+    fn mate_scores_count_plies_from_root() {
+        // Kb6 and Rh8 mates in 2, found whatever depth beyond it the search goes to
+        let b = board_from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        for depth in 4..=6 {
+            let mut search = SearchState::new(SearchOptions::default());
+            let ((_, score), _) = iterative_deepening_search(&b, depth, &mut search);
+            assert_eq!(score, CHECKMATE_SCORE - 3);
+            assert_eq!(moves_to_mate(score), Some(2));
+        }
+        // black being mated next move, and black mating
+        let b = board_from_fen("k7/2K5/8/8/8/8/8/1R6 b - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let ((_, score), _) = iterative_deepening_search(&b, 4, &mut search);
+        assert_eq!(moves_to_mate(score), Some(1));
+        let b = board_from_fen("r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let ((_, score), _) = iterative_deepening_search(&b, 3, &mut search);
+        assert_eq!(score, -CHECKMATE_SCORE + 1);
+        assert_eq!(moves_to_mate(score), Some(-1));
+        assert_eq!(moves_to_mate(250), None);
+    }
+
+    #[test]
+    fn underpromotion_found() {
+        // promoting to a knight forks the king and queen, promoting to a queen loses the rook
+        let b = board_from_fen("8/5P1k/6q1/8/8/8/8/KR6 w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let res = alpha_beta_search(&b, 3, i32::MIN, i32::MAX, b.to_move, &mut search);
+        assert_eq!(res.0.unwrap().last_move.unwrap(), "f7f8n");
+
+        // every promotion piece can be played, with or without a capture
+        let b = board_from_fen("3r3k/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        for (uci, kind) in &[
+            ("e7d8r", PieceKind::Rook),
+            ("e7e8b", PieceKind::Bishop),
+            ("e7e8n", PieceKind::Knight),
+        ] {
+            let mov = Move::from_uci(&b, uci).unwrap();
+            let next_board = make_move(&b, &mov);
+            assert_eq!(next_board.piece_at(mov.to).map(|p| p.kind), Some(*kind));
+            assert_eq!(next_board.last_move.as_deref(), Some(*uci));
+        }
+    }
+
+    #[test]
+    fn iterative_deepening_reaches_depth() {
+        let b = board_from_fen("8/5P1k/6q1/8/8/8/8/KR6 w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let (res, depth_reached) = iterative_deepening_search(&b, 3, &mut search);
+        assert_eq!(depth_reached, 3);
+        assert_eq!(res.0.unwrap().last_move.unwrap(), "f7f8n");
+
+        // out of time after the first depth, which is always searched so there is a move
+        let mut search = SearchState::new(SearchOptions {
+            move_time: Some(Duration::from_millis(0)),
+            ..SearchOptions::default()
+        });
+        let (res, depth_reached) = iterative_deepening_search(&b, 5, &mut search);
+        assert_eq!(depth_reached, 1);
+        assert!(res.0.is_some());
+        assert!(!search.stopped);
+    }
+
+    #[test]
+    fn selective_depth_counts_quiescence() {
+        // nothing to capture, so quiescence search stops where the main search does
+        let b = board_from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        iterative_deepening_search(&b, 2, &mut search);
+        assert_eq!(search.seldepth, 2);
+
+        // exchanges go on past the horizon
+        let b =
+            board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        iterative_deepening_search(&b, 2, &mut search);
+        assert!(search.seldepth > 2);
+    }
+
+    #[test]
+    fn mate_limit_finds_mates() {
+        // Kb6 or Kc7 mates in 2, which takes no more than 3 plies to find however deep the search
+        // could go
+        let b = board_from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        let options = SearchOptions {
+            mate_limit: Some(2),
+            ..SearchOptions::default()
+        };
+        let mut search = SearchState::new(options);
+        let ((next_board, score), depth_reached) = iterative_deepening_search(&b, 20, &mut search);
+        assert_eq!(score, CHECKMATE_SCORE - 3);
+        assert!(depth_reached <= 3);
+        assert!(next_board.is_some());
+        // no mate in 1, and the search goes no deeper looking for one
+        let mut search = SearchState::new(SearchOptions {
+            mate_limit: Some(1),
+            ..options
+        });
+        let ((next_board, score), depth_reached) = iterative_deepening_search(&b, 20, &mut search);
+        assert!(score < MATE_THRESHOLD);
+        assert_eq!(depth_reached, 1);
+        assert!(next_board.is_some());
+        assert_eq!(search.lines[0].bound, Bound::Upper);
+        // the same search again, with the table's entry for the root bounding its score, still
+        // finds a move to play
+        let table = search.table.clone();
+        let mut search = SearchState::with_table(search.options, table);
+        let ((next_board, _), _) = iterative_deepening_search(&b, 20, &mut search);
+        assert!(next_board.is_some());
+
+        // mates by black, found before the search reaches the limit
+        let b = board_from_fen("r5k1/5ppp/8/8/8/8/5PPP/6K1 b - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions {
+            mate_limit: Some(3),
+            ..options
+        });
+        let ((_, score), depth_reached) = iterative_deepening_search(&b, 20, &mut search);
+        assert_eq!(score, -CHECKMATE_SCORE + 1);
+        assert_eq!(depth_reached, 1);
+    }
+
+    #[test]
+    fn easy_moves_cut_move_time() {
+        let move_time = Duration::from_secs(4);
+        let options = SearchOptions {
+            move_time: Some(move_time),
+            ..SearchOptions::default()
+        };
+        let search_easy = |b: &BoardState, last_capture: Option<Square>| {
+            let mut search = SearchState::new(options);
+            search.last_capture = last_capture;
+            let start = Instant::now();
+            iterative_deepening_search(b, 40, &mut search);
+            (search.easy_move, start.elapsed())
+        };
+
+        // black has just taken the queen on d4, and taking back with the pawn is far better than
+        // anything else
+        let b = board_from_fen("r3k3/ppp5/8/8/3q4/4P3/PPP5/R3K3 w Qq - 0 1").unwrap();
+        let d4 = Square::from_algebraic("d4");
+        let (easy, time) = search_easy(&b, d4);
+        assert_eq!(easy, Some(EasyMove::Recapture));
+        assert!(time < move_time / 2);
+
+        // taking the undefended queen stays the best move, far better than any other
+        let b = board_from_fen("r3k3/ppp5/8/3q4/8/8/PPP5/R2RK3 w Qq - 0 1").unwrap();
+        let (easy, time) = search_easy(&b, None);
+        assert_eq!(easy, Some(EasyMove::Stable));
+        assert!(time < move_time / 2);
+
+        // neither once the other moves are close
+        let options = SearchOptions {
+            move_time: Some(Duration::from_millis(300)),
+            ..options
+        };
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let mut search = SearchState::new(options);
+        iterative_deepening_search(&b, 40, &mut search);
+        assert_eq!(search.easy_move, None);
+    }
+
+    #[test]
+    fn capture_square_found() {
+        let b = board_from_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        let take = make_move(&b, &Move::from_uci(&b, "e4d5").unwrap());
+        assert_eq!(capture_square(&b, &take), Square::from_algebraic("d5"));
+        let push = make_move(&b, &Move::from_uci(&b, "e4e5").unwrap());
+        assert_eq!(capture_square(&b, &push), None);
+    }
+
+    #[test]
+    fn node_limit_stops_search() {
+        let b =
+            board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let mut search = SearchState::new(SearchOptions {
+            node_limit: Some(5000),
+            ..SearchOptions::default()
+        });
+        let (res, depth_reached) = iterative_deepening_search(&b, 20, &mut search);
+        assert!(res.0.is_some());
+        assert!(depth_reached < 20);
+        assert!(search.nodes <= 5001);
+
+        // the same limit always stops the search in the same place
+        let mut again = SearchState::new(search.options);
+        let (second, _) = iterative_deepening_search(&b, 20, &mut again);
+        assert_eq!(again.nodes, search.nodes);
+        assert_eq!(second.1, res.1);
+    }
+
+    #[test]
+    fn deterministic_search_repeats() {
+        let b =
+            board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let options = SearchOptions {
+            move_time: Some(Duration::from_millis(10)),
+            deterministic: true,
+            ..SearchOptions::default()
+        };
+        let mut search = SearchState::new(options);
+        assert_eq!(search.options.move_time, None);
+        assert_eq!(search.options.node_limit, Some(5000));
+        let (res, depth_reached) = iterative_deepening_search(&b, 20, &mut search);
+        assert!(depth_reached < 20);
+
+        // however long the search took, the same options stop it in the same place
+        let mut again = SearchState::new(options);
+        let (second, _) = iterative_deepening_search(&b, 20, &mut again);
+        assert_eq!(again.nodes, search.nodes);
+        assert_eq!(second.1, res.1);
+        assert_eq!(again.principal_variation(), search.principal_variation());
+
+        // a lower node limit is kept
+        let options = SearchOptions {
+            node_limit: Some(100),
+            ..options
+        };
+        assert_eq!(SearchState::new(options).options.node_limit, Some(100));
+    }
+
+    #[test]
+    fn stop_flag_interrupts_search() {
+        let b =
+            board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        // told to stop before starting, the first depth is still searched
+        let mut search = SearchState::new(SearchOptions::default());
+        search.stop.store(true, Ordering::Relaxed);
+        let (res, depth_reached) = iterative_deepening_search(&b, 5, &mut search);
+        assert_eq!(depth_reached, 1);
+        assert!(res.0.is_some());
+
+        // told to stop by another thread part way through a search far too deep to finish
+        let mut search = SearchState::new(SearchOptions::default());
+        let stop = search.stop.clone();
+        let stopper = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            stop.store(true, Ordering::Relaxed);
+        });
+        let (res, depth_reached) = iterative_deepening_search(&b, 40, &mut search);
+        stopper.join().unwrap();
+        assert!((1..40).contains(&depth_reached));
+        assert!(res.0.is_some());
+    }
+
+    #[test]
+    fn multi_pv_lines_differ() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let mut search = SearchState::new(SearchOptions {
+            multi_pv: Some(4),
+            ..SearchOptions::default()
+        });
+        let (res, _) = iterative_deepening_search(&b, 4, &mut search);
+        assert_eq!(search.lines.len(), 4);
+        assert_eq!(search.lines[0].score, res.1);
+        for (i, line) in search.lines.iter().enumerate() {
+            for other in &search.lines[i + 1..] {
+                assert_ne!(line.pv[0], other.pv[0]);
+                assert!(line.score >= other.score);
+            }
+        }
+
+        // no more lines than there are moves
+        let b = board_from_fen("k7/2K5/8/8/8/8/8/1R6 b - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions {
+            multi_pv: Some(4),
+            ..SearchOptions::default()
+        });
+        iterative_deepening_search(&b, 3, &mut search);
+        assert_eq!(search.lines.len(), 1);
+    }
+
+    #[test]
+    fn search_stats_counted() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        assert_eq!(search.stats.first_move_cutoff_rate(), 0.0);
+        iterative_deepening_search(&b, 4, &mut search);
+        let stats = search.stats;
+        assert!(stats.first_move_cutoffs > 0);
+        assert!(stats.first_move_cutoffs <= stats.cutoffs);
+        assert!(stats.table_hits > 0);
+        assert!(stats.table_hits <= stats.table_probes);
+        assert!(stats.re_searches <= stats.null_window_searches);
+        for rate in &[
+            stats.first_move_cutoff_rate(),
+            stats.table_hit_rate(),
+            stats.re_search_rate(),
+        ] {
+            assert!((0.0..=100.0).contains(rate));
+        }
+    }
+
+    #[test]
+    fn root_moves_reported() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let reports = Arc::new(Mutex::new(vec![]));
+        let reported = reports.clone();
+        search.on_root_move = Some(Box::new(move |report| {
+            let report = (report.depth, report.mov, report.number);
+            reported.lock().unwrap().push(report);
+        }));
+        iterative_deepening_search(&b, 2, &mut search);
+        // every move at the root, at each depth, numbered in the order they were searched
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 40);
+        for (index, (depth, mov, number)) in reports.iter().enumerate() {
+            assert_eq!(*depth as usize, index / 20 + 1);
+            assert_eq!(*number, index % 20 + 1);
+            assert!(is_move_legal(&b, *mov));
+        }
+    }
+
+    #[test]
+    fn principal_variation_is_legal() {
+        let b =
+            board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let (res, _) = iterative_deepening_search(&b, 5, &mut search);
+        let pv = search.principal_variation().to_vec();
+        assert!(!pv.is_empty() && pv.len() <= 5);
+        assert_eq!(res.0.unwrap().last_move.unwrap(), pv[0].to_uci());
+        let mut board = b;
+        for mov in pv {
+            assert!(is_move_legal(&board, mov));
+            board = make_move(&board, &mov);
+        }
+    }
+
+    #[test]
+    fn killers_kept_per_ply() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let mov = |uci: &str| Move::from_uci(&b, uci).unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        search.add_killer(mov("e2e4"));
+        search.add_killer(mov("d2d4"));
+        search.add_killer(mov("d2d4"));
+        assert_eq!(search.killers(), [Some(mov("d2d4")), Some(mov("e2e4"))]);
+
+        search.ply = 2;
+        assert_eq!(search.killers(), [None, None]);
+        search.add_killer(mov("g1f3"));
+        assert_eq!(search.killers(), [Some(mov("g1f3")), None]);
+        search.ply = 0;
+        assert_eq!(search.killers(), [Some(mov("d2d4")), Some(mov("e2e4"))]);
+
+        // the search is back at the root once it finishes
+        alpha_beta_search(&b, 3, i32::MIN, i32::MAX, b.to_move, &mut search);
+        assert_eq!(search.ply, 0);
+        assert!(search.killers.len() > 1);
+    }
+
+    #[test]
+    fn aspiration_windows_widen() {
+        let b = board_from_fen("8/5P1k/6q1/8/8/8/8/KR6 w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let full = alpha_beta_search(&b, 4, i32::MIN, i32::MAX, b.to_move, &mut search);
+        // expecting far too little and far too much, the window has to be widened on each side
+        // until the score lands inside it
+        for expected in &[full.1 - 5000, full.1 + 5000] {
+            let mut search = SearchState::new(SearchOptions::default());
+            search.tree = Some(SearchTree::new(0, 100));
+            let res = aspiration_search(&b, 4, *expected, &mut search);
+            assert!(res.0.is_some());
+            let roots = search.tree.unwrap().nodes;
+            assert!(roots.len() > 1);
+            assert_eq!(roots.last().unwrap().bound, Bound::Exact);
+            assert_eq!(roots.last().unwrap().score, res.1);
+        }
+    }
+
+    #[test]
+    fn table_keeps_black_refutation() {
+        // taking the queen beats black's bound, an upper bound on white's score, and is the move
+        // to try first next time
+        let b = board_from_fen("3rk3/8/8/3Q4/8/8/8/4K3 b - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let res = alpha_beta_search(&b, 2, 0, 1, b.to_move, &mut search);
+        assert!(res.1 <= 0);
+        let entry = search.table.probe(b.position_key()).unwrap();
+        assert_eq!(entry.bound, Bound::Upper);
+        assert_eq!(entry.best_move.unwrap().to_uci(), "d8d5");
+
+        // failing low for black, no move is better than the rest
+        let b = board_from_fen("4k3/8/8/8/8/8/8/Q3K3 b - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let res = alpha_beta_search(&b, 4, -1, 0, b.to_move, &mut search);
+        assert!(res.1 >= 0);
+        let entry = search.table.probe(b.position_key()).unwrap();
+        assert_eq!(entry.bound, Bound::Lower);
+        assert_eq!(entry.best_move, None);
+    }
+
+    #[test]
+    fn table_remembers_searches() {
+        let b = board_from_fen("8/5P1k/6q1/8/8/8/8/KR6 w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let (first, _) = iterative_deepening_search(&b, 4, &mut search);
+        let entry = search.table.probe(b.position_key()).unwrap();
+        assert_eq!(entry.depth, 4);
+        assert_eq!(entry.bound, Bound::Exact);
+        assert_eq!(entry.best_move.unwrap().to_uci(), "f7f8n");
+
+        // searching the position again with the same table finds the answer straight away
+        let mut again = SearchState::with_table(SearchOptions::default(), search.table.clone());
+        let (second, _) = iterative_deepening_search(&b, 4, &mut again);
+        assert_eq!(second.1, first.1);
+        assert_eq!(second.0.unwrap().last_move, first.0.unwrap().last_move);
+        assert_eq!(again.nodes, 4);
+    }
+
+    #[test]
+    fn stalemate_is_draw() {
+        let b = board_from_fen("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let res = alpha_beta_search(&b, 2, i32::MIN, i32::MAX, b.to_move, &mut search);
+        assert!(res.0.is_none());
+        assert_eq!(res.1, 0);
+    }
+
+    #[test]
+    fn perpetual_check_saves_lost_position() {
+        // white is a queen and rook down, but Qd8+ and Qh4+ check forever as the pawn covers g6
+        let b = board_from_fen("7k/5pp1/2K5/5P2/8/r7/q7/3Q4 w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let ((_, score), _) = iterative_deepening_search(&b, 6, &mut search);
+        assert_eq!(score, 0);
+        assert_eq!(search.principal_variation()[0].to_uci(), "d1d8");
+
+        // once the checks have been played in the game, repeating them is seen straight away
+        let mut history = vec![];
+        let mut board = b;
+        for uci in &["d1d8", "h8h7", "d8h4", "h7g8"] {
+            history.push(board.position_key());
+            board = make_move(&board, &Move::from_uci(&board, uci).unwrap());
+        }
+        let mut search = SearchState::new(SearchOptions::default());
+        let ((_, score), _) = iterative_deepening_search(&board, 2, &mut search);
+        assert!(score < -500);
+        let mut search = SearchState::new(SearchOptions::default());
+        search.set_game_history(&history);
+        let ((next_board, score), _) = iterative_deepening_search(&board, 2, &mut search);
+        assert_eq!(score, 0);
+        assert_eq!(next_board.unwrap().last_move.unwrap(), "h4d8");
+    }
+
+    #[test]
+    fn fifty_move_rule_ends_search() {
+        // a queen up, but every move which does not mate ends the game in a draw
+        let b = board_from_fen("7k/8/8/8/8/8/8/KQ6 w - - 99 80").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let ((_, score), _) = iterative_deepening_search(&b, 3, &mut search);
+        assert_eq!(score, 0);
+        let b = board_from_fen("7k/8/8/8/8/8/8/KQ6 w - - 90 80").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let ((_, score), _) = iterative_deepening_search(&b, 3, &mut search);
+        assert!(score > 0);
+
+        // a checkmate on the move which reaches the limit still wins
+        let b = board_from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 80").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let ((_, score), _) = iterative_deepening_search(&b, 3, &mut search);
+        assert_eq!(score, CHECKMATE_SCORE - 1);
+    }
+
+    #[test]
+    fn contempt_for_draws() {
+        let options = SearchOptions {
+            contempt: 50,
+            ..SearchOptions::default()
+        };
+        // white is lost, so the perpetual check is still its best move
+        let b = board_from_fen("7k/5pp1/2K5/5P2/8/r7/q7/3Q4 w - - 0 1").unwrap();
+        let mut search = SearchState::new(options);
+        let ((_, score), _) = iterative_deepening_search(&b, 6, &mut search);
+        assert_eq!(score, -50);
+        // stalemated at the root with black to move
+        let b = board_from_fen("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1").unwrap();
+        let mut search = SearchState::new(options);
+        let res = alpha_beta_search(&b, 2, i32::MIN, i32::MAX, b.to_move, &mut search);
+        assert_eq!(res.1, 50);
+        let mut search = SearchState::new(SearchOptions {
+            contempt: -50,
+            ..options
+        });
+        let res = alpha_beta_search(&b, 2, i32::MIN, i32::MAX, b.to_move, &mut search);
+        assert_eq!(res.1, -50);
+
+        // Qc7 stalemates black, a draw scored for white at the root which a search for black
+        // would score the other way, so the root's score is not kept in the table
+        let b = board_from_fen("k7/8/1K6/8/8/8/8/2Q5 w - - 0 1").unwrap();
+        let mut search = SearchState::new(options);
+        alpha_beta_search(&b, 2, i32::MIN, i32::MAX, b.to_move, &mut search);
+        assert_eq!(search.table.probe(b.position_key()), None);
+        // but it is without contempt, when a draw scores the same for both sides
+        let mut search = SearchState::new(SearchOptions::default());
+        alpha_beta_search(&b, 2, i32::MIN, i32::MAX, b.to_move, &mut search);
+        assert!(search.table.probe(b.position_key()).is_some());
+    }
+
+    #[test]
+    fn randomized_draw_scores() {
+        let b = board_from_fen("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1").unwrap();
+        let mut scores = vec![];
+        for seed in 0..50 {
+            let options = SearchOptions {
+                draw_randomization_seed: Some(seed),
+                ..SearchOptions::default()
+            };
+            let score = draw_score(&b, &options);
+            assert!(score.abs() <= DRAW_RANDOMIZATION_RANGE);
+            // the same seed and position always give the same score
+            assert_eq!(score, draw_score(&b, &options));
+            scores.push(score);
+        }
+        scores.sort();
+        scores.dedup();
+        assert!(scores.len() > 1);
+        // the scores come from the position's key, not a hasher which can change between builds
+        let score = |seed| {
+            let options = SearchOptions {
+                draw_randomization_seed: Some(seed),
+                ..SearchOptions::default()
+            };
+            draw_score(&b, &options)
+        };
+        assert_eq!((score(1), score(3)), (-1, 1));
+    }
+
+    #[test]
+    fn search_tree_recorded() {
+        let b = board_from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        search.tree = Some(SearchTree::new(1, 1000));
+        let res = alpha_beta_search(&b, 2, i32::MIN, i32::MAX, b.to_move, &mut search);
+        let tree = search.tree.unwrap();
+        assert_eq!(tree.nodes[0].parent, None);
+        assert_eq!(tree.nodes[0].score, res.1);
+        // the root and every white reply (some searched twice), but nothing deeper
+        let mut replies: Vec<_> = tree.nodes[1..]
+            .iter()
+            .map(|n| n.last_move.clone())
+            .collect();
+        replies.dedup();
+        assert_eq!(replies.len(), generate_child_boards(&b).len());
+        assert!(tree.nodes[1..].iter().all(|n| n.parent == Some(0)));
+        assert!(tree.nodes.iter().any(
+            |n| n.last_move == Some("a1a8".to_string()) && n.outcome == NodeOutcome::Checkmate
+        ));
+    }
+
+    #[test]
+    fn jsonl_move_record() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let next_board = generate_child_boards(&b)
+            .into_iter()
+            .find(|m| m.last_move == Some("e2e4".to_string()))
+            .unwrap();
+        assert_eq!(
+            move_record_json(
+                1,
+                &b,
+                &next_board,
+                35,
+                4,
+                1234,
+                Duration::from_millis(56),
+                Some(Duration::from_millis(59944))
+            ),
+            "{\"ply\":1,\"fen\":\"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1\",\"move\":\"e2e4\",\"san\":\"e4\",\"eval\":35,\"depth\":4,\"nodes\":1234,\"time_ms\":56,\"time_left_ms\":59944}"
+        );
+        assert!(
+            move_record_json(1, &b, &next_board, 35, 4, 1234, Duration::ZERO, None)
+                .ends_with(",\"time_left_ms\":null}")
+        );
+    }
+
+    #[test]
+    fn json_strings_escaped() {
+        assert_eq!(json_string("e2e4"), "\"e2e4\"");
+        assert_eq!(
+            json_string("a \"quote\" and a \\ backslash"),
+            "\"a \\\"quote\\\" and a \\\\ backslash\""
+        );
+        assert_eq!(json_string("line\nbreak\u{1}"), "\"line\\nbreak\\u0001\"");
+    }
+
+    #[test]
+    fn nodes_counted() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        search.tree = Some(SearchTree::new(1, 1000));
+        alpha_beta_search(&b, 1, i32::MIN, i32::MAX, b.to_move, &mut search);
+        // the root and its 20 children, counting the children searched again with a full window,
+        // and the quiescence search of each child, which has nothing to capture
+        let tree = search.tree.unwrap();
+        assert!(tree.nodes.len() > 21);
+        assert_eq!(search.nodes, 2 * tree.nodes.len() as u64 - 1);
+    }
+
+    #[test]
+    fn no_progress_scales_evaluation() {
+        let fresh = board_from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 0 60").unwrap();
+        let stale = board_from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 60 60").unwrap();
+        let dead = board_from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 100 60").unwrap();
+        let advantage = get_evaluation(&fresh) - NO_PROGRESS_ADVANTAGE;
+        assert_eq!(
+            get_evaluation(&stale),
+            NO_PROGRESS_ADVANTAGE + advantage / 2
+        );
+        assert_eq!(get_evaluation(&dead), NO_PROGRESS_ADVANTAGE);
+        let stale = board_from_fen("3qk3/8/8/8/8/8/8/4K3 b - - 60 60").unwrap();
+        assert_eq!(
+            get_evaluation(&stale),
+            -NO_PROGRESS_ADVANTAGE - advantage / 2
+        );
+
+        // a middlegame position with neither side winning is not scaled, however long it has been
+        // maneuvered in
+        let fen = "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R1BQKB1R w KQ - {} 7";
+        let fresh = board_from_fen(&fen.replace("{}", "0")).unwrap();
+        let stale = board_from_fen(&fen.replace("{}", "46")).unwrap();
+        assert!(get_evaluation(&fresh) != 0);
+        assert_eq!(get_evaluation(&stale), get_evaluation(&fresh));
+    }
+
+    #[test]
+    fn mobility_counts_in_evaluation() {
+        let start = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        assert_eq!(mobility_value(&start), 0);
+
+        // 1.e4 frees white's bishop and queen, and black's pieces can go where they could before
+        let b = make_move(&start, &Move::from_uci(&start, "e2e4").unwrap());
+        let white = total_mobility(&b, PieceColor::White) as i32;
+        assert_eq!(total_mobility(&b, PieceColor::Black), 20);
+        assert_eq!(mobility_value(&b), (white - 20) * MOBILITY_WEIGHT);
+        assert!(mobility_value(&b) > 0);
+        let static_terms = b.white_total_piece_value - b.black_total_piece_value
+            + b.piece_square_score
+            + king_square_value(&b, PieceColor::White)
+            - king_square_value(&b, PieceColor::Black)
+            + king_safety_value(&b, PieceColor::White)
+            - king_safety_value(&b, PieceColor::Black);
+        assert_eq!(get_evaluation(&b), static_terms + mobility_value(&b));
+    }
+
+    #[test]
+    fn king_safety_counts_attacks_on_the_king() {
+        // the bishop on d3 and the queen on h5 both bear down on black's castled king
+        let attacked =
+            board_from_fen("r1bq1rk1/ppp2ppp/2n2n2/3pp2Q/8/3BP3/PPPP1PPP/RNB1K1NR w KQ - 0 1")
+                .unwrap();
+        assert!(king_safety_value(&attacked, PieceColor::Black) < 0);
+        assert!(
+            king_safety_value(&attacked, PieceColor::Black)
+                < king_safety_value(&attacked, PieceColor::White)
+        );
+        // nothing is attacking either king at the start
+        let start = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        assert_eq!(king_safety_value(&start, PieceColor::White), 0);
+        // and with no pieces but pawns left the king is free to come forward
+        let b = board_from_fen("8/8/4k3/3P4/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(king_safety_value(&b, PieceColor::Black), 0);
+    }
+
+    #[test]
+    fn late_endgame_detected() {
+        let b = board_from_fen("4k3/8/4p3/3p4/8/8/8/3RK3 w - - 0 1").unwrap();
+        assert!(is_late_endgame(&b));
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        assert!(!is_late_endgame(&b));
+    }
 }
@@ -0,0 +1,179 @@
+pub use crate::board::*;
+use std::collections::HashMap;
+
+/*
+    A position from an epd record (www.chessprogramming.org/Extended_Position_Description) with the
+    operations which followed it, ie the best moves of a test suite
+*/
+pub struct EpdRecord {
+    pub board: BoardState,
+    // the operands of each operation by opcode, ie 'bm e2e4 d2d4;' is "bm" -> ["e2e4", "d2d4"],
+    // with the quotes taken off string operands
+    pub operations: HashMap<String, Vec<String>>,
+}
+
+impl EpdRecord {
+    /*
+        The operands of an operation, None if the record does not contain it
+    */
+    pub fn operation(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .get(opcode)
+            .map(|operands| operands.as_slice())
+    }
+}
+
+/*
+    Parse an epd record, the first four fields of a fen string followed by operations each ending
+    in a semicolon
+
+    The move clocks come from the 'hmvc' and 'fmvn' operations when they are given, and are 0 and 1
+    otherwise
+*/
+pub fn parse_epd(line: &str) -> Result<EpdRecord, &'static str> {
+    let mut rest = line.trim();
+    let mut fields = vec![];
+    for _ in 0..4 {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 {
+            return Err("Could not parse epd record: Missing position fields");
+        }
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+
+    let operations = parse_operations(rest)?;
+    let move_clock = |opcode: &str, default: u16| match operations.get(opcode) {
+        None => Ok(default),
+        Some(operands) => operands
+            .first()
+            .and_then(|clock| clock.parse::<u16>().ok())
+            .ok_or("Could not parse epd record: Invalid move clock"),
+    };
+    let fen = format!(
+        "{} {} {}",
+        fields.join(" "),
+        move_clock("hmvc", 0)?,
+        move_clock("fmvn", 1)?
+    );
+
+    Ok(EpdRecord {
+        board: board_from_fen(&fen)?,
+        operations,
+    })
+}
+
+/*
+    Parse a position given either as a fen string, which has no operations, or as an epd record
+*/
+pub fn parse_fen_or_epd(line: &str) -> Result<EpdRecord, &'static str> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() == 6 && fields[4..].iter().all(|f| f.parse::<u16>().is_ok()) {
+        return Ok(EpdRecord {
+            board: board_from_fen(line.trim())?,
+            operations: HashMap::new(),
+        });
+    }
+    parse_epd(line)
+}
+
+/*
+    Split the operations of an epd record into opcodes and operands, where a string operand in
+    double quotes can contain spaces and semicolons
+*/
+fn parse_operations(text: &str) -> Result<HashMap<String, Vec<String>>, &'static str> {
+    let mut operations = HashMap::new();
+    let mut tokens: Vec<String> = vec![];
+    let mut token: Option<String> = None;
+    let mut in_string = false;
+    for c in text.chars() {
+        if in_string {
+            if c == '"' {
+                in_string = false;
+            } else {
+                token.get_or_insert_with(String::new).push(c);
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                token.get_or_insert_with(String::new);
+            }
+            ';' => {
+                tokens.extend(token.take());
+                add_operation(&mut operations, &mut tokens);
+            }
+            c if c.is_whitespace() => tokens.extend(token.take()),
+            c => token.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if in_string {
+        return Err("Could not parse epd record: Unterminated string operand");
+    }
+
+    // be lenient about a missing semicolon after the last operation
+    tokens.extend(token.take());
+    add_operation(&mut operations, &mut tokens);
+    Ok(operations)
+}
+
+fn add_operation(operations: &mut HashMap<String, Vec<String>>, tokens: &mut Vec<String>) {
+    if tokens.is_empty() {
+        return;
+    }
+    let opcode = tokens.remove(0);
+    operations.insert(opcode, std::mem::take(tokens));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_operations_of_record() {
+        let record = parse_epd(
+            "r1bqk1r1/1p1p1n2/p1n2pN1/2p1b2Q/2P1Pp2/1PN5/PB4PP/R4RK1 w q - bm Rxf4; id \"ERET 001 - Relief\"; ce -12;",
+        )
+        .unwrap();
+        assert_eq!(record.operation("bm"), Some(&["Rxf4".to_string()][..]));
+        assert_eq!(
+            record.operation("id"),
+            Some(&["ERET 001 - Relief".to_string()][..])
+        );
+        assert_eq!(record.operation("ce"), Some(&["-12".to_string()][..]));
+        assert_eq!(record.operation("am"), None);
+        assert_eq!(record.board.to_move, PieceColor::White);
+        assert_eq!(record.board.half_move_clock, 0);
+        assert_eq!(record.board.full_move_clock, 1);
+
+        // strings can hold semicolons, and the move clocks can be given
+        let record =
+            parse_epd("4k3/8/8/8/8/8/8/4K3 b - - id \"a;b\"; hmvc 12; fmvn 40; c0 \"x\" \"y\"")
+                .unwrap();
+        assert_eq!(record.operation("id"), Some(&["a;b".to_string()][..]));
+        assert_eq!(
+            record.operation("c0"),
+            Some(&["x".to_string(), "y".to_string()][..])
+        );
+        assert_eq!(record.board.half_move_clock, 12);
+        assert_eq!(record.board.full_move_clock, 40);
+    }
+
+    #[test]
+    fn parse_fen_and_epd() {
+        let record = parse_fen_or_epd(DEFAULT_FEN_STRING).unwrap();
+        assert_eq!(record.board.full_move_clock, 1);
+        assert!(record.operations.is_empty());
+
+        let record = parse_fen_or_epd(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - bm e5; id \"test\";",
+        )
+        .unwrap();
+        assert_eq!(record.board.to_move, PieceColor::Black);
+
+        assert!(parse_fen_or_epd("not a position").is_err());
+        assert!(parse_epd("4k3/8/8/8/8/8/8/4K3 w - -  id \"open").is_err());
+        assert!(parse_epd("4k3/8/8/8/8/8/8/4K3 w - - hmvc x;").is_err());
+    }
+}
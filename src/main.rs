@@ -1,10 +1,26 @@
 extern crate clap;
 use clap::{App, Arg};
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+mod analysis;
+mod attacks;
 mod board;
+mod builder;
+mod chess960;
+mod editor;
 mod engine;
+mod epd;
 mod move_generation;
+mod move_picker;
+mod packed;
+mod san;
+mod search_tree;
+mod see;
+mod transposition;
 mod uci;
 mod utils;
+mod zobrist;
 
 // During testing I found a depth of 6 to perform best on the optimized build, recommend depth 4 on debug build
 const DEFAULT_DEPTH: &str = "6";
@@ -36,6 +52,111 @@ fn main() {
                 .long("play-self")
                 .help("Play a game against itself in the terminal"),
         )
+        .arg(
+            Arg::with_name("chess960")
+                .long("chess960")
+                .value_name("INDEX")
+                .help("Start from Chess960 position INDEX (0-959), or a random one if INDEX is 'random'. Prints the fen unless playing against itself")
+                .takes_value(true)
+                .conflicts_with("fen"),
+        )
+        .arg(
+            Arg::with_name("edit")
+                .short("E")
+                .long("edit")
+                .help("Set up a position interactively, starting from the loaded board, and print its fen"),
+        )
+        .arg(
+            Arg::with_name("analyze")
+                .short("A")
+                .long("analyze")
+                .value_name("FILE")
+                .help("Analyse every position in FILE (one fen or epd per line) and print the best move and score for each, grading epd bm/am operations as a test suite")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .short("t")
+                .long("threads")
+                .value_name("THREADS")
+                .help("Set the number of threads used when analysing many positions or counting perft, defaults to the number of cores")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("move time")
+                .long("move-time")
+                .value_name("MILLISECONDS")
+                .help("Stop searching deeper once a move has taken MILLISECONDS, playing the best move of the deepest search which finished")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("hash")
+                .long("hash")
+                .value_name("MEGABYTES")
+                .help("Set the size of the transposition table the search remembers positions in, defaults to 16")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("contempt")
+                .long("contempt")
+                .value_name("CENTIPAWNS")
+                .help("Score draws CENTIPAWNS below an equal position for the side the engine is moving for, or above it when negative")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("deterministic")
+                .long("deterministic")
+                .help("Never look at the clock while searching, so the same position always gives the same move, searching a move time as a node limit"),
+        )
+        .arg(
+            Arg::with_name("randomize draws")
+                .long("randomize-draws")
+                .value_name("SEED")
+                .help("Score draws as a small value within 2 centipawns of zero derived from SEED, rather than exactly zero")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dot")
+                .long("dot")
+                .value_name("FILE")
+                .help("Search the loaded position and write the search tree to FILE as a graphviz DOT graph")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dot depth")
+                .long("dot-depth")
+                .value_name("PLIES")
+                .help("Number of plies of the search tree written by --dot, defaults to 3")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dot nodes")
+                .long("dot-nodes")
+                .value_name("NODES")
+                .help("Maximum number of nodes written by --dot, defaults to 10000")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("perft")
+                .long("perft")
+                .value_name("DEPTH")
+                .help("Count the positions DEPTH moves from the loaded board, printing the count below each move in the same format as stockfish's go perft")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("perft hash")
+                .long("perft-hash")
+                .value_name("MEGABYTES")
+                .help("Remember perft counts in a hash table of MEGABYTES, so positions reached by different orders of moves are only counted once")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("jsonl")
+                .long("jsonl")
+                .value_name("FILE")
+                .help("When playing against itself or over UCI, write a JSON record for each move the engine plays to FILE, one per line")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("simple print")
                 .short("S")
@@ -53,8 +174,90 @@ fn main() {
         }
     };
 
-    let fen = matches.value_of("fen").unwrap_or(board::DEFAULT_FEN_STRING);
-    let board = match board::board_from_fen(fen) {
+    let mut options = engine::SearchOptions::default();
+    if let Some(seed) = matches.value_of("randomize draws") {
+        options.draw_randomization_seed = match seed.parse::<u64>() {
+            Ok(s) => Some(s),
+            Err(_) => {
+                println!("Invalid draw randomization seed provided");
+                return;
+            }
+        };
+    }
+    if let Some(move_time) = matches.value_of("move time") {
+        options.move_time = match move_time.parse::<u64>() {
+            Ok(ms) => Some(Duration::from_millis(ms)),
+            Err(_) => {
+                println!("Invalid move time provided");
+                return;
+            }
+        };
+    }
+    if let Some(contempt) = matches.value_of("contempt") {
+        options.contempt = match contempt.parse::<i32>() {
+            Ok(c) => c,
+            Err(_) => {
+                println!("Invalid contempt provided");
+                return;
+            }
+        };
+    }
+    options.deterministic = matches.is_present("deterministic");
+    if let Some(hash) = matches.value_of("hash") {
+        options.hash_megabytes = match hash.parse::<usize>() {
+            Ok(mb) => Some(mb),
+            Err(_) => {
+                println!("Invalid hash size provided");
+                return;
+            }
+        };
+    }
+
+    let default_threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let threads = match matches.value_of("threads").map(|t| t.parse::<usize>()) {
+        None => default_threads,
+        Some(Ok(t)) if t > 0 => t,
+        _ => {
+            println!("Invalid thread count provided");
+            return;
+        }
+    };
+
+    if let Some(path) = matches.value_of("analyze") {
+        analysis::analyze_file(path, depth, threads, &options);
+        return;
+    }
+
+    let mut fen = matches
+        .value_of("fen")
+        .unwrap_or(board::DEFAULT_FEN_STRING)
+        .to_string();
+    if let Some(index) = matches.value_of("chess960") {
+        let chess960 = if index == "random" {
+            Some(chess960::random_chess960_fen().1)
+        } else {
+            index.parse::<u16>().ok().and_then(chess960::chess960_fen)
+        };
+        fen = match chess960 {
+            Some(f) => f,
+            None => {
+                println!("Invalid Chess960 position provided");
+                return;
+            }
+        };
+        if !matches.is_present("play self") {
+            println!("{}", fen);
+            return;
+        }
+    }
+
+    // the editor can start from an invalid position to fix it, the engine needs a valid one
+    let board = if matches.is_present("edit") {
+        board::board_from_fen(&fen).map_err(board::FenError::Parse)
+    } else {
+        fen.parse::<board::BoardState>()
+    };
+    let board = match board {
         Ok(b) => b,
         Err(err) => {
             println!("{}", err);
@@ -62,11 +265,94 @@ fn main() {
         }
     };
 
+    if let Some(perft_depth) = matches.value_of("perft") {
+        let perft_depth = match perft_depth.parse::<u8>() {
+            Ok(d) => d,
+            Err(_) => {
+                println!("Invalid perft depth provided");
+                return;
+            }
+        };
+        let perft_hash = match matches.value_of("perft hash").map(|h| h.parse::<usize>()) {
+            None => 0,
+            Some(Ok(h)) => h,
+            Some(Err(_)) => {
+                println!("Invalid perft hash size provided");
+                return;
+            }
+        };
+        let start = Instant::now();
+        let divide = move_generation::perft_divide_parallel(&board, perft_depth, threads, perft_hash);
+        let elapsed = start.elapsed();
+        for (mov, nodes) in &divide {
+            println!("{}: {}", mov, nodes);
+        }
+        // at depth 0 the only position counted is the board itself, which no move leads to
+        let total: u64 = match perft_depth {
+            0 => 1,
+            _ => divide.iter().map(|(_, nodes)| nodes).sum(),
+        };
+        println!("\nNodes searched: {}", total);
+        // on stderr so the output stays comparable with other engines', for benchmarking move
+        // generation
+        eprintln!(
+            "Time: {}ms ({:.0} nodes per second)",
+            elapsed.as_millis(),
+            total as f64 / elapsed.as_secs_f64().max(0.001)
+        );
+        return;
+    }
+
+    if let Some(path) = matches.value_of("dot") {
+        let tree_depth = matches
+            .value_of("dot depth")
+            .unwrap_or("3")
+            .parse::<usize>();
+        let tree_nodes = matches
+            .value_of("dot nodes")
+            .unwrap_or("10000")
+            .parse::<usize>();
+        let (tree_depth, tree_nodes) = match (tree_depth, tree_nodes) {
+            (Ok(d), Ok(n)) => (d, n),
+            _ => {
+                println!("Invalid search tree limits provided");
+                return;
+            }
+        };
+        let dot = engine::search_tree_dot(&board, depth, tree_depth, tree_nodes, &options);
+        if let Err(err) = fs::write(path, dot) {
+            println!("Could not write {}: {}", path, err);
+        }
+        return;
+    }
+
     let simple_print = matches.is_present("simple print");
+    if matches.is_present("edit") {
+        if let Some(b) = editor::edit_position(&board, simple_print) {
+            println!("{}", b.to_fen());
+        }
+        return;
+    }
+
+    let mut jsonl_log = match matches.value_of("jsonl").map(fs::File::create) {
+        None => None,
+        Some(Ok(file)) => Some(file),
+        Some(Err(err)) => {
+            println!("Could not create jsonl log: {}", err);
+            return;
+        }
+    };
     if matches.is_present("play self") {
-        engine::play_game_against_self(&board, depth, 50, simple_print);
+        engine::play_game_against_self(
+            &board,
+            depth,
+            50,
+            simple_print,
+            &options,
+            jsonl_log.as_mut().map(|f| f as &mut dyn std::io::Write),
+        );
         return;
     }
 
-    uci::play_game_uci(depth);
+    uci::play_game_uci(depth, &options, jsonl_log);
 }
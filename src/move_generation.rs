@@ -1,20 +1,16 @@
+use crate::attacks::{DIAGONAL, KNIGHT_OFFSETS, STRAIGHT};
 pub use crate::board::PieceColor;
 pub use crate::board::*;
+use std::fmt;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
 type Point = (usize, usize);
 
-const KNIGHT_CORDS: [(i8, i8); 8] = [
-    (1, 2),
-    (1, -2),
-    (2, 1),
-    (2, -1),
-    (-1, 2),
-    (-1, -2),
-    (-2, -1),
-    (-2, 1),
-];
-
-#[derive(PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum CastlingType {
     WhiteKingSide,
     WhiteQueenSide,
@@ -22,47 +18,1082 @@ pub enum CastlingType {
     BlackQueenSide,
 }
 
-pub const WHITE_KING_SIDE_CASTLE_ALG: &str = "e1g1";
-pub const WHITE_QUEEN_SIDE_CASTLE_ALG: &str = "e1c1";
-pub const BLACK_KING_SIDE_CASTLE_ALG: &str = "e8g8";
-pub const BLACK_QUEEN_SIDE_CASTLE_ALG: &str = "e8c8";
+// in the order KQkq, the same order as BoardState::castling_rook_files
+const CASTLING_TYPES: [CastlingType; 4] = [
+    CastlingType::WhiteKingSide,
+    CastlingType::WhiteQueenSide,
+    CastlingType::BlackKingSide,
+    CastlingType::BlackQueenSide,
+];
+
+impl CastlingType {
+    fn color(&self) -> PieceColor {
+        match self {
+            CastlingType::WhiteKingSide | CastlingType::WhiteQueenSide => PieceColor::White,
+            CastlingType::BlackKingSide | CastlingType::BlackQueenSide => PieceColor::Black,
+        }
+    }
+
+    fn is_king_side(&self) -> bool {
+        matches!(
+            self,
+            CastlingType::WhiteKingSide | CastlingType::BlackKingSide
+        )
+    }
+
+    fn index(&self) -> usize {
+        castling_right_index(self.color(), self.is_king_side())
+    }
+}
+
+// Flags of a move, an en passant capture has both MOVE_CAPTURE and MOVE_EN_PASSANT set
+pub const MOVE_CAPTURE: u8 = 1;
+pub const MOVE_EN_PASSANT: u8 = 2;
+pub const MOVE_CASTLE: u8 = 4;
+pub const MOVE_DOUBLE_PAWN_PUSH: u8 = 8;
+
+/*
+    A move for the side to move, which can be ordered, filtered or printed before make_move plays it
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Move {
+    pub from: Square,
+    // when castling this is the square of the rook, so a Chess960 castle where the king does not
+    // move still goes between two different squares
+    pub to: Square,
+    pub promotion: Option<PieceKind>,
+    pub flags: u8,
+}
+
+impl Move {
+    pub fn is_capture(&self) -> bool {
+        self.flags & MOVE_CAPTURE != 0
+    }
+
+    pub fn is_castle(&self) -> bool {
+        self.flags & MOVE_CASTLE != 0
+    }
+
+    /*
+        Captures and promotions, which change the material on the board
+    */
+    pub fn is_noisy(&self) -> bool {
+        self.is_capture() || self.promotion.is_some()
+    }
+
+    /*
+        The legal move a move in coordinate notation (ie e2e4 or a7a8q) stands for on the given
+        board
+
+        Castling can be written as the king moving two squares in standard chess (ie e1g1), or as
+        the king taking its own rook (ie e1h1) as GUIs do when playing Chess960
+    */
+    pub fn from_uci(board: &BoardState, uci: &str) -> Result<Move, &'static str> {
+        if !uci.is_ascii() || (uci.len() != 4 && uci.len() != 5) {
+            return Err("Could not parse move: Invalid length");
+        }
+        let from = Square::from_algebraic(&uci[0..2]);
+        let to = Square::from_algebraic(&uci[2..4]);
+        let (from, to) = match (from, to) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return Err("Could not parse move: Invalid square"),
+        };
+        let promotion = match uci[4..].chars().next() {
+            None => None,
+            Some(c) => match Piece::from_fen_char(c) {
+                Some(piece) if piece.color == PieceColor::Black => Some(piece.kind),
+                _ => return Err("Could not parse move: Invalid promotion"),
+            },
+        };
+
+        pseudo_legal_moves_from(board, from.point())
+            .into_iter()
+            .find(|m| {
+                m.promotion == promotion
+                    && (m.to_uci()[2..4] == uci[2..4] || (m.is_castle() && m.to == to))
+                    && is_move_legal(board, *m)
+            })
+            .ok_or("Could not parse move: Illegal move")
+    }
+
+    /*
+        The move in the coordinate notation used by UCI (ie e2e4 or a7a8q)
+
+        Castling is written as the king moving two squares (ie e1g1) in standard chess, and as the
+        king taking its own rook (ie b1a1) otherwise, so it can not be mistaken for a normal king move
+    */
+    pub fn to_uci(self) -> String {
+        let standard_castle = self.from.file() == 4 && (self.to.file() == 0 || self.to.file() == 7);
+        let to = if self.is_castle() && standard_castle {
+            let (king_target, _) = castling_targets(self.to.file() == 7);
+            Square::new(self.from.row(), king_target).unwrap()
+        } else {
+            self.to
+        };
+        match self.promotion {
+            Some(kind) => format!(
+                "{}{}{}",
+                self.from,
+                to,
+                Piece::new(PieceColor::Black, kind).fen_char()
+            ),
+            None => format!("{}{}", self.from, to),
+        }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_uci())
+    }
+}
+
+/*
+    Generate all possible moves *legal* from the given board
+*/
+pub fn generate_moves(board: &BoardState) -> Vec<Move> {
+    let legality = LegalityInfo::new(board);
+    let pseudo_legal_moves = if legality.in_check() {
+        generate_evasions(board, &legality)
+    } else {
+        generate_pseudo_legal_moves(board)
+    };
+    let moves: Vec<Move> = pseudo_legal_moves
+        .into_iter()
+        .filter(|mov| is_legal(board, mov, &legality))
+        .collect();
+
+    #[cfg(feature = "paranoid")]
+    for mov in generate_pseudo_legal_moves(board) {
+        let leaves_check = is_check(&make_move(board, &mov), board.to_move);
+        assert_eq!(
+            moves.contains(&mov),
+            !leaves_check,
+            "is_legal wrong about {} in {}",
+            mov,
+            board.to_fen()
+        );
+        // gives_check needs the other king on the board, which some test positions leave off
+        if !leaves_check && validate_position(board).is_empty() {
+            assert_eq!(
+                gives_check(board, mov),
+                in_check(&make_move(board, &mov)),
+                "gives_check wrong about {} in {}",
+                mov,
+                board.to_fen()
+            );
+        }
+    }
+
+    moves
+}
+
+/*
+    Generate the *legal* captures (including en passant) and promotions from the given board, for
+    quiescence search which has no use for quiet moves
+*/
+pub fn generate_captures(board: &BoardState) -> Vec<Move> {
+    let legality = LegalityInfo::new(board);
+    generate_pseudo_legal_moves_of_kind(board, MoveKinds::Noisy)
+        .into_iter()
+        .filter(|mov| is_legal(board, mov, &legality))
+        .collect()
+}
+
+/*
+    Generate the *legal* quiet moves (neither captures nor promotions) which put the other king in
+    check, so quiescence search can look at checks without generating every move
+*/
+pub fn generate_quiet_checks(board: &BoardState) -> Vec<Move> {
+    let legality = LegalityInfo::new(board);
+    generate_pseudo_legal_moves_of_kind(board, MoveKinds::Quiet)
+        .into_iter()
+        .filter(|mov| gives_check(board, *mov) && is_legal(board, mov, &legality))
+        .collect()
+}
+
+/*
+    Generate every move of the side to move without checking whether it leaves its own king in
+    check, is_legal decides that
+*/
+pub fn generate_pseudo_legal_moves(board: &BoardState) -> Vec<Move> {
+    generate_pseudo_legal_moves_of_kind(board, MoveKinds::All)
+}
+
+/*
+    Which moves of a position to generate, so a search can try the captures and promotions before
+    paying for generating the rest
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MoveKinds {
+    All,
+    // captures, including en passant, and promotions
+    Noisy,
+    // every other move, including castling
+    Quiet,
+}
+
+/*
+    Generate the pseudo-legal moves of one kind, in the same order as generate_pseudo_legal_moves
+*/
+pub fn generate_pseudo_legal_moves_of_kind(board: &BoardState, kinds: MoveKinds) -> Vec<Move> {
+    let mut moves = Vec::new();
+    for piece in PAWN..=KING {
+        for square in board.piece_list(board.to_move, piece).iter() {
+            generate_moves_for_piece(board, square, kinds, &mut moves);
+        }
+    }
+
+    if kinds != MoveKinds::Noisy {
+        generate_castling_moves(board, &mut moves);
+    }
+    moves
+}
+
+/*
+    Determine if a move, ie one remembered from another position, is one that
+    generate_pseudo_legal_moves would give for this board
+*/
+pub fn is_pseudo_legal(board: &BoardState, mov: &Move) -> bool {
+    pseudo_legal_moves_from(board, mov.from.point()).contains(mov)
+}
+
+/*
+    Determine if an arbitrary move, ie one sent by a GUI or typed in by a user, can be played on the
+    board, including castling through check and en passant captures by pinned pawns
+*/
+pub fn is_move_legal(board: &BoardState, mov: Move) -> bool {
+    is_pseudo_legal(board, &mov) && is_legal(board, &mov, &LegalityInfo::new(board))
+}
+
+/*
+    The squares the piece on a square can legally move to, for a GUI to highlight when the piece is
+    picked up. Empty if the square is empty or the piece belongs to the side not to move
+
+    A square which can be reached by several promotions is only given once, and castling gives
+    the square the king finishes on, unless the king does not move when castling (as can happen
+    in Chess960) when it is the rook's square
+*/
+#[allow(dead_code)]
+pub fn legal_destinations(board: &BoardState, square: Square) -> Vec<Square> {
+    let legality = LegalityInfo::new(board);
+    let mut destinations = Vec::new();
+    for mov in pseudo_legal_moves_from(board, square.point()) {
+        if !is_legal(board, &mov, &legality) {
+            continue;
+        }
+        let mut to = mov.to;
+        if mov.is_castle() {
+            let (king_target, _) = castling_targets(mov.to.col() > mov.from.col());
+            to = Square::new(mov.from.row(), king_target).unwrap();
+            if to == mov.from {
+                to = mov.to;
+            }
+        }
+        if !destinations.contains(&to) {
+            destinations.push(to);
+        }
+    }
+    destinations
+}
+
+/*
+    The pseudo-legal moves of the piece on a square, which has to belong to the side to move,
+    including castling when it is the king
+*/
+fn pseudo_legal_moves_from(board: &BoardState, from: Point) -> Vec<Move> {
+    let mut moves = Vec::new();
+    let piece = board.board[from.0][from.1];
+    if get_color(piece) != Some(board.to_move) {
+        return moves;
+    }
+    generate_moves_for_piece(board, from, MoveKinds::All, &mut moves);
+    if is_king(piece) {
+        generate_castling_moves(board, &mut moves);
+    }
+    moves
+}
+
+/*
+    What is_legal needs to know about the position before the move, worked out once for all the
+    moves from the position
+*/
+pub struct LegalityInfo {
+    checkers: PieceList,
+    pinned: PieceList,
+    // when in check by a single piece, the squares a piece other than the king can move to to get
+    // out of check, ie the checking piece and the squares between it and the king
+    evasion_squares: Vec<Point>,
+}
+
+impl LegalityInfo {
+    pub fn new(board: &BoardState) -> LegalityInfo {
+        let checkers = checkers(board);
+        let mut evasion_squares = vec![];
+        if checkers.len() == 1 {
+            let checker = checkers.iter().next().unwrap();
+            evasion_squares.push(checker);
+            // a rook, bishop or queen can also be blocked
+            if matches!(
+                board.board[checker.0][checker.1] & PIECE_MASK,
+                ROOK | BISHOP | QUEEN
+            ) {
+                let king = match board.to_move {
+                    PieceColor::White => board.white_king_location.point(),
+                    PieceColor::Black => board.black_king_location.point(),
+                };
+                let step = (
+                    (checker.0 as i8 - king.0 as i8).signum(),
+                    (checker.1 as i8 - king.1 as i8).signum(),
+                );
+                let mut square = (
+                    (king.0 as i8 + step.0) as usize,
+                    (king.1 as i8 + step.1) as usize,
+                );
+                while square != checker {
+                    evasion_squares.push(square);
+                    square = (
+                        (square.0 as i8 + step.0) as usize,
+                        (square.1 as i8 + step.1) as usize,
+                    );
+                }
+            }
+        }
+        LegalityInfo {
+            checkers,
+            pinned: pinned_pieces(board),
+            evasion_squares,
+        }
+    }
+
+    pub fn in_check(&self) -> bool {
+        !self.checkers.is_empty()
+    }
+}
+
+/*
+    Generate the pseudo-legal moves which could get the side to move out of check, which are king
+    moves and moves capturing or blocking the checking piece (only king moves in double check)
+*/
+pub fn generate_evasions(board: &BoardState, legality: &LegalityInfo) -> Vec<Move> {
+    let mut moves = Vec::new();
+    let first_piece = if legality.checkers.len() > 1 {
+        KING
+    } else {
+        PAWN
+    };
+    for piece in first_piece..=KING {
+        for square in board.piece_list(board.to_move, piece).iter() {
+            let mut piece_moves = Vec::new();
+            generate_moves_for_piece(board, square, MoveKinds::All, &mut piece_moves);
+            moves.extend(
+                piece_moves
+                    .into_iter()
+                    .filter(|m| piece == KING || is_evasion(m, legality)),
+            );
+        }
+    }
+    moves
+}
+
+/*
+    Whether a move of a piece other than the king captures or blocks the only checking piece
+*/
+fn is_evasion(mov: &Move, legality: &LegalityInfo) -> bool {
+    // the pawn taken en passant is beside the square the capturing pawn lands on
+    let captured = if mov.flags & MOVE_EN_PASSANT != 0 {
+        (mov.from.row(), mov.to.col())
+    } else {
+        mov.to.point()
+    };
+    legality.evasion_squares.contains(&mov.to.point())
+        || legality.evasion_squares.first() == Some(&captured)
+}
+
+/*
+    Determine if a pseudo-legal move keeps the king of the side to move out of check
+
+    A king move is legal if its target is not attacked, in check any other move has to capture or
+    block the checking piece, and a pinned piece can only move along the line of its pin. En
+    passant (which takes two pieces off a line at once) and castling are rare enough to be checked
+    by making the move
+*/
+pub fn is_legal(board: &BoardState, mov: &Move, legality: &LegalityInfo) -> bool {
+    let color = board.to_move;
+    let king = match color {
+        PieceColor::White => board.white_king_location.point(),
+        PieceColor::Black => board.black_king_location.point(),
+    };
+    let from = mov.from.point();
+    if mov.is_castle() || mov.flags & MOVE_EN_PASSANT != 0 {
+        return !is_check(&make_move(board, mov), color);
+    }
+    if from == king {
+        return !is_attacked_through(board, color, mov.to.point(), from);
+    }
+    if legality.in_check() && !is_evasion(mov, legality) {
+        return false;
+    }
+    if !legality.pinned.contains(from) {
+        return true;
+    }
+
+    // the piece stays on the line from the king through its starting square
+    let to = mov.to.point();
+    let along = (from.0 as i8 - king.0 as i8, from.1 as i8 - king.1 as i8);
+    let moved = (to.0 as i8 - king.0 as i8, to.1 as i8 - king.1 as i8);
+    along.0 * moved.1 == along.1 * moved.0 && along.0 * moved.0 + along.1 * moved.1 > 0
+}
+
+/*
+    The boards reached by each legal move from the given board, in the same order as generate_moves
+*/
+pub fn generate_child_boards(board: &BoardState) -> Vec<BoardState> {
+    generate_moves(board)
+        .iter()
+        .map(|mov| make_move(board, mov))
+        .collect()
+}
+
+/*
+    Play a move from generate_moves, returning the new board
+    Also sets appropriate variables for the board state
+*/
+pub fn make_move(board: &BoardState, mov: &Move) -> BoardState {
+    let color = board.to_move;
+    let piece = board.board[mov.from.row()][mov.from.col()];
+    let mut new_board = board.clone();
+    new_board.swap_color();
+    if color == PieceColor::Black {
+        new_board.full_move_clock = new_board.full_move_clock.saturating_add(1);
+    }
+    new_board.pawn_double_move = None;
+    new_board.last_move = Some(mov.to_string());
+
+    let king_square = if mov.is_castle() {
+        let row = mov.from.row();
+        let (king_target, rook_target) = castling_targets(mov.to.col() > mov.from.col());
+        new_board.half_move_clock = new_board.half_move_clock.saturating_add(1);
+        // in Chess960 the king or rook can finish on the other's starting square, so clear both first
+        new_board.set_square(mov.from.point(), EMPTY);
+        new_board.set_square(mov.to.point(), EMPTY);
+        new_board.set_square((row, king_target), color.as_mask() | KING);
+        new_board.set_square((row, rook_target), color.as_mask() | ROOK);
+        Square::new(row, king_target).unwrap()
+    } else {
+        // pawn moves and captures reset the fifty-move counter
+        if is_pawn(piece) || mov.is_capture() {
+            new_board.half_move_clock = 0;
+        } else {
+            new_board.half_move_clock = new_board.half_move_clock.saturating_add(1);
+        }
+
+        // move the piece, this will take care of any captures (and the material they win) as well, excluding en passant
+        let moved = match mov.promotion {
+            Some(kind) => color.as_mask() | kind.as_bits(),
+            None => piece,
+        };
+        new_board.set_square(mov.to.point(), moved);
+        new_board.set_square(mov.from.point(), EMPTY);
+        // the pawn taken en passant is beside the square the capturing pawn started on
+        if mov.flags & MOVE_EN_PASSANT != 0 {
+            new_board.set_square((mov.from.row(), mov.to.col()), EMPTY);
+        }
+
+        // a pawn which moved two spaces can be captured en passant, record the space *behind* the pawn ie the valid capture square
+        if mov.flags & MOVE_DOUBLE_PAWN_PUSH != 0 {
+            new_board.pawn_double_move =
+                Square::new((mov.from.row() + mov.to.row()) / 2, mov.to.col());
+        }
+        mov.to
+    };
+
+    // update king location and take away castling privileges if we are moving the king
+    if piece == WHITE | KING {
+        new_board.white_king_location = king_square;
+        new_board.white_king_side_castle = false;
+        new_board.white_queen_side_castle = false;
+    } else if piece == BLACK | KING {
+        new_board.black_king_location = king_square;
+        new_board.black_king_side_castle = false;
+        new_board.black_queen_side_castle = false;
+    }
+
+    // if the rook moves or is captured, take away castling privileges
+    for castling_type in &CASTLING_TYPES {
+        let rook_square = (
+            back_rank_row(castling_type.color()),
+            BOARD_START + board.castling_rook_files[castling_type.index()],
+        );
+        if mov.from.point() == rook_square || mov.to.point() == rook_square {
+            remove_castling_right(&mut new_board, *castling_type);
+        }
+    }
+
+    #[cfg(feature = "paranoid")]
+    assert_board_invariants(&new_board);
+
+    new_board
+}
+
+/*
+    Count the positions reached after exactly 'depth' moves from the given board, for checking move
+    generation against known counts (www.chessprogramming.org/Perft_Results)
+*/
+pub fn perft(board: &BoardState, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = generate_moves(board);
+    // the positions after the last move are only counted, not made
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    moves
+        .iter()
+        .map(|mov| perft(&make_move(board, mov), depth - 1))
+        .sum()
+}
+
+/*
+    Perft counts already worked out, so a position reached again by another order of moves is only
+    counted once. A count is only used for the same position at the same depth, and is replaced by
+    any later count which lands in the same slot
+*/
+pub struct PerftTable {
+    entries: Vec<Option<PerftEntry>>,
+}
+
+#[derive(Clone, Copy)]
+struct PerftEntry {
+    // zobrist key of the position
+    key: u64,
+    depth: u8,
+    nodes: u64,
+}
+
+impl PerftTable {
+    /*
+        A table taking up about the given number of megabytes, with room for at least one count
+    */
+    pub fn new(megabytes: usize) -> PerftTable {
+        let entries = megabytes * 1024 * 1024 / mem::size_of::<Option<PerftEntry>>();
+        PerftTable {
+            entries: vec![None; entries.max(1)],
+        }
+    }
+
+    fn index(&self, key: u64, depth: u8) -> usize {
+        // mix in the depth so the counts of a position at different depths do not share a slot
+        let key = key ^ (depth as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        (key % self.entries.len() as u64) as usize
+    }
+
+    fn get(&self, key: u64, depth: u8) -> Option<u64> {
+        match self.entries[self.index(key, depth)] {
+            Some(entry) if entry.key == key && entry.depth == depth => Some(entry.nodes),
+            _ => None,
+        }
+    }
+
+    fn insert(&mut self, key: u64, depth: u8, nodes: u64) {
+        let index = self.index(key, depth);
+        self.entries[index] = Some(PerftEntry { key, depth, nodes });
+    }
+}
+
+/*
+    Same as perft, but positions already counted to the same depth are looked up in the table
+    rather than counted again
+*/
+pub fn perft_hashed(board: &BoardState, depth: u8, table: &mut PerftTable) -> u64 {
+    // counting the moves of a position is about as quick as looking it up
+    if depth <= 1 {
+        return perft(board, depth);
+    }
+    let key = board.position_key();
+    if let Some(nodes) = table.get(key, depth) {
+        return nodes;
+    }
+    let nodes = generate_moves(board)
+        .iter()
+        .map(|mov| perft_hashed(&make_move(board, mov), depth - 1, table))
+        .sum();
+    table.insert(key, depth, nodes);
+    nodes
+}
+
+/*
+    The perft count below each move from the given board, in the order the moves are generated, so
+    a wrong total can be narrowed down to the move (and then the position) it comes from by
+    comparing against another engine
+*/
+pub fn perft_divide(board: &BoardState, depth: u8) -> Vec<(Move, u64)> {
+    if depth == 0 {
+        return vec![];
+    }
+    generate_moves(board)
+        .into_iter()
+        .map(|mov| (mov, perft(&make_move(board, &mov), depth - 1)))
+        .collect()
+}
+
+/*
+    Same as perft_divide, but the moves from the given board are shared out between 'threads'
+    worker threads, each taking the next move not yet counted until there are none left
+
+    With 'hash_megabytes' above 0 the counts are remembered in a PerftTable, split evenly between
+    the threads so they never wait on each other
+*/
+pub fn perft_divide_parallel(
+    board: &BoardState,
+    depth: u8,
+    threads: usize,
+    hash_megabytes: usize,
+) -> Vec<(Move, u64)> {
+    if depth == 0 || (threads <= 1 && hash_megabytes == 0) {
+        return perft_divide(board, depth);
+    }
+    let moves = generate_moves(board);
+    let threads = threads.max(1).min(moves.len());
+    let next_move = AtomicUsize::new(0);
+    let counts = Mutex::new(vec![0; moves.len()]);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                let mut table =
+                    (hash_megabytes > 0).then(|| PerftTable::new(hash_megabytes / threads));
+                loop {
+                    let index = next_move.fetch_add(1, Ordering::Relaxed);
+                    if index >= moves.len() {
+                        break;
+                    }
+                    let next_board = make_move(board, &moves[index]);
+                    let nodes = match table.as_mut() {
+                        Some(table) => perft_hashed(&next_board, depth - 1, table),
+                        None => perft(&next_board, depth - 1),
+                    };
+                    counts.lock().unwrap()[index] = nodes;
+                }
+            });
+        }
+    });
+
+    moves
+        .into_iter()
+        .zip(counts.into_inner().unwrap())
+        .collect()
+}
+
+/*
+    The move (ie e1g1 or a7a8n) which turns one position into the next, found by generating the
+    moves from the first position so castling, en passant and promotions are all handled the same
+    way as the engine's own moves
+
+    Returns None if no legal move leads from one to the other
+*/
+#[allow(dead_code)]
+pub fn move_between(before: &BoardState, after: &BoardState) -> Option<String> {
+    generate_moves(before)
+        .iter()
+        .find(|mov| make_move(before, mov) == *after)
+        .map(|mov| mov.to_string())
+}
+
+/*
+    Whether the game is over, and how it ended
+*/
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameStatus {
+    Ongoing,
+    // the color which gave the checkmate
+    Checkmate(PieceColor),
+    Stalemate,
+    DrawByFiftyMove,
+    DrawByInsufficientMaterial,
+}
+
+impl fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameStatus::Ongoing => write!(f, "ongoing"),
+            GameStatus::Checkmate(PieceColor::White) => write!(f, "checkmate, white wins"),
+            GameStatus::Checkmate(PieceColor::Black) => write!(f, "checkmate, black wins"),
+            GameStatus::Stalemate => write!(f, "draw by stalemate"),
+            GameStatus::DrawByFiftyMove => write!(f, "draw by the fifty-move rule"),
+            GameStatus::DrawByInsufficientMaterial => write!(f, "draw by insufficient material"),
+        }
+    }
+}
+
+impl BoardState {
+    /*
+        Whether the game has ended in this position, by the rules alone (repetitions need the
+        history of the game, which a single position does not have)
+
+        A checkmate on the move which reaches the fifty-move limit still ends the game as a
+        checkmate
+    */
+    pub fn status(&self) -> GameStatus {
+        if generate_moves(self).is_empty() {
+            return if in_check(self) {
+                match self.to_move {
+                    PieceColor::White => GameStatus::Checkmate(PieceColor::Black),
+                    PieceColor::Black => GameStatus::Checkmate(PieceColor::White),
+                }
+            } else {
+                GameStatus::Stalemate
+            };
+        }
+        if self.half_move_clock >= 100 {
+            return GameStatus::DrawByFiftyMove;
+        }
+        if self.has_insufficient_material() {
+            return GameStatus::DrawByInsufficientMaterial;
+        }
+        GameStatus::Ongoing
+    }
+
+    /*
+        Neither side can ever checkmate, which is the case with only kings and at most one knight or
+        bishop left, or with only kings and bishops which all stand on the same color of square
+    */
+    fn has_insufficient_material(&self) -> bool {
+        let colors = [PieceColor::White, PieceColor::Black];
+        for color in &colors {
+            for piece in &[PAWN, ROOK, QUEEN] {
+                if !self.piece_list(*color, *piece).is_empty() {
+                    return false;
+                }
+            }
+        }
+        let knights: usize = colors
+            .iter()
+            .map(|c| self.piece_list(*c, KNIGHT).len())
+            .sum();
+        let bishops: Vec<Point> = colors
+            .iter()
+            .flat_map(|c| self.piece_list(*c, BISHOP).iter())
+            .collect();
+        if knights + bishops.len() <= 1 {
+            return true;
+        }
+        knights == 0
+            && bishops
+                .iter()
+                .all(|b| (b.0 + b.1) % 2 == (bishops[0].0 + bishops[0].1) % 2)
+    }
+}
+
+/*
+    Determine if a color is currently in check
+*/
+pub fn is_check(board: &BoardState, color: PieceColor) -> bool {
+    match color {
+        PieceColor::Black => {
+            is_check_cords(board, PieceColor::Black, board.black_king_location.point())
+        }
+        PieceColor::White => {
+            is_check_cords(board, PieceColor::White, board.white_king_location.point())
+        }
+    }
+}
+
+/*
+    Determine if the side to move is in check
+*/
+pub fn in_check(board: &BoardState) -> bool {
+    is_check(board, board.to_move)
+}
+
+/*
+    Determine if a legal move puts the other king in check, without making the move
+
+    The king can be checked directly by the piece on its new square, or by a rook, bishop or queen
+    behind the square it left (a discovered check). En passant and castling move two pieces at
+    once and are rare enough to be checked by making the move
+*/
+pub fn gives_check(board: &BoardState, mov: Move) -> bool {
+    if mov.is_castle() || mov.flags & MOVE_EN_PASSANT != 0 {
+        return in_check(&make_move(board, &mov));
+    }
+    let color = board.to_move;
+    let king = match color {
+        PieceColor::White => board.black_king_location.point(),
+        PieceColor::Black => board.white_king_location.point(),
+    };
+    let from = mov.from.point();
+    let to = mov.to.point();
+    let kind = match mov.promotion {
+        Some(kind) => kind.as_bits(),
+        None => board.board[from.0][from.1] & PIECE_MASK,
+    };
+    // the piece on a square once the move is made
+    let after = |row: i8, col: i8| {
+        let square = (row as usize, col as usize);
+        if square == to {
+            color.as_mask() | kind
+        } else if square == from {
+            EMPTY
+        } else {
+            board.board[square.0][square.1]
+        }
+    };
+    // whether a rook, bishop or queen sees the king along the line from the king to a square
+    let slider_checks_towards = |square: Point| {
+        let offset = (square.0 as i8 - king.0 as i8, square.1 as i8 - king.1 as i8);
+        if offset.0 != 0 && offset.1 != 0 && offset.0.abs() != offset.1.abs() {
+            return false;
+        }
+        let step = (offset.0.signum(), offset.1.signum());
+        let mut row = king.0 as i8 + step.0;
+        let mut col = king.1 as i8 + step.1;
+        while is_empty(after(row, col)) {
+            row += step.0;
+            col += step.1;
+        }
+        let slider = if step.0 == 0 || step.1 == 0 {
+            ROOK
+        } else {
+            BISHOP
+        };
+        let piece = after(row, col);
+        piece == color.as_mask() | slider || piece == color.as_mask() | QUEEN
+    };
+
+    if slider_checks_towards(from) {
+        return true;
+    }
+    let offset = (king.0 as i8 - to.0 as i8, king.1 as i8 - to.1 as i8);
+    match kind {
+        PAWN => {
+            let forward = match color {
+                PieceColor::White => -1,
+                PieceColor::Black => 1,
+            };
+            offset.0 == forward && offset.1.abs() == 1
+        }
+        KNIGHT => KNIGHT_OFFSETS.contains(&offset),
+        KING => false,
+        _ => slider_checks_towards(to),
+    }
+}
+
+/*
+    Enemy pieces giving check to the king of the side to move
+*/
+pub fn checkers(board: &BoardState) -> PieceList {
+    let mut checkers = PieceList::default();
+    let (king, opponent) = match board.to_move {
+        PieceColor::White => (board.white_king_location.point(), PieceColor::Black),
+        PieceColor::Black => (board.black_king_location.point(), PieceColor::White),
+    };
+    for (directions, slider) in &[(STRAIGHT, ROOK), (DIAGONAL, BISHOP)] {
+        for m in directions {
+            let mut row = king.0 as i8 + m.0;
+            let mut col = king.1 as i8 + m.1;
+            while is_empty(board.board[row as usize][col as usize]) {
+                row += m.0;
+                col += m.1;
+            }
+            let square = board.board[row as usize][col as usize];
+            if square == opponent.as_mask() | slider || square == opponent.as_mask() | QUEEN {
+                checkers.insert((row as usize, col as usize));
+            }
+        }
+    }
+
+    for mods in &KNIGHT_OFFSETS {
+        let square = (
+            (king.0 as i8 + mods.0) as usize,
+            (king.1 as i8 + mods.1) as usize,
+        );
+        if board.board[square.0][square.1] == opponent.as_mask() | KNIGHT {
+            checkers.insert(square);
+        }
+    }
+
+    // enemy pawns attack the king from the rows in front of it
+    let pawn_row = match board.to_move {
+        PieceColor::White => king.0 - 1,
+        PieceColor::Black => king.0 + 1,
+    };
+    for col in &[king.1 - 1, king.1 + 1] {
+        if board.board[pawn_row][*col] == opponent.as_mask() | PAWN {
+            checkers.insert((pawn_row, *col));
+        }
+    }
+    checkers
+}
+
+/*
+    Pieces of the side to move which are absolutely pinned, ie moving them off the line between
+    their king and an enemy rook, bishop or queen would leave the king in check
+*/
+pub fn pinned_pieces(board: &BoardState) -> PieceList {
+    let opponent = match board.to_move {
+        PieceColor::White => PieceColor::Black,
+        PieceColor::Black => PieceColor::White,
+    };
+    line_blockers(board, board.to_move, opponent)
+}
+
+/*
+    Pieces of the side to move which are the only thing between one of its own rooks, bishops or
+    queens and the enemy king, so moving them off the line gives a discovered check
+*/
+#[allow(dead_code)]
+pub fn discovered_checkers(board: &BoardState) -> PieceList {
+    let opponent = match board.to_move {
+        PieceColor::White => PieceColor::Black,
+        PieceColor::Black => PieceColor::White,
+    };
+    line_blockers(board, opponent, board.to_move)
+}
 
 /*
-    Generate all possible moves *legal* from the given board
-    Also sets appropriate variables for the board state
+    Pieces belonging to the side to move which are the only piece on a line between the king of
+    'king_color' and a rook, bishop or queen of 'slider_color' that moves along that line
 */
-pub fn generate_moves(board: &BoardState) -> Vec<BoardState> {
-    let mut new_moves = Vec::new();
-
-    for i in BOARD_START..BOARD_END {
-        for j in BOARD_START..BOARD_END {
-            let color = get_color(board.board[i][j]);
-            if color.is_some() && color.unwrap() == board.to_move {
-                generate_move_for_piece(board, (i, j), &mut new_moves);
+fn line_blockers(
+    board: &BoardState,
+    king_color: PieceColor,
+    slider_color: PieceColor,
+) -> PieceList {
+    let mut blockers = PieceList::default();
+    let king = match king_color {
+        PieceColor::White => board.white_king_location.point(),
+        PieceColor::Black => board.black_king_location.point(),
+    };
+    for (directions, slider) in &[(STRAIGHT, ROOK), (DIAGONAL, BISHOP)] {
+        for m in directions {
+            let mut blocker = None;
+            let mut row = king.0 as i8 + m.0;
+            let mut col = king.1 as i8 + m.1;
+            loop {
+                let square = board.board[row as usize][col as usize];
+                if is_outside_board(square) {
+                    break;
+                }
+                if !is_empty(square) {
+                    if blocker.is_none() && get_color(square) == Some(board.to_move) {
+                        blocker = Some((row as usize, col as usize));
+                    } else {
+                        if let Some(blocker) = blocker {
+                            if square == slider_color.as_mask() | slider
+                                || square == slider_color.as_mask() | QUEEN
+                            {
+                                blockers.insert(blocker);
+                            }
+                        }
+                        break;
+                    }
+                }
+                row += m.0;
+                col += m.1;
             }
         }
     }
-
-    generate_castling_moves(board, &mut new_moves);
-    new_moves
+    blockers
 }
 
 /*
-    Determine if a color is currently in check
+    The number of squares the piece on a square could move to, captures included but not castling
+    or en passant, counted without generating the moves so the evaluation can afford it for every
+    piece. A pawn which can promote counts each of its squares once
 */
-pub fn is_check(board: &BoardState, color: PieceColor) -> bool {
-    match color {
-        PieceColor::Black => is_check_cords(board, PieceColor::Black, board.black_king_location),
-        PieceColor::White => is_check_cords(board, PieceColor::White, board.white_king_location),
+pub fn mobility(board: &BoardState, square: Point) -> u32 {
+    let piece = board.board[square.0][square.1];
+    let enemy = |target: u8| {
+        !is_empty(target) && !is_outside_board(target) && target & COLOR_MASK != piece & COLOR_MASK
+    };
+    let offset = |m: &(i8, i8)| {
+        let target = (square.0 as i8 + m.0, square.1 as i8 + m.1);
+        board.board[target.0 as usize][target.1 as usize]
+    };
+    let slides = |directions: &[(i8, i8)]| {
+        let mut count = 0;
+        for m in directions {
+            let mut row = square.0 as i8 + m.0;
+            let mut col = square.1 as i8 + m.1;
+            while is_empty(board.board[row as usize][col as usize]) {
+                count += 1;
+                row += m.0;
+                col += m.1;
+            }
+            if enemy(board.board[row as usize][col as usize]) {
+                count += 1;
+            }
+        }
+        count
+    };
+
+    match piece & PIECE_MASK {
+        PAWN => {
+            // white pawns move up the board
+            let (forward, start_row) = if is_white(piece) { (-1, 8) } else { (1, 3) };
+            let mut count = [(forward, -1), (forward, 1)]
+                .iter()
+                .filter(|m| enemy(offset(m)))
+                .count() as u32;
+            if is_empty(offset(&(forward, 0))) {
+                count += 1;
+                if square.0 == start_row && is_empty(offset(&(2 * forward, 0))) {
+                    count += 1;
+                }
+            }
+            count
+        }
+        KNIGHT | KING => {
+            let moves: &[(i8, i8)] = if piece & PIECE_MASK == KNIGHT {
+                &KNIGHT_OFFSETS
+            } else {
+                &[
+                    (1, 0),
+                    (-1, 0),
+                    (0, 1),
+                    (0, -1),
+                    (1, -1),
+                    (1, 1),
+                    (-1, 1),
+                    (-1, -1),
+                ]
+            };
+            moves
+                .iter()
+                .map(offset)
+                .filter(|target| is_empty(*target) || enemy(*target))
+                .count() as u32
+        }
+        ROOK => slides(&STRAIGHT),
+        BISHOP => slides(&DIAGONAL),
+        QUEEN => slides(&STRAIGHT) + slides(&DIAGONAL),
+        _ => 0,
     }
 }
 
+/*
+    The mobility of all of a color's pieces added up
+*/
+pub fn total_mobility(board: &BoardState, color: PieceColor) -> u32 {
+    (PAWN..=KING)
+        .map(|piece| {
+            board
+                .piece_list(color, piece)
+                .iter()
+                .map(|square| mobility(board, square))
+                .sum::<u32>()
+        })
+        .sum()
+}
+
 /*
     Generate pseudo-legal moves for a knight
 */
 fn knight_moves(row: usize, col: usize, board: &BoardState, moves: &mut Vec<Point>) {
     let piece = board.board[row][col];
-    for mods in &KNIGHT_CORDS {
+    for mods in &KNIGHT_OFFSETS {
         let row = (row as i8 + mods.0) as usize;
         let col = (col as i8 + mods.1) as usize;
         let square = board.board[row][col];
@@ -134,7 +1165,7 @@ fn pawn_moves(row: usize, col: usize, board: &BoardState, moves: &mut Vec<Point>
 */
 
 fn pawn_moves_en_passant(row: usize, col: usize, board: &BoardState) -> Option<Point> {
-    board.pawn_double_move?;
+    let en_passant = board.pawn_double_move?.point();
 
     let piece = board.board[row][col];
     let left_cap;
@@ -150,9 +1181,9 @@ fn pawn_moves_en_passant(row: usize, col: usize, board: &BoardState) -> Option<P
         return None;
     }
 
-    if left_cap == board.pawn_double_move.unwrap() {
+    if left_cap == en_passant {
         return Some(left_cap);
-    } else if right_cap == board.pawn_double_move.unwrap() {
+    } else if right_cap == en_passant {
         return Some(right_cap);
     }
 
@@ -249,24 +1280,42 @@ fn get_moves(row: usize, col: usize, board: &BoardState, moves: &mut Vec<Point>)
 }
 
 /*
-    Determine if the given position is check
-
-    Rather than checking each piece to see if it attacks the king
-    this function checks all possible attack squares to the king and
-    sees if the piece is there, thus it is important the king_location is set
+    Determine if a piece of the given color on the given square would be attacked, ie whether its
+    king is in check there, looked up in the board's attack map
 */
 fn is_check_cords(board: &BoardState, color: PieceColor, square_cords: Point) -> bool {
     let attacking_color = match color {
         PieceColor::White => PieceColor::Black,
         PieceColor::Black => PieceColor::White,
     };
+    board.attacks.is_attacked(attacking_color, square_cords)
+}
+
+/*
+    Same as is_check_cords, but rooks, bishops and queens see through the piece on 'through', so a
+    king can not step away from a slider along the line it attacks on
+*/
+fn is_attacked_through(
+    board: &BoardState,
+    color: PieceColor,
+    square_cords: Point,
+    through: Point,
+) -> bool {
+    // nothing can see through a piece which is not attacked, so the attack map has the answer
+    if !is_check_cords(board, color, through) {
+        return is_check_cords(board, color, square_cords);
+    }
+    let attacking_color = match color {
+        PieceColor::White => PieceColor::Black,
+        PieceColor::Black => PieceColor::White,
+    };
 
     // Check from rook or queen
     for m in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
         let mut row = square_cords.0 as i8 + m.0;
         let mut col = square_cords.1 as i8 + m.1;
         let mut square = board.board[row as usize][col as usize];
-        while is_empty(square) {
+        while is_empty(square) || (row as usize, col as usize) == through {
             row += m.0;
             col += m.1;
             square = board.board[row as usize][col as usize];
@@ -283,7 +1332,7 @@ fn is_check_cords(board: &BoardState, color: PieceColor, square_cords: Point) ->
         let mut row = square_cords.0 as i8 + m.0;
         let mut col = square_cords.1 as i8 + m.1;
         let mut square = board.board[row as usize][col as usize];
-        while is_empty(square) {
+        while is_empty(square) || (row as usize, col as usize) == through {
             row += m.0;
             col += m.1;
             square = board.board[row as usize][col as usize];
@@ -297,7 +1346,7 @@ fn is_check_cords(board: &BoardState, color: PieceColor, square_cords: Point) ->
     }
 
     // Check from knight
-    for mods in &KNIGHT_CORDS {
+    for mods in &KNIGHT_OFFSETS {
         let row = (square_cords.0 as i8 + mods.0) as usize;
         let col = (square_cords.1 as i8 + mods.1) as usize;
         let square = board.board[row][col];
@@ -345,346 +1394,195 @@ fn is_check_cords(board: &BoardState, color: PieceColor, square_cords: Point) ->
     Rules
     1. The castling must be kingside or queen side.
     2. Neither the king nor the chosen rook has previously moved.
-    3. There are no pieces between the king and the chosen rook.
+    3. There are no pieces between the king and the chosen rook, or on the squares the king and rook
+       move to (other than the king and rook themselves).
     4. The king is not currently in check.
     5. The king does not pass through a square that is attacked by an enemy piece.
     6. The king does not end up in check. (True of any legal move.)
 
-    This method will check all but rule 2
+    This method will check all but rule 2, and rule 6 when the castling rook was blocking an attack
+    along the back rank (only possible in Chess960), which is_legal checks
 
     This method will check the board state to determine if is should go ahead with the castling check
     If the associated castling privilege variable is set to true, the following will be assumed by this function
     1. The king and associated rook have not moved yet this game
-    2. The king and associated rook are in the correct castling positions
 
     Thus its the responsibility of other functions to update the castling privilege variables when the king or associated rook moves (including castling)
 
 */
 fn can_castle(board: &BoardState, castling_type: CastlingType) -> bool {
-    match castling_type {
-        CastlingType::WhiteKingSide => can_castle_white_king_side(board),
-        CastlingType::WhiteQueenSide => can_castle_white_queen_side(board),
-        CastlingType::BlackKingSide => can_castle_black_king_side(board),
-        CastlingType::BlackQueenSide => can_castle_black_queen_side(board),
-    }
-}
-
-fn can_castle_white_king_side(board: &BoardState) -> bool {
-    if !board.white_king_side_castle {
-        return false;
-    }
-    // check that squares required for castling are empty
-    if !is_empty(board.board[BOARD_END - 1][BOARD_END - 3])
-        || !is_empty(board.board[BOARD_END - 1][BOARD_END - 2])
-    {
-        return false;
-    }
-    // check that the king currently isn't in check
-    if is_check(board, PieceColor::White) {
+    if !board.castling_rights()[castling_type.index()] {
         return false;
     }
-    //check that the squares required for castling are not threatened
-    if is_check_cords(board, PieceColor::White, (BOARD_END - 1, BOARD_END - 3))
-        || is_check_cords(board, PieceColor::White, (BOARD_END - 1, BOARD_END - 2))
+    let color = castling_type.color();
+    let (row, king_col, rook_col, king_target, rook_target) =
+        castling_squares(board, castling_type);
+    // the rights of a hand written fen string can be set without the king or rook in place
+    if board.board[row][king_col] != color.as_mask() | KING
+        || board.board[row][rook_col] != color.as_mask() | ROOK
     {
         return false;
     }
 
-    true
-}
-
-fn can_castle_white_queen_side(board: &BoardState) -> bool {
-    if !board.white_queen_side_castle {
-        return false;
-    }
     // check that squares required for castling are empty
-    if !is_empty(board.board[BOARD_END - 1][BOARD_START + 1])
-        || !is_empty(board.board[BOARD_END - 1][BOARD_START + 2])
-        || !is_empty(board.board[BOARD_END - 1][BOARD_START + 3])
-    {
-        return false;
+    let first = king_col.min(rook_col).min(king_target).min(rook_target);
+    let last = king_col.max(rook_col).max(king_target).max(rook_target);
+    for col in first..=last {
+        if col != king_col && col != rook_col && !is_empty(board.board[row][col]) {
+            return false;
+        }
     }
     // check that the king currently isn't in check
-    if is_check(board, PieceColor::White) {
+    if is_check(board, color) {
         return false;
     }
     //check that the squares required for castling are not threatened
-    if is_check_cords(board, PieceColor::White, (BOARD_END - 1, BOARD_START + 3))
-        || is_check_cords(board, PieceColor::White, (BOARD_END - 1, BOARD_START + 2))
-    {
-        return false;
+    for col in king_col.min(king_target)..=king_col.max(king_target) {
+        if is_check_cords(board, color, (row, col)) {
+            return false;
+        }
     }
 
     true
 }
 
-fn can_castle_black_king_side(board: &BoardState) -> bool {
-    if !board.black_king_side_castle {
-        return false;
-    }
-    // check that squares required for castling are empty
-    if !is_empty(board.board[BOARD_START][BOARD_END - 3])
-        || !is_empty(board.board[BOARD_START][BOARD_END - 2])
-    {
-        return false;
-    }
-    // check that the king currently isn't in check
-    if is_check(board, PieceColor::Black) {
-        return false;
-    }
-    //check that the squares required for castling are not threatened
-    if is_check_cords(board, PieceColor::Black, (BOARD_START, BOARD_END - 3))
-        || is_check_cords(board, PieceColor::Black, (BOARD_START, BOARD_END - 2))
-    {
-        return false;
-    }
-
-    true
+/*
+    The row castling happens on, the columns the king and rook start on, and the columns the king
+    and rook finish on (the g and f files king side, the c and d files queen side)
+*/
+fn castling_squares(
+    board: &BoardState,
+    castling_type: CastlingType,
+) -> (usize, usize, usize, usize, usize) {
+    let color = castling_type.color();
+    let king = match color {
+        PieceColor::White => board.white_king_location,
+        PieceColor::Black => board.black_king_location,
+    };
+    let rook_col = BOARD_START + board.castling_rook_files[castling_type.index()];
+    let (king_target, rook_target) = castling_targets(castling_type.is_king_side());
+    (
+        back_rank_row(color),
+        king.col(),
+        rook_col,
+        king_target,
+        rook_target,
+    )
 }
 
-fn can_castle_black_queen_side(board: &BoardState) -> bool {
-    if !board.black_queen_side_castle {
-        return false;
-    }
-    // check that squares required for castling are empty
-    if !is_empty(board.board[BOARD_START][BOARD_START + 1])
-        || !is_empty(board.board[BOARD_START][BOARD_START + 2])
-        || !is_empty(board.board[BOARD_START][BOARD_START + 3])
-    {
-        return false;
-    }
-    // check that the king currently isn't in check
-    if is_check(board, PieceColor::Black) {
-        return false;
-    }
-    //check that the squares required for castling are not threatened
-    if is_check_cords(board, PieceColor::Black, (BOARD_START, BOARD_START + 2))
-        || is_check_cords(board, PieceColor::Black, (BOARD_START, BOARD_START + 3))
-    {
-        return false;
+/*
+    The columns the king and rook finish on when castling, the g and f files king side and the c and
+    d files queen side
+*/
+fn castling_targets(king_side: bool) -> (usize, usize) {
+    if king_side {
+        (BOARD_START + 6, BOARD_START + 5)
+    } else {
+        (BOARD_START + 2, BOARD_START + 3)
     }
-
-    true
 }
 
 /*
-    Given the coordinates of a piece and that pieces color, generate all possible *legal* moves for that piece
+    Given the coordinates of a piece, generate all pseudo-legal moves for that piece
 */
-fn generate_move_for_piece(
+fn generate_moves_for_piece(
     board: &BoardState,
     square_cords: Point,
-    new_moves: &mut Vec<BoardState>,
+    kinds: MoveKinds,
+    moves: &mut Vec<Move>,
 ) {
-    let mut moves: Vec<Point> = vec![];
+    let mut targets: Vec<Point> = vec![];
     let piece = board.board[square_cords.0][square_cords.1];
-    let color = get_color(piece).unwrap();
-    get_moves(square_cords.0, square_cords.1, &board, &mut moves);
-
-    // make all the valid moves of this piece
-    for _move in moves {
-        let mut new_board = board.clone();
-        new_board.swap_color();
-        if color == PieceColor::Black {
-            new_board.full_move_clock += 1;
-        }
-        // update king location if we are moving the king
-        if piece == WHITE | KING {
-            new_board.white_king_location = _move;
-        } else if piece == BLACK | KING {
-            new_board.black_king_location = _move;
-        }
-
-        let target_square = new_board.board[_move.0][_move.1];
-        if !is_empty(target_square) {
-            let piece_value = PIECE_VALUES[(target_square & PIECE_MASK) as usize];
-            if board.to_move == PieceColor::White {
-                new_board.black_total_piece_value -= piece_value;
-            } else {
-                new_board.white_total_piece_value -= piece_value;
-            }
-        }
+    get_moves(square_cords.0, square_cords.1, board, &mut targets);
+    let from = Square::new(square_cords.0, square_cords.1).unwrap();
 
-        // move the piece, this will take care of any captures as well, excluding en passant
-        new_board.board[_move.0][_move.1] = piece;
-        new_board.board[square_cords.0][square_cords.1] = EMPTY;
-        let move_alg = board_position_to_algebraic_pair(square_cords)
-            + &board_position_to_algebraic_pair(_move);
-        new_board.last_move = Some(move_alg.to_string());
-
-        // if you make your move, and you are in check, this move is not valid
-        if is_check(&new_board, color) {
-            continue;
+    for target in targets {
+        let mut flags = 0;
+        if !is_empty(board.board[target.0][target.1]) {
+            flags |= MOVE_CAPTURE;
         }
-
-        // if the rook or king move, take away castling privileges
-        if piece == WHITE | KING {
-            new_board.white_king_side_castle = false;
-            new_board.white_queen_side_castle = false;
-        } else if piece == BLACK | KING {
-            new_board.black_queen_side_castle = false;
-            new_board.black_king_side_castle = false;
-        } else if square_cords.0 == BOARD_END - 1 && square_cords.1 == BOARD_END - 1 {
-            new_board.white_king_side_castle = false;
-        } else if square_cords.0 == BOARD_END - 1 && square_cords.1 == BOARD_START {
-            new_board.white_queen_side_castle = false;
-        } else if square_cords.0 == BOARD_START && square_cords.1 == BOARD_START {
-            new_board.black_queen_side_castle = false;
-        } else if square_cords.0 == BOARD_START && square_cords.1 == BOARD_END - 1 {
-            new_board.black_king_side_castle = false;
-        }
-
-        // if the rook is captured, take away castling privileges
-        if _move.0 == BOARD_END - 1 && _move.1 == BOARD_END - 1 {
-            new_board.white_king_side_castle = false;
-        } else if _move.0 == BOARD_END - 1 && _move.1 == BOARD_START {
-            new_board.white_queen_side_castle = false;
-        } else if _move.0 == BOARD_START && _move.1 == BOARD_START {
-            new_board.black_queen_side_castle = false;
-        } else if _move.0 == BOARD_START && _move.1 == BOARD_END - 1 {
-            new_board.black_king_side_castle = false;
-        }
-
-        // checks if the pawn has moved two spaces, if it has it can be captured en passant, record the space *behind* the pawn ie the valid capture square
-        if is_pawn(piece) && (square_cords.0 as i8 - _move.0 as i8).abs() == 2 {
-            if is_white(piece) {
-                new_board.pawn_double_move = Some((_move.0 + 1, _move.1));
-            } else {
-                new_board.pawn_double_move = Some((_move.0 - 1, _move.1));
-            }
-        } else {
-            // the most recent move was not a double pawn move, unset any possibly existing pawn double move
-            new_board.pawn_double_move = None;
+        if is_pawn(piece) && (square_cords.0 as i8 - target.0 as i8).abs() == 2 {
+            flags |= MOVE_DOUBLE_PAWN_PUSH;
+        }
+        let mov = Move {
+            from,
+            to: Square::new(target.0, target.1).unwrap(),
+            promotion: None,
+            flags,
+        };
+
+        let promotion = is_pawn(piece) && (target.0 == BOARD_START || target.0 == BOARD_END - 1);
+        let noisy = promotion || mov.is_capture();
+        if (kinds == MoveKinds::Noisy && !noisy) || (kinds == MoveKinds::Quiet && noisy) {
+            continue;
         }
 
         // deal with pawn promotions
-        if _move.0 == BOARD_START && piece == WHITE | PAWN {
-            promote_pawn(&new_board, PieceColor::White, square_cords, _move, new_moves);
-        } else if _move.0 == BOARD_END - 1 && piece == BLACK | PAWN {
-            promote_pawn(&new_board, PieceColor::Black, square_cords, _move, new_moves);
+        if promotion {
+            promote_pawn(mov, moves);
         } else {
-            new_moves.push(new_board);
+            moves.push(mov);
         }
     }
 
     // take care of en passant captures
-    if is_pawn(piece) {
-        let en_passant = pawn_moves_en_passant(square_cords.0, square_cords.1, &board);
-        if let Some(mov) = en_passant {
-            let mut new_board = board.clone();
-            new_board.swap_color();
-            new_board.pawn_double_move = None;
-            new_board.board[mov.0][mov.1] = piece;
-            new_board.board[square_cords.0][square_cords.1] = EMPTY;
-            if is_white(piece) {
-                new_board.board[mov.0 + 1][mov.1] = EMPTY;
-                new_board.black_total_piece_value -= PIECE_VALUES[PAWN as usize];
-            } else {
-                new_board.board[mov.0 - 1][mov.1] = EMPTY;
-                new_board.white_total_piece_value -= PIECE_VALUES[PAWN as usize];
-            }
-
-            // if you make a move, and you do not end up in check, then this move is valid
-            if !is_check(&new_board, board.to_move) {
-                new_moves.push(new_board);
-            }
+    if is_pawn(piece) && kinds != MoveKinds::Quiet {
+        if let Some(target) = pawn_moves_en_passant(square_cords.0, square_cords.1, board) {
+            moves.push(Move {
+                from,
+                to: Square::new(target.0, target.1).unwrap(),
+                promotion: None,
+                flags: MOVE_CAPTURE | MOVE_EN_PASSANT,
+            });
         }
     }
 }
 
+fn remove_castling_right(board: &mut BoardState, castling_type: CastlingType) {
+    match castling_type {
+        CastlingType::WhiteKingSide => board.white_king_side_castle = false,
+        CastlingType::WhiteQueenSide => board.white_queen_side_castle = false,
+        CastlingType::BlackKingSide => board.black_king_side_castle = false,
+        CastlingType::BlackQueenSide => board.black_queen_side_castle = false,
+    }
+}
+
 /*
     Given the current board, attempt to castle
     If castling is possible add the move the the list of possible moves
-    Will also update appropriate castling variables if castling was successful
 */
-fn generate_castling_moves(board: &BoardState, new_moves: &mut Vec<BoardState>) {
-    if board.to_move == PieceColor::White && can_castle(&board, CastlingType::WhiteKingSide) {
-        let mut new_board = board.clone();
-        new_board.swap_color();
-        new_board.pawn_double_move = None;
-        new_board.white_king_side_castle = false;
-        new_board.white_queen_side_castle = false;
-        new_board.white_king_location = (BOARD_END - 1, BOARD_END - 2);
-        new_board.board[BOARD_END - 1][BOARD_START + 4] = EMPTY;
-        new_board.board[BOARD_END - 1][BOARD_END - 1] = EMPTY;
-        new_board.board[BOARD_END - 1][BOARD_END - 2] = WHITE | KING;
-        new_board.board[BOARD_END - 1][BOARD_END - 3] = WHITE | ROOK;
-        new_board.last_move = Some(WHITE_KING_SIDE_CASTLE_ALG.to_string());
-        new_moves.push(new_board);
-    }
-
-    if board.to_move == PieceColor::White && can_castle(&board, CastlingType::WhiteQueenSide) {
-        let mut new_board = board.clone();
-        new_board.swap_color();
-        new_board.pawn_double_move = None;
-        new_board.white_king_side_castle = false;
-        new_board.white_queen_side_castle = false;
-        new_board.white_king_location = (BOARD_END - 1, BOARD_START + 2);
-        new_board.board[BOARD_END - 1][BOARD_START + 4] = EMPTY;
-        new_board.board[BOARD_END - 1][BOARD_START] = EMPTY;
-        new_board.board[BOARD_END - 1][BOARD_START + 2] = WHITE | KING;
-        new_board.board[BOARD_END - 1][BOARD_START + 3] = WHITE | ROOK;
-        new_board.last_move = Some(WHITE_QUEEN_SIDE_CASTLE_ALG.to_string());
-        new_moves.push(new_board);
-    }
-
-    if board.to_move == PieceColor::Black && can_castle(&board, CastlingType::BlackKingSide) {
-        let mut new_board = board.clone();
-        new_board.swap_color();
-        new_board.pawn_double_move = None;
-        new_board.black_king_side_castle = false;
-        new_board.black_queen_side_castle = false;
-        new_board.black_king_location = (BOARD_START, BOARD_END - 2);
-        new_board.board[BOARD_START][BOARD_START + 4] = EMPTY;
-        new_board.board[BOARD_START][BOARD_END - 1] = EMPTY;
-        new_board.board[BOARD_START][BOARD_END - 2] = BLACK | KING;
-        new_board.board[BOARD_START][BOARD_END - 3] = BLACK | ROOK;
-        new_board.last_move = Some(BLACK_KING_SIDE_CASTLE_ALG.to_string());
-        new_moves.push(new_board);
-    }
-
-    if board.to_move == PieceColor::Black && can_castle(&board, CastlingType::BlackQueenSide) {
-        let mut new_board = board.clone();
-        new_board.swap_color();
-        new_board.pawn_double_move = None;
-        new_board.black_king_side_castle = false;
-        new_board.black_queen_side_castle = false;
-        new_board.black_king_location = (BOARD_START, BOARD_START + 2);
-        new_board.board[BOARD_START][BOARD_START + 4] = EMPTY;
-        new_board.board[BOARD_START][BOARD_START] = EMPTY;
-        new_board.board[BOARD_START][BOARD_START + 2] = BLACK | KING;
-        new_board.board[BOARD_START][BOARD_START + 3] = BLACK | ROOK;
-        new_board.last_move = Some(BLACK_QUEEN_SIDE_CASTLE_ALG.to_string());
-        new_moves.push(new_board);
+fn generate_castling_moves(board: &BoardState, moves: &mut Vec<Move>) {
+    for castling_type in &CASTLING_TYPES {
+        let color = castling_type.color();
+        if color != board.to_move || !can_castle(board, *castling_type) {
+            continue;
+        }
+        let (row, king_col, rook_col, _, _) = castling_squares(board, *castling_type);
+        moves.push(Move {
+            from: Square::new(row, king_col).unwrap(),
+            to: Square::new(row, rook_col).unwrap(),
+            promotion: None,
+            flags: MOVE_CASTLE,
+        });
     }
 }
 
 /*
-    Executes a pawn promotion on the given cords
+    Adds a move of a pawn onto the last rank once for each piece it can promote to
 
-    This function assumes that the board state is a valid pawn promotion and does not do additional checks
+    This function assumes that the move is a valid pawn promotion and does not do additional checks
 */
-fn promote_pawn(
-    board: &BoardState,
-    color: PieceColor,
-    start: Point,
-    target: Point,
-    moves: &mut Vec<BoardState>,
-) {
-    let pawn_value = PIECE_VALUES[PAWN as usize];
-    for piece in &[(QUEEN, 'q'), (KNIGHT, 'n'), (BISHOP, 'b'), (ROOK, 'r')] {
-        let mut new_board = board.clone();
-        new_board.pawn_double_move = None;
-        new_board.board[target.0][target.1] = color.as_mask() | piece.0;
-        let value = PIECE_VALUES[piece.0 as usize] - pawn_value;
-        if color == PieceColor::Black {
-            new_board.black_total_piece_value += value;
-        } else {
-            new_board.white_total_piece_value += value;
-        }
-        let move_alg = format!("{}{}{}", board_position_to_algebraic_pair(start), 
-            board_position_to_algebraic_pair(target), piece.1);
-        new_board.last_move = Some(move_alg.to_string());
-        moves.push(new_board);
+fn promote_pawn(mov: Move, moves: &mut Vec<Move>) {
+    for kind in &[
+        PieceKind::Queen,
+        PieceKind::Knight,
+        PieceKind::Bishop,
+        PieceKind::Rook,
+    ] {
+        moves.push(Move {
+            promotion: Some(*kind),
+            ..mov
+        });
     }
 }
 
@@ -692,6 +1590,544 @@ fn promote_pawn(
 mod tests {
     use super::*;
 
+    #[test]
+    fn move_clocks_updated() {
+        let b = board_from_fen("r3k3/4p3/8/8/8/8/8/R3K2R b KQq - 7 10").unwrap();
+        for new_board in generate_child_boards(&b) {
+            assert_eq!(new_board.full_move_clock, 11);
+            let last_move = new_board.last_move.unwrap();
+            if last_move.starts_with("e7") || last_move == "a8a1" {
+                assert_eq!(new_board.half_move_clock, 0);
+            } else {
+                assert_eq!(new_board.half_move_clock, 8);
+            }
+        }
+
+        let b = board_from_fen("4k3/8/8/8/8/8/8/R3K2R w KQ - 7 10").unwrap();
+        for new_board in generate_child_boards(&b) {
+            assert_eq!(new_board.full_move_clock, 10);
+            assert_eq!(new_board.half_move_clock, 8);
+        }
+
+        // long games go past 255 moves, and the clocks stop at their limit rather than wrapping
+        let b = board_from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 300 400").unwrap();
+        let new_board = &generate_child_boards(&b)[0];
+        assert_eq!(new_board.full_move_clock, 401);
+        assert_eq!(new_board.half_move_clock, 301);
+        let b = board_from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 65535 65535").unwrap();
+        let new_board = &generate_child_boards(&b)[0];
+        assert_eq!(new_board.full_move_clock, 65535);
+        assert_eq!(new_board.half_move_clock, 65535);
+    }
+
+    #[test]
+    fn incremental_state_follows_moves() {
+        // castling both ways, en passant and promotions with and without a capture
+        for fen in &[
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/8/8/8/3pP3/8/8/R3K2R b KQkq e3 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ] {
+            let b = board_from_fen(fen).unwrap();
+            for new_board in generate_child_boards(&b) {
+                let rebuilt = board_from_fen(&new_board.to_fen()).unwrap();
+                let incremental = |b: &BoardState| {
+                    (
+                        b.piece_lists,
+                        b.white_total_piece_value,
+                        b.black_total_piece_value,
+                        b.piece_square_score,
+                    )
+                };
+                assert_eq!(
+                    incremental(&new_board),
+                    incremental(&rebuilt),
+                    "{:?}",
+                    new_board.last_move
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pinned_pieces_found() {
+        // the knight is pinned by the rook, the bishop by the queen and the pawn by the bishop,
+        // the queen on g1 has nothing behind it
+        let b = board_from_fen("4k3/8/8/8/1q5b/8/3B1P2/1r1NK1Q1 w - - 0 1").unwrap();
+        let pinned: Vec<Point> = pinned_pieces(&b).iter().collect();
+        let expected: Vec<Point> = ["d2", "f2", "d1"]
+            .iter()
+            .map(|s| algebraic_pairs_to_board_position(s).unwrap())
+            .collect();
+        assert_eq!(pinned, expected);
+        assert!(discovered_checkers(&b).is_empty());
+
+        // two pieces in the way means neither is pinned
+        let b = board_from_fen("4k3/8/8/8/1q6/2P5/3B4/4K3 w - - 0 1").unwrap();
+        assert!(pinned_pieces(&b).is_empty());
+    }
+
+    #[test]
+    fn discovered_checkers_found() {
+        // moving the pawn uncovers the bishop and moving the knight uncovers the rook
+        let b = board_from_fen("7k/8/8/8/3P4/2B5/7N/4K2R w - - 0 1").unwrap();
+        let checkers: Vec<Point> = discovered_checkers(&b).iter().collect();
+        let expected: Vec<Point> = ["d4", "h2"]
+            .iter()
+            .map(|s| algebraic_pairs_to_board_position(s).unwrap())
+            .collect();
+        assert_eq!(checkers, expected);
+        assert!(pinned_pieces(&b).is_empty());
+
+        // a black pawn already blocks the bishop
+        let b = board_from_fen("7k/8/5p2/8/3P4/2B5/8/4K3 w - - 0 1").unwrap();
+        assert!(discovered_checkers(&b).is_empty());
+    }
+
+    #[test]
+    fn move_between_positions() {
+        let before = "r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1";
+        for (after, expected) in &[
+            ("r3k2r/1P6/8/3pP3/8/8/8/R4RK1 b kq - 1 1", "e1g1"),
+            ("r3k2r/1P6/8/3pP3/8/8/8/2KR3R b kq - 1 1", "e1c1"),
+            ("r3k2r/1P6/3P4/8/8/8/8/R3K2R b KQkq - 0 1", "e5d6"),
+            ("N3k2r/8/8/3pP3/8/8/8/R3K2R b KQk - 0 1", "b7a8n"),
+            ("Q3k2r/8/8/3pP3/8/8/8/R3K2R b KQk - 0 1", "b7a8q"),
+            ("r3k2r/1P6/8/3pP3/8/8/7R/R3K3 b Qkq - 1 1", "h1h2"),
+        ] {
+            assert_eq!(
+                move_between(
+                    &board_from_fen(before).unwrap(),
+                    &board_from_fen(after).unwrap()
+                ),
+                Some(expected.to_string())
+            );
+        }
+
+        // two moves apart
+        let after = board_from_fen("r3k2r/1P6/8/3pP3/8/8/8/R4RK1 w kq - 1 2").unwrap();
+        assert_eq!(move_between(&board_from_fen(before).unwrap(), &after), None);
+    }
+
+    #[test]
+    fn moves_before_making_them() {
+        let b = board_from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let moves = generate_moves(&b);
+        let boards = generate_child_boards(&b);
+        assert_eq!(moves.len(), boards.len());
+        for (mov, board) in moves.iter().zip(boards.iter()) {
+            assert_eq!(board.last_move, Some(mov.to_string()));
+        }
+
+        let find = |name: &str| *moves.iter().find(|m| m.to_string() == name).unwrap();
+        let castle = find("e1c1");
+        assert!(castle.is_castle());
+        assert_eq!(castle.to.to_string(), "a1");
+        let en_passant = find("e5d6");
+        assert_eq!(en_passant.flags, MOVE_CAPTURE | MOVE_EN_PASSANT);
+        assert_eq!(
+            make_move(&b, &en_passant).to_fen(),
+            "r3k2r/1P6/3P4/8/8/8/8/R3K2R b KQkq - 0 1"
+        );
+        let promotion = find("b7a8n");
+        assert!(promotion.is_capture());
+        assert_eq!(promotion.promotion, Some(PieceKind::Knight));
+        assert_eq!(find("a1a8").flags, MOVE_CAPTURE);
+        assert_eq!(find("h1h2").flags, 0);
+        assert_eq!(moves.iter().filter(|m| m.promotion.is_some()).count(), 8);
+
+        // Chess960 castling is written as the king taking its own rook
+        let b = board_from_fen("4k3/8/8/8/8/8/8/1R2K3 w B - 0 1").unwrap();
+        let castle = generate_moves(&b)
+            .into_iter()
+            .find(|m| m.is_castle())
+            .unwrap();
+        assert_eq!(castle.to_string(), "e1b1");
+        let b = board_from_fen("4k3/8/8/8/8/8/8/1RK5 w B - 0 1").unwrap();
+        assert_eq!(
+            generate_moves(&b)
+                .into_iter()
+                .find(|m| m.is_castle())
+                .map(|m| m.to_string()),
+            Some("c1b1".to_string())
+        );
+    }
+
+    #[test]
+    fn legality_of_pseudo_legal_moves() {
+        // the rook on e4 and the bishop on c3 are pinned, and can only move along their pins
+        let b = board_from_fen("4r3/7k/8/q7/4R3/2B5/8/4K3 w - - 0 1").unwrap();
+        let legality = LegalityInfo::new(&b);
+        let legal = |name: &str| {
+            let mov = generate_pseudo_legal_moves(&b)
+                .into_iter()
+                .find(|m| m.to_string() == name)
+                .unwrap();
+            is_legal(&b, &mov, &legality)
+        };
+        assert!(legal("c3b4"));
+        assert!(!legal("c3d4"));
+        assert!(legal("e4e8"));
+        assert!(!legal("e4d4"));
+        assert!(legal("e1d1"));
+
+        let b = board_from_fen("4r3/7k/8/8/4R3/2B5/8/4K2q w - - 0 1").unwrap();
+        let legality = LegalityInfo::new(&b);
+        let legal: Vec<String> = generate_pseudo_legal_moves(&b)
+            .iter()
+            .filter(|m| is_legal(&b, m, &legality))
+            .map(|m| m.to_string())
+            .collect();
+        // the king can not step back along the line the queen checks it on
+        assert!(!legal.contains(&"e1d1".to_string()));
+        assert!(legal.contains(&"e1d2".to_string()));
+        assert!(!legal.contains(&"e4e5".to_string()));
+        assert_eq!(
+            legal,
+            generate_moves(&b)
+                .iter()
+                .map(|m| m.to_string())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn uci_moves() {
+        let b = board_from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        for uci in &[
+            "e1g1", "e1c1", "e5d6", "b7a8q", "b7a8r", "b7a8b", "b7a8n", "h1h8",
+        ] {
+            assert_eq!(Move::from_uci(&b, uci).unwrap().to_uci(), *uci);
+        }
+        // castling as the king taking its own rook, as Chess960 GUIs send it
+        let castle = Move::from_uci(&b, "e1h1").unwrap();
+        assert!(castle.is_castle());
+        assert_eq!(castle.to_uci(), "e1g1");
+
+        assert_eq!(
+            Move::from_uci(&b, "b7a8"),
+            Err("Could not parse move: Illegal move")
+        );
+        assert_eq!(
+            Move::from_uci(&b, "b7a8k"),
+            Err("Could not parse move: Illegal move")
+        );
+        assert_eq!(
+            Move::from_uci(&b, "b7a8Q"),
+            Err("Could not parse move: Invalid promotion")
+        );
+        assert_eq!(
+            Move::from_uci(&b, "e2e9"),
+            Err("Could not parse move: Invalid square")
+        );
+        assert_eq!(
+            Move::from_uci(&b, "e2"),
+            Err("Could not parse move: Invalid length")
+        );
+        assert!(Move::from_uci(&b, "e1e2").is_ok());
+        assert!(Move::from_uci(&b, "e1f1").is_ok());
+        assert!(Move::from_uci(&b, "e1d1").is_ok());
+
+        let b = board_from_fen("4k3/8/8/8/8/8/8/1RK5 w B - 0 1").unwrap();
+        assert_eq!(Move::from_uci(&b, "c1b1").unwrap().to_uci(), "c1b1");
+    }
+
+    #[test]
+    fn captures_only() {
+        let b = board_from_fen("r3k2r/1P6/8/3pP3/8/8/6p1/R3K2R w KQkq d6 0 1").unwrap();
+        let captures: Vec<String> = generate_captures(&b).iter().map(|m| m.to_uci()).collect();
+        let expected: Vec<String> = generate_moves(&b)
+            .iter()
+            .filter(|m| m.is_capture() || m.promotion.is_some())
+            .map(|m| m.to_uci())
+            .collect();
+        assert_eq!(captures, expected);
+        assert!(captures.contains(&"e5d6".to_string()));
+        assert!(captures.contains(&"b7b8n".to_string()));
+        assert!(captures.contains(&"a1a8".to_string()));
+        assert_eq!(captures.len(), 11);
+
+        // a pinned piece can not capture off its line
+        let b = board_from_fen("4k3/4r3/3p4/4B3/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(generate_captures(&b).is_empty());
+    }
+
+    #[test]
+    fn quiet_checks_only() {
+        let quiet_checks = |fen: &str| {
+            let b = board_from_fen(fen).unwrap();
+            let checks: Vec<String> = generate_quiet_checks(&b)
+                .iter()
+                .map(|m| m.to_uci())
+                .collect();
+            let expected: Vec<String> = generate_moves(&b)
+                .into_iter()
+                .filter(|m| !m.is_noisy() && in_check(&make_move(&b, m)))
+                .map(|m| m.to_uci())
+                .collect();
+            assert_eq!(checks, expected);
+            checks
+        };
+        // the rook checks along the rank and the file, and every knight move uncovers the bishop
+        let checks = quiet_checks("6k1/8/8/3N4/8/8/B7/4KR2 w - - 0 1");
+        assert!(checks.contains(&"f1f8".to_string()));
+        assert!(checks.contains(&"f1g1".to_string()));
+        assert!(checks.contains(&"d5e7".to_string()));
+        assert!(checks.contains(&"d5b4".to_string()));
+        assert!(!checks.contains(&"a2b1".to_string()));
+        assert_eq!(checks.len(), 10);
+
+        // castling which checks with the rook, a pawn push check, and none when pinned
+        assert_eq!(
+            quiet_checks("5k2/8/8/8/8/8/8/4K2R w K - 0 1"),
+            ["h1h8", "h1f1", "e1g1"]
+        );
+        assert_eq!(quiet_checks("8/8/3k4/8/4P3/8/8/4K3 w - - 0 1"), ["e4e5"]);
+        assert!(quiet_checks("4k3/8/8/8/8/8/4N3/r3K3 w - - 0 1").is_empty());
+        assert!(quiet_checks(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+        )
+        .iter()
+        .all(|m| m != "d5e6"));
+    }
+
+    #[test]
+    fn check_evasions() {
+        let evasions = |fen: &str| {
+            let b = board_from_fen(fen).unwrap();
+            let legality = LegalityInfo::new(&b);
+            assert!(legality.in_check());
+            let mut moves: Vec<String> = generate_evasions(&b, &legality)
+                .iter()
+                .map(|m| m.to_uci())
+                .collect();
+            moves.sort();
+            moves
+        };
+
+        // the rook can be captured by the knight or the bishop, or blocked by the knight
+        assert_eq!(
+            evasions("4k3/8/8/8/4r3/2N5/6B1/4K3 w - - 0 1"),
+            ["c3e2", "c3e4", "e1d1", "e1d2", "e1e2", "e1f1", "e1f2", "g2e4"]
+        );
+        // in double check only the king can move
+        assert_eq!(
+            evasions("4k3/8/8/8/4r3/2N2n2/8/4K3 w - - 0 1"),
+            ["e1d1", "e1d2", "e1e2", "e1f1", "e1f2"]
+        );
+        // the checking pawn can be taken en passant
+        assert_eq!(
+            evasions("8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1"),
+            ["c5b4", "c5b5", "c5b6", "c5c4", "c5c6", "c5d4", "c5d5", "c5d6", "e4d3"]
+        );
+
+        let b = board_from_fen("4k3/8/8/8/4r3/2N2n2/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(checkers(&b).len(), 2);
+        assert_eq!(
+            generate_moves(&b)
+                .iter()
+                .map(|m| m.to_uci())
+                .collect::<Vec<_>>(),
+            ["e1f2", "e1d1", "e1f1"]
+        );
+    }
+
+    #[test]
+    fn game_status() {
+        let status = |fen: &str| board_from_fen(fen).unwrap().status();
+        assert_eq!(status(DEFAULT_FEN_STRING), GameStatus::Ongoing);
+        assert_eq!(
+            status("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1"),
+            GameStatus::Checkmate(PieceColor::White)
+        );
+        assert_eq!(
+            status("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"),
+            GameStatus::Checkmate(PieceColor::Black)
+        );
+        assert_eq!(
+            status("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"),
+            GameStatus::Stalemate
+        );
+        assert_eq!(
+            status("4k3/8/8/8/8/8/8/R3K3 w - - 100 80"),
+            GameStatus::DrawByFiftyMove
+        );
+        // checkmate takes precedence over the fifty-move rule
+        assert_eq!(
+            status("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80"),
+            GameStatus::Checkmate(PieceColor::White)
+        );
+
+        for fen in &[
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2N1K3 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "2b1k3/8/8/8/8/8/8/3BK3 b - - 0 1",
+        ] {
+            assert_eq!(
+                status(fen),
+                GameStatus::DrawByInsufficientMaterial,
+                "{}",
+                fen
+            );
+        }
+        for fen in &[
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1",
+            "2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2BNK3 w - - 0 1",
+        ] {
+            assert_eq!(status(fen), GameStatus::Ongoing, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn single_move_legality() {
+        let mov = |from: &str, to: &str, flags: u8| Move {
+            from: Square::from_algebraic(from).unwrap(),
+            to: Square::from_algebraic(to).unwrap(),
+            promotion: None,
+            flags,
+        };
+
+        // castling through the bishop's attack on f1, and queen side where nothing is attacked
+        let b = board_from_fen("4k3/8/8/8/8/8/6b1/R3K2R w KQ - 0 1").unwrap();
+        assert!(!is_move_legal(&b, mov("e1", "h1", MOVE_CASTLE)));
+        assert!(is_move_legal(&b, mov("e1", "a1", MOVE_CASTLE)));
+        // not a castle without the flag, and not a move of the side to move
+        assert!(!is_move_legal(&b, mov("e1", "a1", 0)));
+        assert!(!is_move_legal(&b, mov("g2", "f1", 0)));
+        assert!(is_move_legal(&b, mov("h1", "h8", 0)));
+        assert!(!is_move_legal(&b, mov("h1", "h8", MOVE_CAPTURE)));
+
+        // the pawn taking en passant would leave the king in check along the rank
+        let b = board_from_fen("8/8/8/K2pP2r/8/8/8/7k w - d6 0 1").unwrap();
+        assert!(!is_move_legal(
+            &b,
+            mov("e5", "d6", MOVE_CAPTURE | MOVE_EN_PASSANT)
+        ));
+        assert!(is_move_legal(&b, mov("e5", "e6", 0)));
+        assert!(Move::from_uci(&b, "e5d6").is_err());
+
+        for fen in &[
+            DEFAULT_FEN_STRING,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        ] {
+            let b = board_from_fen(fen).unwrap();
+            for m in generate_pseudo_legal_moves(&b) {
+                assert_eq!(
+                    is_move_legal(&b, m),
+                    generate_moves(&b).contains(&m),
+                    "{}",
+                    m
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn destinations_of_a_piece() {
+        let destinations = |fen: &str, square: &str| {
+            let b = board_from_fen(fen).unwrap();
+            let mut squares: Vec<String> =
+                legal_destinations(&b, Square::from_algebraic(square).unwrap())
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect();
+            squares.sort();
+            squares
+        };
+        assert_eq!(destinations(DEFAULT_FEN_STRING, "g1"), ["f3", "h3"]);
+        assert_eq!(destinations(DEFAULT_FEN_STRING, "e2"), ["e3", "e4"]);
+        assert!(destinations(DEFAULT_FEN_STRING, "e4").is_empty());
+        assert!(destinations(DEFAULT_FEN_STRING, "e7").is_empty());
+
+        // the bishop is pinned along the diagonal, and the pawn has four promotions to each square
+        let fen = "1r2k3/P7/8/8/8/5b2/6B1/7K w - - 0 1";
+        assert_eq!(destinations(fen, "g2"), ["f3"]);
+        assert_eq!(destinations(fen, "a7"), ["a8", "b8"]);
+
+        // castling is given as the square the king finishes on
+        let fen = "4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1";
+        assert_eq!(
+            destinations(fen, "e1"),
+            ["c1", "d1", "d2", "e2", "f1", "f2", "g1"]
+        );
+        // unless the king is already there, when it is the rook's square
+        let fen = "4k3/8/8/8/8/8/8/6KR w H - 0 1";
+        assert_eq!(destinations(fen, "g1"), ["f1", "f2", "g2", "h1", "h2"]);
+    }
+
+    #[test]
+    fn mobility_counts() {
+        for fen in &[
+            DEFAULT_FEN_STRING,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ] {
+            let b = board_from_fen(fen).unwrap();
+            for color in &[PieceColor::White, PieceColor::Black] {
+                let mut total = 0;
+                for piece in PAWN..=KING {
+                    for square in b.piece_list(*color, piece).iter() {
+                        let mut targets = vec![];
+                        get_moves(square.0, square.1, &b, &mut targets);
+                        assert_eq!(mobility(&b, square) as usize, targets.len(), "{:?}", square);
+                        total += targets.len() as u32;
+                    }
+                }
+                assert_eq!(total_mobility(&b, *color), total);
+            }
+        }
+
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        assert_eq!(total_mobility(&b, PieceColor::White), 20);
+        assert_eq!(mobility(&b, (5, 5)), 0);
+    }
+
+    #[test]
+    fn checking_moves() {
+        let gives = |fen: &str, uci: &str| {
+            let b = board_from_fen(fen).unwrap();
+            gives_check(&b, Move::from_uci(&b, uci).unwrap())
+        };
+        // the bishop uncovers the rook, and on b5 checks the king itself as well
+        let fen = "4k3/8/8/8/8/8/4B3/4R1K1 w - - 0 1";
+        assert!(gives(fen, "e2d3"));
+        assert!(gives(fen, "e2b5"));
+        assert!(!gives(fen, "g1f1"));
+        assert!(!gives("4k3/8/8/8/4P3/8/8/4R1K1 w - - 0 1", "e4e5"));
+
+        assert!(gives("4k3/8/3P4/8/8/8/8/4K3 w - - 0 1", "d6d7"));
+        assert!(gives("4k3/8/8/8/4N3/8/8/4K3 w - - 0 1", "e4d6"));
+        assert!(!gives("4k3/8/8/8/4N3/8/8/4K3 w - - 0 1", "e4c5"));
+
+        let fen = "k7/4P3/8/8/8/8/8/4K3 w - - 0 1";
+        assert!(gives(fen, "e7e8q"));
+        assert!(gives(fen, "e7e8r"));
+        assert!(!gives(fen, "e7e8b"));
+        assert!(!gives(fen, "e7e8n"));
+
+        // the castled rook checks, and en passant takes two pawns off the rook's rank
+        assert!(gives("5k2/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1"));
+        assert!(gives("8/8/8/k2pP2R/8/8/8/4K3 w - d6 0 1", "e5d6"));
+
+        for fen in &[
+            DEFAULT_FEN_STRING,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        ] {
+            let b = board_from_fen(fen).unwrap();
+            for m in generate_moves(&b) {
+                assert_eq!(gives_check(&b, m), in_check(&make_move(&b, &m)), "{}", m);
+            }
+        }
+    }
+
     #[test]
     fn check_sanity_test() {
         let b = board_from_fen("8/8/8/8/3K4/8/8/8 w - - 0 1").unwrap();
@@ -1263,15 +2699,144 @@ mod tests {
             return;
         }
 
-        let moves = generate_moves(board);
+        let moves = generate_child_boards(board);
         move_counts[cur_depth] += moves.len() as u32;
         for mov in moves {
             generate_moves_test(&mov, cur_depth + 1, depth, move_counts);
         }
     }
 
+    #[test]
+    fn chess960_castling() {
+        // the king stays on c1 and the rook from b1 goes to d1, written as the king taking the rook
+        let b = board_from_fen("4k3/8/8/8/8/8/8/1RK5 w B - 0 1").unwrap();
+        let castled = generate_child_boards(&b)
+            .into_iter()
+            .find(|m| m.last_move.as_deref() == Some("c1b1"))
+            .unwrap();
+        assert_eq!(castled.to_fen(), "4k3/8/8/8/8/8/8/2KR4 b - - 1 1");
+        assert_eq!(castled.white_king_location.to_string(), "c1");
+
+        // the rook on b1 is blocking the queen's attack on the king
+        let b = board_from_fen("4k3/8/8/8/8/8/8/qRK5 w B - 0 1").unwrap();
+        assert!(can_castle(&b, CastlingType::WhiteQueenSide));
+        assert!(!generate_child_boards(&b)
+            .iter()
+            .any(|m| m.last_move.as_deref() == Some("c1b1")));
+
+        // moving the castling rook, or capturing it, takes away the right
+        let b = board_from_fen("1r2k3/8/8/8/8/8/8/1R2K3 w Bb - 0 1").unwrap();
+        let captured = generate_child_boards(&b)
+            .into_iter()
+            .find(|m| m.last_move.as_deref() == Some("b1b8"))
+            .unwrap();
+        assert_eq!(captured.castling_rights(), [false; 4]);
+    }
+
     // Perft tests - move generation. Table of values taken from https://www.chessprogramming.org/Perft_Results
 
+    #[test]
+    fn perft_node_counts() {
+        for (fen, depth, nodes) in &[
+            (DEFAULT_FEN_STRING, 0, 1),
+            (DEFAULT_FEN_STRING, 1, 20),
+            (DEFAULT_FEN_STRING, 4, 197281),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                3,
+                97862,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4, 43238),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                3,
+                9467,
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                3,
+                62379,
+            ),
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                3,
+                12189,
+            ),
+        ] {
+            assert_eq!(
+                perft(&board_from_fen(fen).unwrap(), *depth),
+                *nodes,
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn perft_divide_by_move() {
+        let b =
+            board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let divide = perft_divide(&b, 2);
+        assert_eq!(divide.len(), 48);
+        assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2039);
+        let nodes = |name: &str| {
+            divide
+                .iter()
+                .find(|(mov, _)| mov.to_string() == name)
+                .map(|(_, nodes)| *nodes)
+        };
+        // counts from stockfish's go perft
+        assert_eq!(nodes("e1g1"), Some(43));
+        assert_eq!(nodes("d5e6"), Some(46));
+        assert_eq!(nodes("e5f7"), Some(44));
+        assert_eq!(nodes("e1c1"), Some(43));
+        assert_eq!(nodes("a2a4"), Some(44));
+
+        assert!(perft_divide(&b, 0).is_empty());
+    }
+
+    #[test]
+    fn perft_divide_across_threads() {
+        let b =
+            board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let divide = perft_divide(&b, 3);
+        assert_eq!(divide.iter().map(|(_, nodes)| nodes).sum::<u64>(), 97862);
+        for threads in &[0, 1, 4, 100] {
+            assert_eq!(perft_divide_parallel(&b, 3, *threads, 0), divide);
+            assert_eq!(perft_divide_parallel(&b, 3, *threads, 1), divide);
+        }
+        assert!(perft_divide_parallel(&b, 0, 4, 0).is_empty());
+
+        // no moves to share out from checkmate
+        let b = board_from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        assert!(perft_divide_parallel(&b, 2, 4, 0).is_empty());
+    }
+
+    #[test]
+    fn perft_with_table() {
+        for (fen, depth, nodes) in &[
+            (DEFAULT_FEN_STRING, 5, 4865609),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                4,
+                4085603,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 5, 674624),
+        ] {
+            let b = board_from_fen(fen).unwrap();
+            assert_eq!(perft_hashed(&b, *depth, &mut PerftTable::new(1)), *nodes);
+        }
+
+        // a table with a single slot still gives the right counts, it is just no help
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let mut table = PerftTable::new(0);
+        assert_eq!(table.entries.len(), 1);
+        assert_eq!(perft_hashed(&b, 4, &mut table), 197281);
+        assert_eq!(perft_hashed(&b, 4, &mut table), 197281);
+    }
+
     #[test]
     fn perft_test_position_1() {
         let mut moves_states = [0; 5];
@@ -1358,4 +2923,20 @@ mod tests {
         assert_eq!(moves_states[2], 89890);
         assert_eq!(moves_states[3], 3894594);
     }
+
+    #[test]
+    fn perft_chess960() {
+        // Chess960 positions taken from https://www.chessprogramming.org/Chess960_Perft_Results
+        let mut moves_states = [0; 4];
+        let b = board_from_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9")
+            .unwrap();
+        generate_moves_test(&b, 0, 4, &mut moves_states);
+        assert_eq!(moves_states, [21, 528, 12189, 326672]);
+
+        let mut moves_states = [0; 4];
+        let b = board_from_fen("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9")
+            .unwrap();
+        generate_moves_test(&b, 0, 4, &mut moves_states);
+        assert_eq!(moves_states, [21, 807, 18002, 667366]);
+    }
 }
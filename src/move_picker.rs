@@ -0,0 +1,298 @@
+pub use crate::engine::*;
+
+// History scores are halved once one of them passes this, so recent cutoffs count for more
+const HISTORY_MAX: i32 = 1 << 20;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Stage {
+    HashMove,
+    Noisy,
+    Killers,
+    Quiet,
+    Done,
+}
+
+/*
+    How often each quiet move (by color, starting square and target square) has caused a cutoff,
+    weighted by the depth left so cutoffs near the root count for more
+*/
+#[derive(Clone)]
+pub struct History {
+    scores: Vec<i32>,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History {
+            scores: vec![0; 2 * 64 * 64],
+        }
+    }
+}
+
+impl History {
+    fn index(color: PieceColor, mov: Move) -> usize {
+        let square = |s: Square| s.rank() * 8 + s.file();
+        (color as usize * 64 + square(mov.from)) * 64 + square(mov.to)
+    }
+
+    pub fn score(&self, color: PieceColor, mov: Move) -> i32 {
+        self.scores[History::index(color, mov)]
+    }
+
+    pub fn add_cutoff(&mut self, color: PieceColor, mov: Move, depth: u8) {
+        let index = History::index(color, mov);
+        self.scores[index] += depth as i32 * depth as i32;
+        if self.scores[index] > HISTORY_MAX {
+            for score in self.scores.iter_mut() {
+                *score /= 2;
+            }
+        }
+    }
+}
+
+// A move waiting to be handed out, the one with the highest score goes first
+#[derive(Clone, Copy)]
+struct ScoredMove {
+    mov: Move,
+    score: i32,
+}
+
+/*
+    Hands out the legal moves of a position one at a time, the likeliest best moves first, and only
+    generates each group of moves once the ones before it are used up, so a search which cuts off
+    early never pays for generating the quiet moves
+
+    The moves come in the order
+    1. The hash move, the best move found for the position by an earlier search
+    2. Captures and promotions, by most valuable victim then least valuable attacker
+    3. The killer moves, quiet moves which caused a cutoff in another position at the same ply
+    4. The remaining quiet moves, those with the best history first
+
+    Each stage is scored when it is generated, and the best move left is found when it is needed
+    (a selection sort which stops early), so the moves after a cutoff are never sorted
+*/
+pub struct MovePicker<'a> {
+    board: &'a BoardState,
+    legality: LegalityInfo,
+    stage: Stage,
+    hash_move: Option<Move>,
+    killers: [Option<Move>; 2],
+    // the pseudo-legal moves of the current stage not handed out yet
+    pending: Vec<ScoredMove>,
+}
+
+impl<'a> MovePicker<'a> {
+    pub fn new(
+        board: &'a BoardState,
+        hash_move: Option<Move>,
+        killers: [Option<Move>; 2],
+    ) -> MovePicker<'a> {
+        MovePicker {
+            board,
+            legality: LegalityInfo::new(board),
+            stage: Stage::HashMove,
+            hash_move,
+            killers,
+            pending: Vec::new(),
+        }
+    }
+
+    /*
+        Whether a move has already been handed out by an earlier stage
+        The hash move and killers come from other positions, so they are only used once they are
+        known to be pseudo-legal here
+    */
+    fn already_picked(&self, mov: &Move) -> bool {
+        Some(*mov) == self.hash_move || (!mov.is_noisy() && self.killers.contains(&Some(*mov)))
+    }
+
+    /*
+        Fill the pending moves with those of the next stage
+    */
+    fn next_stage(&mut self, history: &History) {
+        let board = self.board;
+        match self.stage {
+            Stage::HashMove => {
+                self.hash_move = self.hash_move.filter(|m| is_pseudo_legal(board, m));
+                self.pending
+                    .extend(self.hash_move.map(|mov| ScoredMove { mov, score: 0 }));
+                self.stage = Stage::Noisy;
+            }
+            Stage::Noisy => {
+                self.pending = generate_pseudo_legal_moves_of_kind(board, MoveKinds::Noisy)
+                    .into_iter()
+                    .filter(|m| Some(*m) != self.hash_move)
+                    .map(|mov| ScoredMove {
+                        mov,
+                        score: mvv_lva(board, &mov),
+                    })
+                    .collect();
+                self.stage = Stage::Killers;
+            }
+            Stage::Killers => {
+                let hash_move = self.hash_move;
+                self.killers = self.killers.map(|killer| {
+                    killer.filter(|m| {
+                        !m.is_noisy() && Some(*m) != hash_move && is_pseudo_legal(board, m)
+                    })
+                });
+                // the newer killer first
+                self.pending = (self.killers.iter().enumerate())
+                    .filter_map(|(i, killer)| {
+                        killer.map(|mov| ScoredMove {
+                            mov,
+                            score: -(i as i32),
+                        })
+                    })
+                    .collect();
+                self.stage = Stage::Quiet;
+            }
+            Stage::Quiet => {
+                self.pending = generate_pseudo_legal_moves_of_kind(board, MoveKinds::Quiet)
+                    .into_iter()
+                    .filter(|m| !self.already_picked(m))
+                    .map(|mov| ScoredMove {
+                        mov,
+                        score: history.score(board.to_move, mov),
+                    })
+                    .collect();
+                self.stage = Stage::Done;
+            }
+            Stage::Done => {}
+        }
+    }
+
+    /*
+        Take the pending move with the highest score, the first generated of those with the same
+        score
+    */
+    fn pop_best(&mut self) -> Option<Move> {
+        let mut best = 0;
+        for (i, pending) in self.pending.iter().enumerate() {
+            if pending.score > self.pending[best].score {
+                best = i;
+            }
+        }
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(self.pending.remove(best).mov)
+        }
+    }
+
+    /*
+        The next legal move, the quiet moves being ordered by the given history
+    */
+    pub fn next_move(&mut self, history: &History) -> Option<Move> {
+        loop {
+            match self.pop_best() {
+                Some(mov) if is_legal(self.board, &mov, &self.legality) => return Some(mov),
+                Some(_) => {}
+                None if self.stage == Stage::Done => return None,
+                None => self.next_stage(history),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_moves(mut picker: MovePicker, history: &History) -> Vec<Move> {
+        let mut moves = vec![];
+        while let Some(mov) = picker.next_move(history) {
+            moves.push(mov);
+        }
+        moves
+    }
+
+    fn picked(fen: &str, hash_move: Option<&str>, killers: [Option<&str>; 2]) -> Vec<String> {
+        let b = board_from_fen(fen).unwrap();
+        let find = |uci: Option<&str>| uci.map(|m| Move::from_uci(&b, m).unwrap());
+        let picker = MovePicker::new(&b, find(hash_move), killers.map(find));
+        all_moves(picker, &History::default())
+            .iter()
+            .map(|m| m.to_uci())
+            .collect()
+    }
+
+    #[test]
+    fn picks_moves_in_stages() {
+        let fen = "r3k2r/1P6/8/3pP3/2q5/8/8/R3K2R w KQkq d6 0 1";
+        let moves = picked(fen, Some("h1h7"), [Some("a1a2"), Some("e1d1")]);
+        let b = board_from_fen(fen).unwrap();
+        let mut expected: Vec<String> = generate_moves(&b).iter().map(|m| m.to_uci()).collect();
+        expected.sort();
+        let mut sorted = moves.clone();
+        sorted.sort();
+        assert_eq!(sorted, expected);
+
+        // the hash move, then the best captures and promotions, then the killers
+        assert_eq!(moves[0], "h1h7");
+        assert_eq!(moves[1], "b7a8q");
+        assert_eq!(moves[2], "b7a8r");
+        assert_eq!(moves[3], "b7b8q");
+        let killers = moves.iter().position(|m| m == "a1a2").unwrap();
+        assert_eq!(moves[killers + 1], "e1d1");
+        assert!(moves[..killers]
+            .iter()
+            .all(|m| m == "h1h7" || Move::from_uci(&b, m).unwrap().is_noisy()));
+        assert!(moves[killers + 2..]
+            .iter()
+            .all(|m| !Move::from_uci(&b, m).unwrap().is_noisy()));
+    }
+
+    #[test]
+    fn orders_by_victim_attacker_and_history() {
+        // the pawn and the knight can both take the rook, the knight and the queen the bishop
+        let b = board_from_fen("4k3/8/8/3r4/2P1b3/2N2Q2/8/4K3 w - - 0 1").unwrap();
+        let mut history = History::default();
+        let g1 = Move::from_uci(&b, "e1f1").unwrap();
+        history.add_cutoff(PieceColor::White, g1, 3);
+        history.add_cutoff(PieceColor::Black, Move::from_uci(&b, "e1e2").unwrap(), 5);
+        assert_eq!(history.score(PieceColor::White, g1), 9);
+
+        let moves: Vec<String> = all_moves(MovePicker::new(&b, None, [None, None]), &history)
+            .iter()
+            .map(|m| m.to_uci())
+            .collect();
+        assert_eq!(moves[..4], ["c4d5", "c3d5", "c3e4", "f3e4"]);
+        // only white's history counts for white's moves
+        assert_eq!(moves[4], "e1f1");
+    }
+
+    #[test]
+    fn history_is_aged() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let mut history = History::default();
+        let e4 = Move::from_uci(&b, "e2e4").unwrap();
+        let d4 = Move::from_uci(&b, "d2d4").unwrap();
+        history.add_cutoff(PieceColor::White, d4, 10);
+        while history.score(PieceColor::White, d4) == 100 {
+            history.add_cutoff(PieceColor::White, e4, 255);
+        }
+        assert!(history.score(PieceColor::White, e4) <= HISTORY_MAX);
+        assert_eq!(history.score(PieceColor::White, d4), 50);
+    }
+
+    #[test]
+    fn skips_moves_from_other_positions() {
+        // a hash move and killer for a piece which is not there, and a killer which is illegal
+        let b = board_from_fen("4k3/8/8/8/8/8/4r3/R3K3 w Q - 0 1").unwrap();
+        let mov = |from: &str, to: &str| Move {
+            from: Square::from_algebraic(from).unwrap(),
+            to: Square::from_algebraic(to).unwrap(),
+            promotion: None,
+            flags: 0,
+        };
+        let picker = MovePicker::new(
+            &b,
+            Some(mov("h1", "h2")),
+            [Some(mov("d2", "d3")), Some(mov("a1", "a2"))],
+        );
+        let moves = all_moves(picker, &History::default());
+        assert_eq!(moves.len(), generate_moves(&b).len());
+        assert_eq!(moves[0].to_uci(), "e1e2");
+    }
+}
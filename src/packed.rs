@@ -0,0 +1,173 @@
+#![allow(dead_code)]
+pub use crate::board::*;
+
+// Number of bytes in a packed position
+pub const PACKED_POSITION_SIZE: usize = 32;
+// Most pieces a packed position has room for, one per nibble of bytes 8 to 23
+const MAX_PACKED_PIECES: usize = 32;
+const NO_EN_PASSANT: u8 = 0xFF;
+
+/*
+    Pack a position into 32 bytes, for storing large numbers of positions (ie opening books and
+    tuning data)
+
+    Layout
+    0..8    bitboard of the occupied squares, bit 0 is a1 and bit 63 is h8, little endian
+    8..24   a nibble for each occupied square in bitboard order, 8 | piece for white, piece for black
+    24      bit 0 is set when black is to move, bits 1 to 4 are the castling rights in the order KQkq
+    25      file of the en passant square, 0xFF if there is none
+    26..28  half move clock, little endian
+    28..30  full move clock, little endian
+    30..32  the castling rook files, 3 bits each in the order KQkq, little endian
+
+    Returns None if the position has more than 32 pieces, which can not happen in a game
+*/
+pub fn pack_position(board: &BoardState) -> Option<[u8; PACKED_POSITION_SIZE]> {
+    let mut packed = [0; PACKED_POSITION_SIZE];
+    let mut occupied: u64 = 0;
+    let mut pieces = 0;
+    for index in 0..64 {
+        let square = Square::from_file_rank(index % 8, index / 8).unwrap();
+        let piece = match board.piece_at(square) {
+            Some(piece) => piece,
+            None => continue,
+        };
+        if pieces == MAX_PACKED_PIECES {
+            return None;
+        }
+        occupied |= 1 << index;
+        let nibble = match piece.color {
+            PieceColor::White => 8 | piece.kind.as_bits(),
+            PieceColor::Black => piece.kind.as_bits(),
+        };
+        packed[8 + pieces / 2] |= nibble << (4 * (pieces % 2));
+        pieces += 1;
+    }
+    packed[0..8].copy_from_slice(&occupied.to_le_bytes());
+
+    let mut flags = 0;
+    if board.to_move == PieceColor::Black {
+        flags |= 1;
+    }
+    for (i, allowed) in board.castling_rights().iter().enumerate() {
+        if *allowed {
+            flags |= 2 << i;
+        }
+    }
+    packed[24] = flags;
+    packed[25] = match board.pawn_double_move {
+        Some(square) => square.file() as u8,
+        None => NO_EN_PASSANT,
+    };
+    packed[26..28].copy_from_slice(&board.half_move_clock.to_le_bytes());
+    packed[28..30].copy_from_slice(&board.full_move_clock.to_le_bytes());
+
+    let mut rook_files: u16 = 0;
+    for (i, file) in board.castling_rook_files.iter().enumerate() {
+        rook_files |= (*file as u16) << (3 * i);
+    }
+    packed[30..32].copy_from_slice(&rook_files.to_le_bytes());
+    Some(packed)
+}
+
+/*
+    Unpack a position written by pack_position
+*/
+pub fn unpack_position(packed: &[u8; PACKED_POSITION_SIZE]) -> Result<BoardState, &'static str> {
+    let mut board = board_from_fen("8/8/8/8/8/8/8/8 w - - 0 1").unwrap();
+    let mut occupied_bytes = [0; 8];
+    occupied_bytes.copy_from_slice(&packed[0..8]);
+    let occupied = u64::from_le_bytes(occupied_bytes);
+    if occupied.count_ones() as usize > MAX_PACKED_PIECES {
+        return Err("Could not unpack position: Too many pieces");
+    }
+
+    let mut pieces = 0;
+    for index in 0..64 {
+        if occupied & (1 << index) == 0 {
+            continue;
+        }
+        let square = Square::from_file_rank(index % 8, index / 8).unwrap();
+        let nibble = (packed[8 + pieces / 2] >> (4 * (pieces % 2))) & 0xF;
+        pieces += 1;
+        let color = if nibble & 8 != 0 { WHITE } else { BLACK };
+        let piece = match Piece::from_u8(color | (nibble & PIECE_MASK)) {
+            Some(piece) => piece,
+            None => return Err("Could not unpack position: Invalid piece"),
+        };
+        board.set_square(square.point(), piece.as_u8());
+        match (piece.color, piece.kind) {
+            (PieceColor::White, PieceKind::King) => board.white_king_location = square,
+            (PieceColor::Black, PieceKind::King) => board.black_king_location = square,
+            _ => {}
+        }
+    }
+
+    let flags = packed[24];
+    if flags & 1 != 0 {
+        board.to_move = PieceColor::Black;
+    }
+    board.white_king_side_castle = flags & 2 != 0;
+    board.white_queen_side_castle = flags & 4 != 0;
+    board.black_king_side_castle = flags & 8 != 0;
+    board.black_queen_side_castle = flags & 16 != 0;
+
+    // the en passant square is behind the pawn of the side which just moved
+    board.pawn_double_move = match (packed[25], board.to_move) {
+        (NO_EN_PASSANT, _) => None,
+        (file, PieceColor::White) => Square::from_file_rank(file as usize, 5),
+        (file, PieceColor::Black) => Square::from_file_rank(file as usize, 2),
+    };
+    if packed[25] != NO_EN_PASSANT && board.pawn_double_move.is_none() {
+        return Err("Could not unpack position: Invalid en passant file");
+    }
+
+    board.half_move_clock = u16::from_le_bytes([packed[26], packed[27]]);
+    board.full_move_clock = u16::from_le_bytes([packed[28], packed[29]]);
+
+    let rook_files = u16::from_le_bytes([packed[30], packed[31]]);
+    for (i, file) in board.castling_rook_files.iter_mut().enumerate() {
+        *file = ((rook_files >> (3 * i)) & 7) as usize;
+    }
+    Ok(board)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_round_trip() {
+        for fen in &[
+            DEFAULT_FEN_STRING,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2",
+            "rnbqkbnr/pppp1ppp/8/8/3pP3/8/PPP2PPP/RNBQKBNR b KQkq e3 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 12 40",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        ] {
+            let b = board_from_fen(fen).unwrap();
+            let packed = pack_position(&b).unwrap();
+            assert_eq!(packed.len(), PACKED_POSITION_SIZE);
+            let unpacked = unpack_position(&packed).unwrap();
+            assert_eq!(unpacked, b);
+            assert_eq!(unpacked.to_fen(), b.to_fen());
+            assert_eq!(unpacked.castling_rook_files, b.castling_rook_files);
+            assert_eq!(unpacked.piece_lists, b.piece_lists);
+            assert_eq!(unpacked.piece_square_score, b.piece_square_score);
+            assert_eq!(unpacked.white_king_location, b.white_king_location);
+            assert_eq!(unpacked.black_king_location, b.black_king_location);
+        }
+    }
+
+    #[test]
+    fn pack_limits() {
+        // 33 pieces do not fit
+        let b = board_from_fen("QQQQkQQQ/QQQQQQQQ/QQQQQQQQ/QQQQQQQQ/Q7/8/8/4K3 w - - 0 1").unwrap();
+        assert!(pack_position(&b).is_none());
+
+        let mut packed = pack_position(&board_from_fen(DEFAULT_FEN_STRING).unwrap()).unwrap();
+        packed[8] = 0x07;
+        assert!(unpack_position(&packed).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+pub use crate::board::*;
+pub use crate::move_generation::*;
+
+/*
+    Parse a move in Standard Algebraic Notation (ie Nbd7, exd8=Q+ or O-O-O) into the legal move it
+    describes on the given board
+
+    The file, rank or square after the piece letter is only needed when more than one piece of that
+    kind can reach the target square, check and mate markers and annotations (ie !? or +) are
+    ignored, and castling can be written with zeros as well as the letter O
+*/
+pub fn parse_san(board: &BoardState, san: &str) -> Result<Move, &'static str> {
+    let san = san.trim_end_matches(['+', '#', '!', '?']);
+    let moves = generate_moves(board);
+
+    if let Some(king_side) = match san {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    } {
+        return moves
+            .into_iter()
+            .find(|m| m.is_castle() && (m.to.col() > m.from.col()) == king_side)
+            .ok_or("Could not parse move: Castling is not legal");
+    }
+
+    let (kind, rest) = match san.chars().next().and_then(san_piece_kind) {
+        Some(kind) => (kind, &san[1..]),
+        None => (PieceKind::Pawn, san),
+    };
+    // the promotion piece can be written with or without an equals sign, ie e8=Q or e8Q
+    let (rest, promotion) = match rest.chars().last().and_then(san_piece_kind) {
+        Some(promotion) if kind == PieceKind::Pawn => (
+            rest[..rest.len() - 1].trim_end_matches('='),
+            Some(promotion),
+        ),
+        _ => (rest, None),
+    };
+    if rest.len() < 2 || !rest.is_ascii() {
+        return Err("Could not parse move: Missing target square");
+    }
+    let target = Square::from_algebraic(&rest[rest.len() - 2..])
+        .ok_or("Could not parse move: Invalid target square")?;
+    let disambiguation = rest[..rest.len() - 2].trim_end_matches('x');
+    if disambiguation.len() > 2 {
+        return Err("Could not parse move: Invalid disambiguation");
+    }
+
+    let mut candidates = moves.into_iter().filter(|m| {
+        !m.is_castle()
+            && m.to == target
+            && m.promotion == promotion
+            && board.piece_at(m.from).map(|p| p.kind) == Some(kind)
+            && disambiguation.chars().all(|c| match c {
+                'a'..='h' => m.from.file() == c as usize - 'a' as usize,
+                '1'..='8' => m.from.rank() == c as usize - '1' as usize,
+                _ => false,
+            })
+    });
+    match (candidates.next(), candidates.next()) {
+        (Some(mov), None) => Ok(mov),
+        (None, _) => Err("Could not parse move: No legal move matches"),
+        (Some(_), Some(_)) => Err("Could not parse move: Ambiguous move"),
+    }
+}
+
+/*
+    Write a legal move in Standard Algebraic Notation, with + or # after moves which give check or
+    mate
+
+    When other pieces of the same kind can legally move to the target square, the piece moving is
+    told apart by its file if none of the others share it, otherwise by its rank if none of the
+    others share that, otherwise by its whole square (PGN standard 8.2.3)
+*/
+pub fn move_to_san(board: &BoardState, mov: Move) -> String {
+    let mut san = String::new();
+    let kind = board.piece_at(mov.from).unwrap().kind;
+    if mov.is_castle() {
+        san.push_str(if mov.to.col() > mov.from.col() {
+            "O-O"
+        } else {
+            "O-O-O"
+        });
+    } else {
+        if kind == PieceKind::Pawn {
+            // pawn captures are the only pawn moves which need the starting file
+            if mov.is_capture() {
+                san.push_str(&mov.from.to_string()[..1]);
+            }
+        } else {
+            san.push(san_piece_letter(kind));
+            let others: Vec<Move> = generate_moves(board)
+                .into_iter()
+                .filter(|m| {
+                    m.to == mov.to
+                        && m.from != mov.from
+                        && !m.is_castle()
+                        && board.piece_at(m.from).map(|p| p.kind) == Some(kind)
+                })
+                .collect();
+            let from = mov.from.to_string();
+            if !others.is_empty() {
+                if others.iter().all(|m| m.from.file() != mov.from.file()) {
+                    san.push_str(&from[..1]);
+                } else if others.iter().all(|m| m.from.rank() != mov.from.rank()) {
+                    san.push_str(&from[1..]);
+                } else {
+                    san.push_str(&from);
+                }
+            }
+        }
+        if mov.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&mov.to.to_string());
+        if let Some(promotion) = mov.promotion {
+            san.push('=');
+            san.push(san_piece_letter(promotion));
+        }
+    }
+
+    let new_board = make_move(board, &mov);
+    if is_check(&new_board, new_board.to_move) {
+        san.push(if generate_moves(&new_board).is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    san
+}
+
+/*
+    The kind of piece a SAN piece letter stands for, pawns have no letter
+*/
+fn san_piece_kind(c: char) -> Option<PieceKind> {
+    match c {
+        'N' => Some(PieceKind::Knight),
+        'B' => Some(PieceKind::Bishop),
+        'R' => Some(PieceKind::Rook),
+        'Q' => Some(PieceKind::Queen),
+        'K' => Some(PieceKind::King),
+        _ => None,
+    }
+}
+
+fn san_piece_letter(kind: PieceKind) -> char {
+    Piece::new(PieceColor::White, kind).fen_char()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(fen: &str, san: &str) -> Result<String, &'static str> {
+        parse_san(&board_from_fen(fen).unwrap(), san).map(|m| m.to_string())
+    }
+
+    #[test]
+    fn parse_san_moves() {
+        assert_eq!(parse(DEFAULT_FEN_STRING, "e4"), Ok("e2e4".to_string()));
+        assert_eq!(parse(DEFAULT_FEN_STRING, "Nf3"), Ok("g1f3".to_string()));
+        assert_eq!(parse(DEFAULT_FEN_STRING, "Nf3!?"), Ok("g1f3".to_string()));
+        assert!(parse(DEFAULT_FEN_STRING, "e5").is_err());
+        assert!(parse(DEFAULT_FEN_STRING, "Ke2").is_err());
+        assert!(parse(DEFAULT_FEN_STRING, "").is_err());
+
+        // two knights can reach d7
+        let fen = "r3k2r/ppp2ppp/1n3n2/8/8/8/PPP2PPP/R3K2R b KQkq - 0 1";
+        assert_eq!(parse(fen, "Nbd7"), Ok("b6d7".to_string()));
+        assert_eq!(parse(fen, "Nfd7"), Ok("f6d7".to_string()));
+        assert_eq!(parse(fen, "Nf6d5"), Ok("f6d5".to_string()));
+        assert_eq!(
+            parse(fen, "Nd7"),
+            Err("Could not parse move: Ambiguous move")
+        );
+        assert_eq!(parse(fen, "O-O-O"), Ok("e8c8".to_string()));
+        assert_eq!(parse(fen, "0-0"), Ok("e8g8".to_string()));
+
+        // promotions, with and without a capture and an equals sign
+        let fen = "3r3k/4P3/8/8/8/8/8/K7 w - - 0 1";
+        assert_eq!(parse(fen, "exd8=Q+"), Ok("e7d8q".to_string()));
+        assert_eq!(parse(fen, "e8N"), Ok("e7e8n".to_string()));
+        assert!(parse(fen, "e8").is_err());
+
+        // en passant and rooks told apart by rank
+        let fen = "7k/8/R7/3pP3/8/8/8/R6K w - d6 0 1";
+        assert_eq!(parse(fen, "exd6"), Ok("e5d6".to_string()));
+        assert_eq!(parse(fen, "R1a3"), Ok("a1a3".to_string()));
+        assert_eq!(parse(fen, "R6a3"), Ok("a6a3".to_string()));
+
+        // with three knights on d4 the file alone is not always enough
+        let fen = "k7/8/8/1N3N2/8/1N6/8/7K w - - 0 1";
+        assert_eq!(
+            parse(fen, "Nbd4"),
+            Err("Could not parse move: Ambiguous move")
+        );
+        assert_eq!(
+            parse(fen, "N5d4"),
+            Err("Could not parse move: Ambiguous move")
+        );
+        assert_eq!(parse(fen, "Nb5d4"), Ok("b5d4".to_string()));
+    }
+
+    #[test]
+    fn write_san_moves() {
+        let san = |fen: &str, uci: &str| {
+            let b = board_from_fen(fen).unwrap();
+            let mov = Move::from_uci(&b, uci).unwrap();
+            let san = move_to_san(&b, mov);
+            // whatever is written can be read back
+            assert_eq!(parse_san(&b, &san), Ok(mov));
+            san
+        };
+        assert_eq!(san(DEFAULT_FEN_STRING, "e2e4"), "e4");
+        assert_eq!(san(DEFAULT_FEN_STRING, "g1f3"), "Nf3");
+
+        let fen = "r3k2r/ppp2ppp/1n3n2/8/8/8/PPP2PPP/R3K2R b KQkq - 0 1";
+        assert_eq!(san(fen, "b6d7"), "Nbd7");
+        assert_eq!(san(fen, "f6d5"), "Nfd5");
+        assert_eq!(san(fen, "e8c8"), "O-O-O");
+        assert_eq!(san(fen, "e8g8"), "O-O");
+
+        let fen = "3r3k/4P3/8/8/8/8/8/K7 w - - 0 1";
+        assert_eq!(san(fen, "e7d8q"), "exd8=Q+");
+        assert_eq!(san(fen, "e7e8n"), "e8=N");
+
+        let fen = "7k/8/R7/3pP3/8/8/8/R6K w - d6 0 1";
+        assert_eq!(san(fen, "e5d6"), "exd6");
+        assert_eq!(san(fen, "a1a3"), "R1a3");
+        assert_eq!(san(fen, "a6a8"), "Ra8+");
+        assert_eq!(san("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8"), "Ra8#");
+
+        // three queens which share files and ranks need the whole square
+        let fen = "k7/8/8/8/1Q1Q4/8/1Q6/7K w - - 0 1";
+        assert_eq!(san(fen, "b4c3"), "Qb4c3");
+        assert_eq!(san(fen, "d4c3"), "Qdc3");
+        assert_eq!(san(fen, "b2c3"), "Q2c3");
+
+        // three knights which can reach d4, the file is used whenever it tells them apart
+        let fen = "k7/8/8/1N3N2/8/1N6/8/7K w - - 0 1";
+        assert_eq!(san(fen, "b3d4"), "N3d4");
+        assert_eq!(san(fen, "b5d4"), "Nb5d4");
+        assert_eq!(san(fen, "f5d4"), "Nfd4");
+        assert_eq!(san(fen, "b5c7"), "Nc7+");
+        assert_eq!(san(fen, "b5a3"), "Na3");
+        assert_eq!(san(fen, "f5e3"), "Ne3");
+        assert_eq!(san(fen, "b5d6"), "Nbd6");
+        assert_eq!(san(fen, "b5a7"), "Na7");
+
+        // a pinned knight can not move, so it does not need telling apart
+        let fen = "k3r3/8/8/8/8/8/2N1N3/4K3 w - - 0 1";
+        assert_eq!(san(fen, "c2d4"), "Nd4");
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt::Write;
+
+/*
+    Why a node in the search stopped searching
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeOutcome {
+    Searched,
+    BetaCutoff,
+    Horizon,
+    Checkmate,
+    Stalemate,
+    TableHit,
+    Pruned,
+    Repetition,
+    FiftyMoveRule,
+}
+
+/*
+    How the score of a node relates to its true value, given the window it was searched with
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Clone, Debug)]
+pub struct TreeNode {
+    pub parent: Option<usize>,
+    pub last_move: Option<String>,
+    pub score: i32,
+    pub bound: Bound,
+    pub outcome: NodeOutcome,
+}
+
+/*
+    Records the nodes visited by a search, up to a maximum depth from the root and a maximum
+    number of nodes, so the shape of the search can be exported and visualised
+*/
+pub struct SearchTree {
+    pub nodes: Vec<TreeNode>,
+    max_depth: usize,
+    max_nodes: usize,
+    // the nodes currently being searched from the root down, None where a node was not recorded
+    path: Vec<Option<usize>>,
+}
+
+impl SearchTree {
+    pub fn new(max_depth: usize, max_nodes: usize) -> SearchTree {
+        SearchTree {
+            nodes: vec![],
+            max_depth,
+            max_nodes,
+            path: vec![],
+        }
+    }
+
+    /*
+        Called when the search starts on a node
+    */
+    pub fn enter(&mut self, last_move: Option<String>) {
+        let parent = self.path.last().copied();
+        let parent_recorded = parent.is_none_or(|p| p.is_some());
+        let id = if parent_recorded
+            && self.path.len() <= self.max_depth
+            && self.nodes.len() < self.max_nodes
+        {
+            self.nodes.push(TreeNode {
+                parent: parent.flatten(),
+                last_move,
+                score: 0,
+                bound: Bound::Exact,
+                outcome: NodeOutcome::Searched,
+            });
+            Some(self.nodes.len() - 1)
+        } else {
+            None
+        };
+        self.path.push(id);
+    }
+
+    /*
+        Called when the search of the most recently entered node finishes, with the window
+        the node was searched with
+    */
+    pub fn exit(&mut self, score: i32, alpha: i32, beta: i32, outcome: NodeOutcome) {
+        if let Some(Some(id)) = self.path.pop() {
+            let node = &mut self.nodes[id];
+            node.score = score;
+            node.outcome = outcome;
+            node.bound = if score <= alpha {
+                Bound::Upper
+            } else if score >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+        }
+    }
+
+    /*
+        Export the recorded tree in the graphviz DOT format
+    */
+    pub fn to_dot(&self) -> String {
+        let mut dot = "digraph search {\n    node [shape=box];\n".to_string();
+        for (id, node) in self.nodes.iter().enumerate() {
+            let mut label = match (&node.last_move, node.parent) {
+                (Some(m), Some(_)) => m.clone(),
+                _ => "root".to_string(),
+            };
+            write!(label, "\\n{} ({:?})", node.score, node.bound).unwrap();
+            match node.outcome {
+                NodeOutcome::Searched => {}
+                NodeOutcome::BetaCutoff => label += "\\nbeta cutoff",
+                NodeOutcome::Horizon => label += "\\nhorizon",
+                NodeOutcome::Checkmate => label += "\\ncheckmate",
+                NodeOutcome::Stalemate => label += "\\nstalemate",
+                NodeOutcome::TableHit => label += "\\ntable hit",
+                NodeOutcome::Pruned => label += "\\npruned",
+                NodeOutcome::Repetition => label += "\\nrepetition",
+                NodeOutcome::FiftyMoveRule => label += "\\nfifty-move rule",
+            }
+            writeln!(dot, "    n{} [label=\"{}\"];", id, label).unwrap();
+            if let Some(parent) = node.parent {
+                writeln!(dot, "    n{} -> n{};", parent, id).unwrap();
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_up_to_max_depth() {
+        let mut tree = SearchTree::new(1, 100);
+        tree.enter(None);
+        tree.enter(Some("e2e4".to_string()));
+        tree.enter(Some("e7e5".to_string()));
+        tree.exit(10, i32::MIN, i32::MAX, NodeOutcome::Horizon);
+        tree.exit(10, i32::MIN, i32::MAX, NodeOutcome::Searched);
+        tree.enter(Some("d2d4".to_string()));
+        tree.exit(50, i32::MIN, 20, NodeOutcome::BetaCutoff);
+        tree.exit(10, i32::MIN, i32::MAX, NodeOutcome::Searched);
+
+        assert_eq!(tree.nodes.len(), 3);
+        assert_eq!(tree.nodes[1].parent, Some(0));
+        assert_eq!(tree.nodes[2].parent, Some(0));
+        assert_eq!(tree.nodes[2].bound, Bound::Lower);
+        assert_eq!(tree.nodes[2].outcome, NodeOutcome::BetaCutoff);
+        assert_eq!(tree.nodes[0].bound, Bound::Exact);
+    }
+
+    #[test]
+    fn records_up_to_max_nodes() {
+        let mut tree = SearchTree::new(10, 2);
+        tree.enter(None);
+        for _ in 0..5 {
+            tree.enter(Some("e2e4".to_string()));
+            tree.exit(0, -10, 10, NodeOutcome::Horizon);
+        }
+        tree.exit(0, -10, 10, NodeOutcome::Searched);
+        assert_eq!(tree.nodes.len(), 2);
+    }
+
+    #[test]
+    fn dot_output() {
+        let mut tree = SearchTree::new(2, 10);
+        tree.enter(None);
+        tree.enter(Some("e2e4".to_string()));
+        tree.exit(-5, 0, 10, NodeOutcome::Checkmate);
+        tree.exit(-5, 0, 10, NodeOutcome::Searched);
+        assert_eq!(
+            tree.to_dot(),
+            "digraph search {
+    node [shape=box];
+    n0 [label=\"root\\n-5 (Upper)\"];
+    n1 [label=\"e2e4\\n-5 (Upper)\\ncheckmate\"];
+    n0 -> n1;
+}
+"
+        );
+    }
+}
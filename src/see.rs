@@ -0,0 +1,167 @@
+use crate::attacks::{Point, DIAGONAL, KNIGHT_OFFSETS, STRAIGHT};
+pub use crate::board::*;
+use std::cmp;
+
+// Most captures an exchange on one square can have, one for each piece on the board
+const MAX_EXCHANGE: usize = 32;
+
+/*
+    Static exchange evaluation: the material the side to move ends up winning on the target square
+    of a move, if both sides keep recapturing there with their least valuable piece for as long as
+    it pays them, ie 220 for a pawn taking a knight defended by a pawn and -220 for a knight taking
+    a pawn defended by a pawn
+
+    Rooks, bishops and queens lined up behind a capturing piece join in once it has moved. Pins
+    are not looked at, but a king never captures onto a square the other side still attacks
+*/
+pub fn static_exchange(board: &BoardState, mov: &Move) -> i32 {
+    let mut squares = board.board;
+    let target = mov.to.point();
+    let from = mov.from.point();
+    if mov.flags & MOVE_EN_PASSANT != 0 {
+        squares[from.0][target.1] = EMPTY;
+    }
+    // value of the piece standing on the target square, the next to be captured
+    let mut on_target = match mov.promotion {
+        Some(kind) => PIECE_VALUES[kind.as_bits() as usize],
+        None => PIECE_VALUES[(squares[from.0][from.1] & PIECE_MASK) as usize],
+    };
+    squares[from.0][from.1] = EMPTY;
+
+    // gains[i] is what the side making capture i wins if the exchange stops after it
+    let mut gains = [0; MAX_EXCHANGE];
+    gains[0] = material_won(board, mov);
+    let mut color = board.to_move;
+    let mut captures = 1;
+    while captures < MAX_EXCHANGE {
+        color = match color {
+            PieceColor::White => PieceColor::Black,
+            PieceColor::Black => PieceColor::White,
+        };
+        let (point, piece) = match least_valuable_attacker(&squares, target, color) {
+            Some(attacker) => attacker,
+            None => break,
+        };
+        squares[point.0][point.1] = EMPTY;
+        if piece == KING {
+            let other = match color {
+                PieceColor::White => PieceColor::Black,
+                PieceColor::Black => PieceColor::White,
+            };
+            if least_valuable_attacker(&squares, target, other).is_some() {
+                break;
+            }
+        }
+        gains[captures] = on_target - gains[captures - 1];
+        on_target = PIECE_VALUES[piece as usize];
+        captures += 1;
+    }
+
+    // each side stops capturing once carrying on would lose it material
+    for i in (1..captures).rev() {
+        gains[i - 1] = -cmp::max(-gains[i - 1], gains[i]);
+    }
+    gains[0]
+}
+
+/*
+    The square and kind of the least valuable piece of a color attacking a square
+*/
+fn least_valuable_attacker(
+    squares: &[[u8; 12]; 12],
+    target: Point,
+    color: PieceColor,
+) -> Option<(Point, u8)> {
+    let offset = |m: &(i8, i8)| {
+        (
+            (target.0 as i8 + m.0) as usize,
+            (target.1 as i8 + m.1) as usize,
+        )
+    };
+    let find = |offsets: &[(i8, i8)], kind: u8| {
+        offsets
+            .iter()
+            .map(offset)
+            .find(|p| squares[p.0][p.1] == color.as_mask() | kind)
+    };
+    // pawns attack the square from the rank behind it, as seen by their side
+    let pawn_row = match color {
+        PieceColor::White => 1,
+        PieceColor::Black => -1,
+    };
+    if let Some(point) = find(&[(pawn_row, -1), (pawn_row, 1)], PAWN) {
+        return Some((point, PAWN));
+    }
+    if let Some(point) = find(&KNIGHT_OFFSETS, KNIGHT) {
+        return Some((point, KNIGHT));
+    }
+
+    // the first piece of the color along each line from the square
+    let first_pieces = |directions: &[(i8, i8)]| -> Vec<Point> {
+        let mut pieces = vec![];
+        for m in directions {
+            let mut point = offset(m);
+            while is_empty(squares[point.0][point.1]) {
+                point = (
+                    (point.0 as i8 + m.0) as usize,
+                    (point.1 as i8 + m.1) as usize,
+                );
+            }
+            if get_color(squares[point.0][point.1]) == Some(color) {
+                pieces.push(point);
+            }
+        }
+        pieces
+    };
+    let diagonal = first_pieces(&DIAGONAL);
+    let straight = first_pieces(&STRAIGHT);
+    let slider = |points: &[Point], kind: u8| {
+        points
+            .iter()
+            .find(|p| squares[p.0][p.1] & PIECE_MASK == kind)
+            .map(|p| (*p, kind))
+    };
+    slider(&diagonal, BISHOP)
+        .or_else(|| slider(&straight, ROOK))
+        .or_else(|| slider(&diagonal, QUEEN))
+        .or_else(|| slider(&straight, QUEEN))
+        .or_else(|| {
+            let king = find(&STRAIGHT, KING).or_else(|| find(&DIAGONAL, KING));
+            king.map(|p| (p, KING))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn see(fen: &str, uci: &str) -> i32 {
+        let b = board_from_fen(fen).unwrap();
+        static_exchange(&b, &Move::from_uci(&b, uci).unwrap())
+    }
+
+    #[test]
+    fn exchanges() {
+        // an undefended pawn, and one defended by a pawn
+        assert_eq!(see("4k3/8/8/3p4/8/8/3R4/4K3 w - - 0 1", "d2d5"), 100);
+        assert_eq!(see("4k3/8/4p3/3p4/8/8/3R4/4K3 w - - 0 1", "d2d5"), -400);
+        assert_eq!(see("4k3/8/4p3/3n4/4P3/8/8/4K3 w - - 0 1", "e4d5"), 220);
+        // the rook behind the first rook joins in, so the queen taking back loses it
+        assert_eq!(see("3qk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"), 100);
+        assert_eq!(see("3qk3/3r4/8/3p4/8/8/3R4/3RK3 w - - 0 1", "d2d5"), -400);
+        // black does best not to take back with the queen while the rook defends
+        assert_eq!(see("4k3/2q5/3p4/8/5B2/8/8/4K3 w - - 0 1", "f4d6"), -230);
+        assert_eq!(see("4k3/2q5/3p4/8/5B2/8/8/3RK3 w - - 0 1", "f4d6"), 100);
+    }
+
+    #[test]
+    fn kings_and_special_moves() {
+        // the king can take back only when nothing else defends the square
+        assert_eq!(see("8/8/3k4/3p4/8/8/3Q4/4K3 w - - 0 1", "d2d5"), -800);
+        assert_eq!(see("8/8/3k4/3p4/8/8/3Q4/3RK3 w - - 0 1", "d2d5"), 100);
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        // promoting on a square the rook defends loses the pawn
+        assert_eq!(see("3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8q"), -100);
+        assert_eq!(see("3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7d8q"), 1300);
+    }
+}
@@ -0,0 +1,335 @@
+pub use crate::board::*;
+use std::mem;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+// Size of the transposition table when the search options do not give one
+pub const DEFAULT_HASH_MEGABYTES: usize = 16;
+
+// Number of searches told apart by the generation of an entry, which has 3 bits
+const GENERATIONS: u8 = 8;
+
+// Number of entries at the start of the table looked at to estimate how full it is
+const HASHFULL_SAMPLE: usize = 1000;
+
+/*
+    What an earlier search found out about a position
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TableEntry {
+    // depth the position was searched to
+    pub depth: u8,
+    // score of the position, checkmates (see MATE_THRESHOLD) being stored relative to the position
+    // itself rather than the root of the search which found them
+    score: i32,
+    pub bound: Bound,
+    pub best_move: Option<Move>,
+}
+
+impl TableEntry {
+    /*
+        The stored score, for a position reached 'ply' moves from the root of a search
+        Checkmate scores count the plies from the root to the mate, so the plies to the position
+        are added back to keep the same distance to the mate
+    */
+    pub fn score(&self, ply: usize) -> i32 {
+        if self.score >= MATE_THRESHOLD {
+            self.score - ply as i32
+        } else if self.score <= -MATE_THRESHOLD {
+            self.score + ply as i32
+        } else {
+            self.score
+        }
+    }
+}
+
+/*
+    Positions seen by earlier searches, keyed by their zobrist key, with the depth they were
+    searched to, their score, how the score bounds the true value and the best move found
+
+    The table is a fixed number of entries, each position going in the one picked by its key. A
+    new position replaces the one in its entry if that is from an earlier search, or was searched
+    no deeper than the new one (depth preferred replacement)
+
+    Every method takes &self, so one table can be shared between threads searching at the same
+    time. Entries are two words, the key xored with the data and the data, so an entry read while
+    another thread is half way through writing it does not match its key and is ignored
+*/
+pub struct TranspositionTable {
+    entries: Vec<[AtomicU64; 2]>,
+    // counts the searches the table has been used for, wrapping at GENERATIONS
+    generation: AtomicU8,
+}
+
+impl TranspositionTable {
+    /*
+        A table taking up at most 'megabytes', with at least one entry
+    */
+    pub fn new(megabytes: usize) -> TranspositionTable {
+        let size = (megabytes * 1024 * 1024 / mem::size_of::<[AtomicU64; 2]>()).max(1);
+        TranspositionTable {
+            entries: (0..size)
+                .map(|_| [AtomicU64::new(0), AtomicU64::new(0)])
+                .collect(),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    /*
+        Called at the start of each search, so the entries from earlier searches are replaced
+        before the ones from this search
+    */
+    pub fn new_search(&self) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        self.generation
+            .store((generation + 1) % GENERATIONS, Ordering::Relaxed);
+    }
+
+    /*
+        Forget every position, ie for a new game
+    */
+    pub fn clear(&self) {
+        for entry in &self.entries {
+            entry[0].store(0, Ordering::Relaxed);
+            entry[1].store(0, Ordering::Relaxed);
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /*
+        How full the table is in permille, counting only the entries stored by the current search
+        as the rest are replaced first
+        Estimated from the entries at the start of the table, as the keys spread positions evenly
+        over it
+    */
+    pub fn hashfull(&self) -> usize {
+        let sample = self.entries.len().min(HASHFULL_SAMPLE);
+        let generation = self.generation.load(Ordering::Relaxed);
+        let used = (0..sample)
+            .filter_map(|index| self.read(index))
+            .filter(|(_, data)| unpack_entry(*data).1 == generation)
+            .count();
+        used * 1000 / sample
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+
+    /*
+        The key and data in an entry, None if it is empty or was torn by two threads writing it
+    */
+    fn read(&self, index: usize) -> Option<(u64, u64)> {
+        let entry = &self.entries[index];
+        let data = entry[1].load(Ordering::Relaxed);
+        if data == 0 {
+            return None;
+        }
+        Some((entry[0].load(Ordering::Relaxed) ^ data, data))
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TableEntry> {
+        match self.read(self.index(key)) {
+            Some((stored_key, data)) if stored_key == key => Some(unpack_entry(data).0),
+            _ => None,
+        }
+    }
+
+    /*
+        Store what a search to 'depth' found about a position 'ply' moves from its root, 'score'
+        being the score it gave
+    */
+    pub fn store(
+        &self,
+        key: u64,
+        depth: u8,
+        ply: usize,
+        score: i32,
+        bound: Bound,
+        mut best_move: Option<Move>,
+    ) {
+        let index = self.index(key);
+        let generation = self.generation.load(Ordering::Relaxed);
+        if let Some((stored_key, data)) = self.read(index) {
+            let (stored, stored_generation) = unpack_entry(data);
+            if stored_key == key {
+                // a search which failed low has no best move, the one found before is still the
+                // best guess
+                best_move = best_move.or(stored.best_move);
+            } else if stored_generation == generation && stored.depth > depth {
+                return;
+            }
+        }
+
+        let score = if score >= MATE_THRESHOLD {
+            score + ply as i32
+        } else if score <= -MATE_THRESHOLD {
+            score - ply as i32
+        } else {
+            score
+        };
+        let entry = TableEntry {
+            depth,
+            score,
+            bound,
+            best_move,
+        };
+        let data = pack_entry(&entry, generation);
+        self.entries[index][0].store(key ^ data, Ordering::Relaxed);
+        self.entries[index][1].store(data, Ordering::Relaxed);
+    }
+}
+
+/*
+    An entry packed into 64 bits, a move of a1a1 meaning there is no move
+
+    Layout
+    0..32   score
+    32..40  depth
+    40..42  bound, 0 for exact, 1 for lower and 2 for upper
+    42..48  square the move is from, counted from a1 along each rank
+    48..54  square the move is to
+    54..57  promotion piece, 0 if there is none
+    57..61  move flags
+    61..64  generation
+*/
+fn pack_entry(entry: &TableEntry, generation: u8) -> u64 {
+    let bound = match entry.bound {
+        Bound::Exact => 0,
+        Bound::Lower => 1,
+        Bound::Upper => 2,
+    };
+    let mut data = entry.score as u32 as u64 | (entry.depth as u64) << 32 | bound << 40;
+    if let Some(mov) = entry.best_move {
+        let square = |s: Square| (s.rank() * 8 + s.file()) as u64;
+        let promotion = mov.promotion.map_or(0, |kind| kind.as_bits());
+        data |= square(mov.from) << 42
+            | square(mov.to) << 48
+            | (promotion as u64) << 54
+            | (mov.flags as u64) << 57;
+    }
+    data | (generation as u64) << 61
+}
+
+fn unpack_entry(data: u64) -> (TableEntry, u8) {
+    let square = |shift: u32| {
+        let index = (data >> shift) as usize & 0x3F;
+        Square::from_file_rank(index % 8, index / 8).unwrap()
+    };
+    let (from, to) = (square(42), square(48));
+    let best_move = if from == to {
+        None
+    } else {
+        Some(Move {
+            from,
+            to,
+            promotion: Piece::from_u8(WHITE | (data >> 54) as u8 & 0x7).map(|p| p.kind),
+            flags: (data >> 57) as u8 & 0xF,
+        })
+    };
+    let bound = match (data >> 40) & 0x3 {
+        0 => Bound::Exact,
+        1 => Bound::Lower,
+        _ => Bound::Upper,
+    };
+    let entry = TableEntry {
+        depth: (data >> 32) as u8,
+        score: data as u32 as i32,
+        bound,
+        best_move,
+    };
+    (entry, (data >> 61) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_round_trip() {
+        let b = board_from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let table = TranspositionTable::new(1);
+        for (i, mov) in generate_moves(&b).into_iter().enumerate() {
+            let key = i as u64 * 7919;
+            let score = i as i32 * 37 - 500;
+            table.store(key, 3, 0, score, Bound::Lower, Some(mov));
+            let entry = table.probe(key).unwrap();
+            assert_eq!(entry.best_move, Some(mov));
+            assert_eq!(entry.score(0), score);
+            assert_eq!(entry.depth, 3);
+            assert_eq!(entry.bound, Bound::Lower);
+        }
+        table.store(1, 200, 0, -3, Bound::Upper, None);
+        assert_eq!(
+            table.probe(1),
+            Some(TableEntry {
+                depth: 200,
+                score: -3,
+                bound: Bound::Upper,
+                best_move: None,
+            })
+        );
+        assert_eq!(table.probe(2), None);
+        table.clear();
+        assert_eq!(table.probe(1), None);
+    }
+
+    #[test]
+    fn mate_scores_keep_their_distance() {
+        let table = TranspositionTable::new(1);
+        // mated 7 plies from the root, in a position 5 plies from it
+        table.store(1, 4, 5, -CHECKMATE_SCORE + 7, Bound::Exact, None);
+        let entry = table.probe(1).unwrap();
+        assert_eq!(entry.score(5), -CHECKMATE_SCORE + 7);
+        // the same position reached sooner, or in a later search from a root nearer to it
+        assert_eq!(entry.score(3), -CHECKMATE_SCORE + 5);
+        assert_eq!(entry.score(0), -CHECKMATE_SCORE + 2);
+        table.store(2, 4, 2, CHECKMATE_SCORE - 3, Bound::Lower, None);
+        assert_eq!(table.probe(2).unwrap().score(6), CHECKMATE_SCORE - 7);
+    }
+
+    #[test]
+    fn deeper_entries_are_kept() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let e4 = Move::from_uci(&b, "e2e4").unwrap();
+        let d4 = Move::from_uci(&b, "d2d4").unwrap();
+        let table = TranspositionTable::new(0);
+        table.store(1, 6, 0, 20, Bound::Exact, Some(e4));
+        // a shallower search of another position in the same entry
+        table.store(2, 2, 0, 10, Bound::Exact, Some(d4));
+        assert_eq!(table.probe(1).unwrap().depth, 6);
+        assert_eq!(table.probe(2), None);
+
+        // the same position always replaces, keeping the move if the new search has none
+        table.store(1, 2, 0, 5, Bound::Upper, None);
+        assert_eq!(table.probe(1).unwrap().best_move, Some(e4));
+        assert_eq!(table.probe(1).unwrap().depth, 2);
+
+        // entries from an earlier search are replaced whatever their depth
+        table.store(1, 6, 0, 20, Bound::Exact, Some(e4));
+        table.new_search();
+        table.store(2, 2, 0, 10, Bound::Exact, Some(d4));
+        assert_eq!(table.probe(1), None);
+        assert_eq!(table.probe(2).unwrap().best_move, Some(d4));
+    }
+
+    #[test]
+    fn hashfull_counts_current_search() {
+        // 10 entries, each key going in the entry it is equal to
+        let table = TranspositionTable {
+            entries: (0..10)
+                .map(|_| [AtomicU64::new(0), AtomicU64::new(0)])
+                .collect(),
+            generation: AtomicU8::new(0),
+        };
+        assert_eq!(table.hashfull(), 0);
+        for key in 0..4 {
+            table.store(key, 1, 0, 0, Bound::Exact, None);
+        }
+        assert_eq!(table.hashfull(), 400);
+        table.new_search();
+        table.store(4, 1, 0, 0, Bound::Exact, None);
+        assert_eq!(table.hashfull(), 100);
+        table.clear();
+        assert_eq!(table.hashfull(), 0);
+    }
+}
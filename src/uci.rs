@@ -1,9 +1,30 @@
 pub use crate::board::*;
 pub use crate::engine::*;
-pub use crate::move_generation::*;
 use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-pub fn play_game_uci(search_depth: u8) {
+// UCI notation for passing the move without moving a piece
+const NULL_MOVE_ALG: &str = "0000";
+
+// Most lines the MultiPV option can ask for
+const MAX_MULTI_PV: usize = 256;
+
+// Furthest from zero the Contempt option can be, in centipawns
+const MAX_CONTEMPT: i32 = 100;
+
+// How long a search runs before the GUI is told which move it is searching at the root, so short
+// searches do not flood it with info lines
+const CURRMOVE_DELAY: Duration = Duration::from_secs(1);
+
+/*
+    Play games over UCI, writing a JSON record for each move the engine plays to the JSONL log
+    when one is given
+*/
+pub fn play_game_uci(search_depth: u8, options: &SearchOptions, jsonl_log: Option<std::fs::File>) {
     let mut board = board_from_fen(DEFAULT_FEN_STRING).unwrap();
     let log = std::fs::File::create("log.txt").expect("Could not create log file");
     let buffer = read_from_gui(&log);
@@ -13,16 +34,94 @@ pub fn play_game_uci(search_depth: u8) {
     }
     send_to_gui("id name Walleye\n".to_string(), &log);
     send_to_gui("id author Mitchel Paulin\n".to_string(), &log);
+    send_to_gui(
+        format!(
+            "option name Hash type spin default {} min 1 max 65536\n",
+            DEFAULT_HASH_MEGABYTES
+        ),
+        &log,
+    );
+    send_to_gui(
+        format!(
+            "option name MultiPV type spin default 1 min 1 max {}\n",
+            MAX_MULTI_PV
+        ),
+        &log,
+    );
+    send_to_gui(
+        format!(
+            "option name Contempt type spin default 0 min {} max {}\n",
+            -MAX_CONTEMPT, MAX_CONTEMPT
+        ),
+        &log,
+    );
+    send_to_gui(
+        "option name Deterministic type check default false\n".to_string(),
+        &log,
+    );
     send_to_gui("uciok\n".to_string(), &log);
 
+    // the table is kept from one move to the next, so the positions searched for the last move
+    // are not searched again
+    let mut options = *options;
+    let mut table = options.new_table();
+    // keys of the positions played before the current one, for the search to find repetitions
+    let mut history = vec![];
+    // the square the last move captured on, for the search to find recaptures
+    let mut last_capture = None;
+    // the search runs on a thread of its own, so stop can be read while it runs, and gives back the
+    // board after the move it plays
+    let mut search_thread: Option<thread::JoinHandle<BoardState>> = None;
+    let stop = Arc::new(AtomicBool::new(false));
+    // set by debug on, to send the search's statistics once it finishes
+    let mut debug = false;
+
     loop {
         let buffer = read_from_gui(&log);
         let command: Vec<&str> = buffer.split(' ').collect();
+        if matches!(command[0].trim(), "stop" | "quit") {
+            stop.store(true, Ordering::Relaxed);
+        }
+        // every command but isready waits for the search to finish, so the board is up to date
+        if command[0] != "isready\n" {
+            if let Some(thread) = search_thread.take() {
+                let next_board = thread.join().expect("search thread panicked");
+                if next_board.position_key() != board.position_key() {
+                    history.push(board.position_key());
+                    last_capture = capture_square(&board, &next_board);
+                }
+                board = next_board;
+            }
+        }
+
         if command[0] == "quit\n" {
             break;
+        } else if command[0] == "stop\n" {
+            // the search has already been stopped and has sent its move
         } else if command[0] == "isready\n" {
             send_to_gui("readyok\n".to_string(), &log);
+        } else if command[0] == "debug" {
+            debug = command.get(1).is_some_and(|c| c.trim() == "on");
+        } else if command[0] == "setoption" {
+            if let Some(megabytes) = spin_option(&command, "Hash").filter(|m| *m > 0) {
+                options.hash_megabytes = Some(megabytes);
+                table = options.new_table();
+            } else if let Some(lines) = spin_option::<usize>(&command, "MultiPV").filter(|l| *l > 0)
+            {
+                options.multi_pv = Some(lines.min(MAX_MULTI_PV));
+            } else if let Some(contempt) = spin_option::<i32>(&command, "Contempt") {
+                options.contempt = contempt.clamp(-MAX_CONTEMPT, MAX_CONTEMPT);
+                // the table's scores were worked out with the old contempt
+                table.clear();
+            } else if let Some(deterministic) = spin_option(&command, "Deterministic") {
+                options.deterministic = deterministic;
+            } else {
+                log_error(format!("Unsupported option: {}", buffer), &log);
+            }
         } else if command[0] == "ucinewgame\n" {
+            table.clear();
+            history.clear();
+            last_capture = None;
             let buffer = read_from_gui(&log);
             board = match setup_new_game(buffer, &log) {
                 Some(b) => b,
@@ -34,97 +133,358 @@ pub fn play_game_uci(search_depth: u8) {
             // only play last move, the rest has been recorded in the board state
             let player_move = command.last().unwrap();
             log_info(player_move.to_string(), &log);
+            let before = board.clone();
             handle_player_move(&mut board, player_move, &log);
-        } else if command[0] == "go" {
-            board = find_best_move(&board, search_depth, &log);
+            if board.position_key() != before.position_key() {
+                history.push(before.position_key());
+                last_capture = capture_square(&before, &board);
+            }
+        } else if command[0].trim() == "go" {
+            let options = go_options(&command, &options);
+            // go depth searches to exactly that depth rather than the engine's own
+            let search_depth = go_value(&command, "depth").unwrap_or(search_depth);
+            let clock = go_clock(&command, board.to_move);
+            stop.store(false, Ordering::Relaxed);
+            let (board, table, history) = (board.clone(), table.clone(), history.clone());
+            let stop = stop.clone();
+            let log = log.try_clone().expect("Could not clone log file");
+            let jsonl_log = jsonl_log
+                .as_ref()
+                .map(|f| f.try_clone().expect("Could not clone jsonl log"));
+            search_thread = Some(thread::spawn(move || {
+                find_best_move(
+                    &board,
+                    search_depth,
+                    &options,
+                    &table,
+                    &history,
+                    last_capture,
+                    &stop,
+                    debug,
+                    &log,
+                    jsonl_log.as_ref().map(|f| (f, clock)),
+                )
+            }));
         } else {
             log_error(format!("Unrecognized command: {}", buffer), &log);
         }
     }
 }
 
+/*
+    Play the move sent by the gui, by finding the generated move with the same notation so
+    everything about the board (piece lists, king locations, castling rights and so on) is updated
+    exactly as it is for the engine's own moves
+*/
 fn handle_player_move(board: &mut BoardState, player_move: &&str, log: &std::fs::File) {
-    let start_pair = algebraic_pairs_to_board_position(&player_move[0..2]).unwrap();
-    let end_pair = algebraic_pairs_to_board_position(&player_move[2..4]).unwrap();
-    let target_square = board.board[end_pair.0][end_pair.1];
-    if !is_empty(target_square) {
-        if is_white(target_square) {
-            board.white_total_piece_value -= PIECE_VALUES[(target_square & PIECE_MASK) as usize];
-        } else {
-            board.black_total_piece_value -= PIECE_VALUES[(target_square & PIECE_MASK) as usize];
+    let player_move = player_move.trim();
+    if player_move == NULL_MOVE_ALG {
+        if let Err(err) = handle_null_move(board) {
+            log_error(format!("{}: {}\n", err, player_move), log);
+            return;
         }
+        log_info(format!("\n{}\n", board), log);
+        return;
     }
 
-    board.board[end_pair.0][end_pair.1] = board.board[start_pair.0][start_pair.1];
-    board.board[start_pair.0][start_pair.1] = EMPTY;
-    //deal with pawn promotions, check for 6 because of new line character
-    if player_move.len() == 6 {
-        let piece = match player_move.chars().nth(4).unwrap() {
-            'q' => QUEEN,
-            'n' => KNIGHT,
-            'b' => BISHOP,
-            'r' => ROOK,
-            _ => {
-                log_error(
-                    "Could not recognize piece value, default to queen".to_string(),
-                    &log,
-                );
-                QUEEN
+    match Move::from_uci(board, player_move) {
+        Ok(mov) => *board = make_move(board, &mov),
+        Err(err) => {
+            log_error(format!("{}: {}", err, player_move), log);
+            return;
+        }
+    }
+    log_info(format!("\n{}\n", board), log);
+}
+
+/*
+    Pass the move to the other side without moving a piece, used by analysts to ask what
+    would happen if the other side were to move in this position
+    A side in check can not pass, as the other side could then take its king
+*/
+fn handle_null_move(board: &mut BoardState) -> Result<(), &'static str> {
+    if in_check(board) {
+        return Err("Could not pass the move: Side to move is in check");
+    }
+    if board.to_move == PieceColor::Black {
+        board.full_move_clock = board.full_move_clock.saturating_add(1);
+    }
+    board.half_move_clock = board.half_move_clock.saturating_add(1);
+    board.make_null_move();
+    Ok(())
+}
+
+/*
+    The value given by a setoption command for a spin or check option with the given name
+    (ie setoption name Hash value 64), None for any other option or a value which does not parse
+*/
+fn spin_option<T: FromStr>(command: &[&str], name: &str) -> Option<T> {
+    match command {
+        ["setoption", "name", option, "value", value] if *option == name => {
+            value.trim().parse().ok()
+        }
+        _ => None,
+    }
+}
+
+/*
+    The search options for a go command, using the time it gives for the move (ie go movetime
+    1000), the most nodes it can search (ie go nodes 100000) and the moves to look for a checkmate
+    in (ie go mate 3) if it gives them
+*/
+fn go_options(command: &[&str], options: &SearchOptions) -> SearchOptions {
+    let mut options = *options;
+    if let Some(ms) = go_value(command, "movetime") {
+        options.move_time = Some(Duration::from_millis(ms));
+    }
+    if let Some(nodes) = go_value(command, "nodes") {
+        options.node_limit = Some(nodes);
+    }
+    if let Some(moves) = go_value(command, "mate") {
+        options.mate_limit = Some(moves);
+    }
+    options
+}
+
+/*
+    The time the side to move has on its clock, given by a go command as wtime or btime in
+    milliseconds, None if the command does not give it
+*/
+fn go_clock(command: &[&str], to_move: PieceColor) -> Option<Duration> {
+    let name = match to_move {
+        PieceColor::White => "wtime",
+        PieceColor::Black => "btime",
+    };
+    go_value(command, name).map(Duration::from_millis)
+}
+
+/*
+    The value following a parameter of a go command, None if the command does not give the
+    parameter or its value does not parse
+*/
+fn go_value<T: FromStr>(command: &[&str], name: &str) -> Option<T> {
+    let index = command.iter().position(|c| c.trim() == name)?;
+    command.get(index + 1)?.trim().parse().ok()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn find_best_move(
+    board: &BoardState,
+    search_depth: u8,
+    options: &SearchOptions,
+    table: &Arc<TranspositionTable>,
+    history: &[u64],
+    last_capture: Option<Square>,
+    stop: &Arc<AtomicBool>,
+    debug: bool,
+    log: &std::fs::File,
+    jsonl_log: Option<(&std::fs::File, Option<Duration>)>,
+) -> BoardState {
+    // the GUI should not ask for a move once the game is over, but if it does there is none to give
+    let status = board.status();
+    if matches!(status, GameStatus::Checkmate(_) | GameStatus::Stalemate) {
+        send_to_gui("bestmove 0000\n".to_string(), log);
+        log_info(format!("Game over: {}", status), log);
+        return board.clone();
+    }
+
+    let start = Instant::now();
+    // the record of the move played, with the clock the GUI gave less the time spent finding it
+    let record_move = |next_board: &BoardState, evaluation: i32, depth: u8, nodes: u64| {
+        if let Some((mut jsonl_log, clock)) = jsonl_log {
+            let elapsed = start.elapsed();
+            let time_left = clock.map(|c| c.saturating_sub(elapsed));
+            let record = move_record_json(
+                game_ply(board),
+                board,
+                next_board,
+                evaluation,
+                depth,
+                nodes,
+                elapsed,
+                time_left,
+            );
+            if let Err(err) = writeln!(jsonl_log, "{}", record) {
+                log_error(format!("Could not write to jsonl log: {}\n", err), log);
             }
-        };
-        board.board[end_pair.0][end_pair.1] = piece | board.to_move.as_mask();
-    }
-
-    //deal with castling
-    if &player_move[0..4] == WHITE_KING_SIDE_CASTLE_ALG
-        && board.board[end_pair.0][end_pair.1] == WHITE | KING
-    {
-        board.board[BOARD_END - 1][BOARD_END - 1] = EMPTY;
-        board.board[BOARD_END - 1][BOARD_END - 3] = WHITE | ROOK;
-    } else if &player_move[0..4] == WHITE_QUEEN_SIDE_CASTLE_ALG
-        && board.board[end_pair.0][end_pair.1] == WHITE | KING
-    {
-        board.board[BOARD_END - 1][BOARD_START] = EMPTY;
-        board.board[BOARD_END - 1][BOARD_START + 3] = WHITE | ROOK;
-    } else if &player_move[0..4] == BLACK_KING_SIDE_CASTLE_ALG
-        && board.board[end_pair.0][end_pair.1] == BLACK | KING
-    {
-        board.board[BOARD_START][BOARD_END - 1] = EMPTY;
-        board.board[BOARD_START][BOARD_END - 3] = BLACK | ROOK;
-    } else if &player_move[0..4] == BLACK_QUEEN_SIDE_CASTLE_ALG
-        && board.board[end_pair.0][end_pair.1] == BLACK | KING
-    {
-        board.board[BOARD_START][BOARD_START] = EMPTY;
-        board.board[BOARD_START][BOARD_START + 3] = BLACK | ROOK;
-    }
-
-    board.swap_color();
-    log_info(board.simple_board(), &log);
-}
-
-fn find_best_move(board: &BoardState, search_depth: u8, log: &std::fs::File) -> BoardState {
-    let evaluation = alpha_beta_search(&board, search_depth, i32::MIN, i32::MAX, board.to_move);
-    let next_board = evaluation.0.unwrap();
+        }
+    };
+
+    if let Some(next_board) = forced_move(board) {
+        record_move(&next_board, get_evaluation(&next_board), 0, 0);
+        let best_move = next_board.last_move.clone().unwrap();
+        send_to_gui(format!("bestmove {}\n", best_move), log);
+        log_info(format!("\n{}\n", board), log);
+        return next_board;
+    }
+
+    let mut search = SearchState::with_table(*options, table.clone());
+    search.set_game_history(history);
+    search.last_capture = last_capture;
+    search.stop = stop.clone();
+    let info_log = log.try_clone().expect("Could not clone log file");
+    let to_move = board.to_move;
+    // the time taken and when to send currmove lines depend on the clock, so a deterministic
+    // search sends neither and always gives the same output
+    let deterministic = options.deterministic;
+    search.on_depth = Some(Box::new(move |report| {
+        send_to_gui(info_line(report, to_move, deterministic), &info_log);
+    }));
+    let currmove_log = log.try_clone().expect("Could not clone log file");
+    search.on_root_move = Some(Box::new(move |report| {
+        if !deterministic && start.elapsed() >= CURRMOVE_DELAY {
+            send_to_gui(currmove_line(report), &currmove_log);
+        }
+    }));
+    let (evaluation, depth_reached) = iterative_deepening_search(board, search_depth, &mut search);
+    let time = Some(start.elapsed()).filter(|_| !deterministic);
+    send_to_gui(summary_line(search.nodes, time), log);
+    if debug {
+        send_to_gui(stats_line(&search.stats), log);
+    }
+    // the search always finds a move at the root, but should it not the first legal move is
+    // played rather than sending no move at all
+    let next_board = match evaluation.0 {
+        Some(next_board) => next_board,
+        None => {
+            log_error("Search found no move\n".to_string(), log);
+            generate_child_boards(board).swap_remove(0)
+        }
+    };
+    record_move(&next_board, evaluation.1, depth_reached, search.nodes);
     let best_move = next_board.last_move.clone().unwrap();
-    send_to_gui(format!("bestmove {}\n", best_move), &log);
-    log_info(board.simple_board(), &log);
+    send_to_gui(format!("bestmove {}\n", best_move), log);
+    log_info(format!("\n{}\n", board), log);
     next_board
 }
 
+/*
+    The number of the half move about to be played in the game, counting from 1 for white's
+    first move
+*/
+fn game_ply(board: &BoardState) -> u32 {
+    let black = (board.to_move == PieceColor::Black) as u32;
+    u32::from(board.full_move_clock).saturating_sub(1) * 2 + black + 1
+}
+
+/*
+    The info line telling the GUI about a line the search found at a depth, ie
+    info depth 3 seldepth 7 multipv 1 score cp 35 nodes 2410 nps 803333 time 3 hashfull 12
+    pv e2e4 e7e5 g1f3
+    A score which only bounds the line's, as a mate search gives when it finds no mate, is marked
+    upperbound or lowerbound from the side to move's point of view. A deterministic search leaves
+    out the nodes per second and time
+*/
+fn info_line(report: &DepthReport, to_move: PieceColor, deterministic: bool) -> String {
+    let pv: Vec<String> = report.line.pv.iter().map(|m| m.to_uci()).collect();
+    let time = Some(report.time).filter(|_| !deterministic);
+    format!(
+        "info depth {} seldepth {} multipv {} score {}{} {} hashfull {} pv {}\n",
+        report.depth,
+        report.seldepth,
+        report.multipv,
+        uci_score(report.line.score, to_move),
+        uci_bound(report.line.bound, to_move),
+        nodes_and_time(report.nodes, time),
+        report.hashfull,
+        pv.join(" ")
+    )
+}
+
+/*
+    The info line sent once the search has finished, with the positions it visited over every
+    depth, including a depth it gave up, ie
+    info nodes 152034 nps 1013560 time 150
+*/
+fn summary_line(nodes: u64, time: Option<Duration>) -> String {
+    format!("info {}\n", nodes_and_time(nodes, time))
+}
+
+/*
+    The nodes part of an info line, followed by the nodes per second and time in milliseconds
+    when the time is given
+*/
+fn nodes_and_time(nodes: u64, time: Option<Duration>) -> String {
+    match time {
+        Some(time) => format!(
+            "nodes {} nps {} time {}",
+            nodes,
+            nodes_per_second(nodes, time),
+            time.as_millis()
+        ),
+        None => format!("nodes {}", nodes),
+    }
+}
+
+/*
+    The search's statistics as an info string, sent after the search when debugging is on, ie
+    info string first move cutoffs 91.3% table hits 38.0% re-searches 4.2% pruned 1520
+*/
+fn stats_line(stats: &SearchStats) -> String {
+    format!(
+        "info string first move cutoffs {:.1}% table hits {:.1}% re-searches {:.1}% pruned {}\n",
+        stats.first_move_cutoff_rate(),
+        stats.table_hit_rate(),
+        stats.re_search_rate(),
+        stats.pruned
+    )
+}
+
+/*
+    The info line telling the GUI which move the search is searching at the root, ie
+    info depth 12 currmove e2e4 currmovenumber 5
+*/
+fn currmove_line(report: &RootMoveReport) -> String {
+    format!(
+        "info depth {} currmove {} currmovenumber {}\n",
+        report.depth,
+        report.mov.to_uci(),
+        report.number
+    )
+}
+
+/*
+    A search score as the score part of an info line, from the point of view of the side to move:
+    centipawns (ie cp 35), or for a checkmate the moves until it, negative when the side to move is
+    the one mated (ie mate -2)
+*/
+fn uci_score(score: i32, to_move: PieceColor) -> String {
+    let sign = match to_move {
+        PieceColor::White => 1,
+        PieceColor::Black => -1,
+    };
+    match moves_to_mate(score) {
+        Some(moves) => format!("mate {}", moves * sign),
+        None => format!("cp {}", score * sign),
+    }
+}
+
+/*
+    The bound part of an info line's score for a score which is not exact, from the point of view
+    of the side to move, empty for an exact score
+*/
+fn uci_bound(bound: Bound, to_move: PieceColor) -> &'static str {
+    match (bound, to_move) {
+        (Bound::Exact, _) => "",
+        (Bound::Upper, PieceColor::White) | (Bound::Lower, PieceColor::Black) => " upperbound",
+        (Bound::Lower, PieceColor::White) | (Bound::Upper, PieceColor::Black) => " lowerbound",
+    }
+}
+
 fn setup_new_game(buffer: String, log: &std::fs::File) -> Option<BoardState> {
     let command: Vec<&str> = buffer.split(' ').collect();
     if command[1] == "startpos\n" {
         return Some(board_from_fen(DEFAULT_FEN_STRING).unwrap());
     } else if command[1] == "fen" {
         let mut fen = "".to_string();
-        for i in 2..7 {
-            fen += &format!("{} ", command[i]);
+        for part in &command[2..7] {
+            fen += &format!("{} ", part);
         }
         fen += command[7];
         match board_from_fen(&fen) {
             Ok(b) => return Some(b),
             Err(err) => {
-                log_error(format!("{} : {}", err, fen), &log);
+                log_error(format!("{} : {}", err, fen), log);
                 return None;
             }
         }
@@ -156,3 +516,238 @@ fn read_from_gui(mut log: &std::fs::File) -> String {
         .expect("write failed");
     buffer
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn scores_from_side_to_move() {
+        assert_eq!(uci_score(35, PieceColor::White), "cp 35");
+        assert_eq!(uci_score(35, PieceColor::Black), "cp -35");
+        assert_eq!(uci_score(CHECKMATE_SCORE - 3, PieceColor::White), "mate 2");
+        assert_eq!(uci_score(CHECKMATE_SCORE - 2, PieceColor::Black), "mate -1");
+        assert_eq!(uci_score(-CHECKMATE_SCORE + 1, PieceColor::Black), "mate 1");
+        assert_eq!(
+            uci_score(-CHECKMATE_SCORE + 4, PieceColor::White),
+            "mate -2"
+        );
+        assert_eq!(uci_bound(Bound::Exact, PieceColor::White), "");
+        assert_eq!(uci_bound(Bound::Upper, PieceColor::White), " upperbound");
+        assert_eq!(uci_bound(Bound::Upper, PieceColor::Black), " lowerbound");
+    }
+
+    #[test]
+    fn null_move_passes() {
+        let log_path =
+            std::env::temp_dir().join(format!("walleye_null_move_{}.txt", std::process::id()));
+        let log = std::fs::File::create(&log_path).unwrap();
+        let mut b = board_from_fen("4k3/8/8/8/8/8/4r3/K7 w - - 3 10").unwrap();
+        handle_player_move(&mut b, &"0000\n", &log);
+        assert_eq!(b.to_move, PieceColor::Black);
+        assert_eq!(b.half_move_clock, 4);
+
+        // white is in check, so passing would let black take the king
+        let mut b = board_from_fen("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1").unwrap();
+        handle_player_move(&mut b, &"0000\n", &log);
+        assert_eq!(b.to_move, PieceColor::White);
+        let output = std::fs::read_to_string(&log_path).unwrap();
+        std::fs::remove_file(&log_path).unwrap();
+        assert!(output.contains("<ERROR> Could not pass the move: Side to move is in check"));
+    }
+
+    #[test]
+    fn info_lines_give_pv() {
+        let b = board_from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions::default());
+        let lines = Arc::new(Mutex::new(vec![]));
+        let reported = lines.clone();
+        search.on_depth = Some(Box::new(move |report| {
+            let line = info_line(report, PieceColor::White, false);
+            reported.lock().unwrap().push(line);
+        }));
+        iterative_deepening_search(&b, 4, &mut search);
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("info depth 1 seldepth "));
+        assert!(lines[0].contains(" multipv 1 score cp "));
+        // the mate in 2 and the moves leading to it
+        assert!(lines[3].contains(" multipv 1 score mate 2 nodes "));
+        assert_eq!(lines[3].split(' ').count(), 22);
+        assert_eq!(search.principal_variation().len(), 3);
+    }
+
+    #[test]
+    fn summary_gives_nodes_per_second() {
+        assert_eq!(
+            summary_line(152034, Some(Duration::from_millis(150))),
+            "info nodes 152034 nps 1013560 time 150\n"
+        );
+        assert_eq!(
+            summary_line(1, Some(Duration::ZERO)),
+            "info nodes 1 nps 0 time 0\n"
+        );
+    }
+
+    #[test]
+    fn deterministic_go_repeats_output() {
+        let b =
+            board_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        let options = SearchOptions {
+            move_time: Some(Duration::from_millis(20)),
+            deterministic: true,
+            ..SearchOptions::default()
+        };
+        // what a go sends the GUI, read back from the log it is written to
+        let go = |run: usize| {
+            let path = std::env::temp_dir().join(format!(
+                "walleye_deterministic_{}_{}.txt",
+                std::process::id(),
+                run
+            ));
+            let log = std::fs::File::create(&path).unwrap();
+            let stop = Arc::new(AtomicBool::new(false));
+            find_best_move(
+                &b,
+                20,
+                &options,
+                &options.new_table(),
+                &[],
+                None,
+                &stop,
+                true,
+                &log,
+                None,
+            );
+            let output = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            output
+        };
+        let output = go(1);
+        assert!(output.contains("ENGINE >> info depth 2 "));
+        assert!(output.contains("ENGINE >> info nodes "));
+        assert!(output.contains("ENGINE >> bestmove "));
+        assert!(!output.contains(" time ") && !output.contains(" nps "));
+        assert_eq!(go(2), output);
+    }
+
+    #[test]
+    fn go_writes_jsonl_record() {
+        let b = board_from_fen("k7/8/2K5/8/8/8/8/7R b - - 4 30").unwrap();
+        let dir = std::env::temp_dir();
+        let log_path = dir.join(format!("walleye_jsonl_go_{}.txt", std::process::id()));
+        let jsonl_path = dir.join(format!("walleye_jsonl_go_{}.jsonl", std::process::id()));
+        let log = std::fs::File::create(&log_path).unwrap();
+        let jsonl_log = std::fs::File::create(&jsonl_path).unwrap();
+        let command: Vec<&str> = "go wtime 1000 btime 60000 depth 3\n".split(' ').collect();
+        let clock = go_clock(&command, b.to_move);
+        assert_eq!(clock, Some(Duration::from_secs(60)));
+        let options = SearchOptions::default();
+        let stop = Arc::new(AtomicBool::new(false));
+        find_best_move(
+            &b,
+            3,
+            &options,
+            &options.new_table(),
+            &[],
+            None,
+            &stop,
+            false,
+            &log,
+            Some((&jsonl_log, clock)),
+        );
+        let record = std::fs::read_to_string(&jsonl_path).unwrap();
+        std::fs::remove_file(&log_path).unwrap();
+        std::fs::remove_file(&jsonl_path).unwrap();
+        assert_eq!(record.lines().count(), 1);
+        assert!(record.starts_with("{\"ply\":60,\"fen\":\"k7/8/2K5/8/8/8/8/7R b - - 4 30\","));
+        assert!(record.contains(",\"depth\":3,"));
+        let time_left = record.split("\"time_left_ms\":").nth(1).unwrap();
+        let time_left: u64 = time_left.trim().trim_end_matches('}').parse().unwrap();
+        assert!(time_left > 50000 && time_left <= 60000);
+    }
+
+    #[test]
+    fn stats_as_info_string() {
+        let stats = SearchStats {
+            cutoffs: 200,
+            first_move_cutoffs: 183,
+            table_probes: 1000,
+            table_hits: 380,
+            null_window_searches: 0,
+            re_searches: 0,
+            pruned: 1520,
+        };
+        assert_eq!(
+            stats_line(&stats),
+            "info string first move cutoffs 91.5% table hits 38.0% re-searches 0.0% pruned 1520\n"
+        );
+    }
+
+    #[test]
+    fn currmove_lines() {
+        let b = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let report = RootMoveReport {
+            depth: 12,
+            mov: Move::from_uci(&b, "e2e4").unwrap(),
+            number: 5,
+        };
+        assert_eq!(
+            currmove_line(&report),
+            "info depth 12 currmove e2e4 currmovenumber 5\n"
+        );
+    }
+
+    #[test]
+    fn go_parameters() {
+        let command: Vec<&str> = "go nodes 20000 movetime 500\n".split(' ').collect();
+        let options = go_options(&command, &SearchOptions::default());
+        assert_eq!(options.node_limit, Some(20000));
+        assert_eq!(options.move_time, Some(Duration::from_millis(500)));
+        let command: Vec<&str> = "go mate 3\n".split(' ').collect();
+        assert_eq!(go_options(&command, &options).mate_limit, Some(3));
+        let command: Vec<&str> = "go\n".split(' ').collect();
+        let options = go_options(&command, &SearchOptions::default());
+        assert_eq!(options.node_limit, None);
+        assert_eq!(options.move_time, None);
+        assert_eq!(go_value::<u8>(&command, "depth"), None);
+        let command: Vec<&str> = "go depth 7\n".split(' ').collect();
+        assert_eq!(go_value::<u8>(&command, "depth"), Some(7));
+        assert_eq!(go_value::<u64>(&command, "nodes"), None);
+    }
+
+    #[test]
+    fn multi_pv_reports_each_line() {
+        let command: Vec<&str> = "setoption name MultiPV value 3\n".split(' ').collect();
+        assert_eq!(spin_option(&command, "MultiPV"), Some(3));
+        assert_eq!(spin_option::<usize>(&command, "Hash"), None);
+        let command: Vec<&str> = "setoption name Contempt value -20\n".split(' ').collect();
+        assert_eq!(spin_option(&command, "Contempt"), Some(-20));
+        assert_eq!(spin_option::<usize>(&command, "Contempt"), None);
+        let command: Vec<&str> = "setoption name Deterministic value true\n"
+            .split(' ')
+            .collect();
+        assert_eq!(spin_option(&command, "Deterministic"), Some(true));
+
+        // Kb6 and Kc7 both mate in 2, the other moves take longer
+        let b = board_from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        let mut search = SearchState::new(SearchOptions {
+            multi_pv: Some(3),
+            ..SearchOptions::default()
+        });
+        let lines = Arc::new(Mutex::new(vec![]));
+        let reported = lines.clone();
+        search.on_depth = Some(Box::new(move |report| {
+            let line = info_line(report, PieceColor::White, false);
+            reported.lock().unwrap().push(line);
+        }));
+        iterative_deepening_search(&b, 4, &mut search);
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines.len(), 12);
+        assert!(lines[9].contains(" multipv 1 score mate 2 nodes "));
+        assert!(lines[10].contains(" multipv 2 score mate 2 nodes "));
+        assert!(lines[11].contains(" multipv 3 score mate 3 nodes "));
+    }
+}
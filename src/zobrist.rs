@@ -0,0 +1,155 @@
+pub use crate::board::*;
+
+// Keys for each piece (indexed by color * 8 + piece) on each of the 64 squares
+const PIECE_KEYS: [u64; 16 * 64] = random_keys(1);
+// Keys for the castling rights, in the order KQkq
+const CASTLING_KEYS: [u64; 4] = random_keys(2);
+// Keys for the file of a square where an en passant capture can be made
+const EN_PASSANT_KEYS: [u64; 8] = random_keys(3);
+const BLACK_TO_MOVE_KEY: u64 = random_keys::<1>(4)[0];
+// Keys for the file of the rook each castling right castles with (indexed by right * 8 + file),
+// only used while the right is held and the file is not the one in DEFAULT_CASTLING_ROOK_FILES,
+// as in Chess960
+const CASTLING_ROOK_FILE_KEYS: [u64; 4 * 8] = random_keys(5);
+
+/*
+    Fill an array with pseudo random numbers using splitmix64, so the keys are fixed at compile time
+    and the same position always has the same key
+*/
+const fn random_keys<const N: usize>(seed: u64) -> [u64; N] {
+    let mut keys = [0; N];
+    let mut state = seed;
+    let mut i = 0;
+    while i < N {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        keys[i] = splitmix64(state);
+        i += 1;
+    }
+    keys
+}
+
+/*
+    The splitmix64 output for a state of the generator, which spreads a change to any bit of the
+    state over the whole output
+*/
+pub const fn splitmix64(state: u64) -> u64 {
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/*
+    Zobrist key of the position, covering only what decides whether two positions are the same
+    for the repetition rules: the pieces, the side to move, the castling rights and the rooks they
+    castle with, and whether an en passant capture can be made
+*/
+pub fn position_key(board: &BoardState) -> u64 {
+    let mut key = 0;
+    for color in &[PieceColor::Black, PieceColor::White] {
+        for piece in PAWN..=KING {
+            for (row, col) in board.piece_list(*color, piece).iter() {
+                let square = (row - BOARD_START) * 8 + col - BOARD_START;
+                key ^= PIECE_KEYS[(*color as usize * 8 + piece as usize) * 64 + square];
+            }
+        }
+    }
+
+    if board.to_move == PieceColor::Black {
+        key ^= BLACK_TO_MOVE_KEY;
+    }
+
+    for (allowed, castling_key) in board.castling_rights().iter().zip(CASTLING_KEYS.iter()) {
+        if *allowed {
+            key ^= castling_key;
+        }
+    }
+    for (right, file) in board.held_castling_rook_files().iter().enumerate() {
+        match file {
+            Some(file) if *file != DEFAULT_CASTLING_ROOK_FILES[right] => {
+                key ^= CASTLING_ROOK_FILE_KEYS[right * 8 + file];
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(square) = board.en_passant_capture_square() {
+        key ^= EN_PASSANT_KEYS[square.file()];
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(board: &BoardState, moves: &[&str]) -> BoardState {
+        let mut board = board.clone();
+        for m in moves {
+            board = make_move(&board, &Move::from_uci(&board, m).unwrap());
+        }
+        board
+    }
+
+    #[test]
+    fn transpositions_have_the_same_key() {
+        let start = board_from_fen(DEFAULT_FEN_STRING).unwrap();
+        let a = play(&start, &["g1f3", "g8f6", "b1c3"]);
+        let b = play(&start, &["b1c3", "g8f6", "g1f3"]);
+        assert_eq!(position_key(&a), position_key(&b));
+        assert!(a == b);
+
+        // knights out and back again repeats the starting position
+        let c = play(&start, &["g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert_eq!(position_key(&c), position_key(&start));
+        assert!(c == start);
+    }
+
+    #[test]
+    fn repetition_relevant_fields_change_the_key() {
+        let white = board_from_fen("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        let black = board_from_fen("4k3/8/8/8/8/8/8/R3K2R b KQ - 0 1").unwrap();
+        let no_castling = board_from_fen("4k3/8/8/8/8/8/8/R3K2R w Q - 0 1").unwrap();
+        let moved_rook = board_from_fen("4k3/8/8/8/8/8/8/R3K1R1 w Q - 0 1").unwrap();
+        let keys = [&white, &black, &no_castling, &moved_rook]
+            .iter()
+            .map(|b| position_key(b))
+            .collect::<Vec<u64>>();
+        for i in 0..keys.len() {
+            for j in i + 1..keys.len() {
+                assert_ne!(keys[i], keys[j]);
+            }
+        }
+        assert!(white != black);
+
+        // the same rights castling with different rooks, as in Chess960
+        let outer = board_from_fen("4k3/8/8/8/8/8/8/RR2K1RR w GB - 0 1").unwrap();
+        let inner = board_from_fen("4k3/8/8/8/8/8/8/RR2K1RR w HA - 0 1").unwrap();
+        assert!(outer != inner);
+        assert_ne!(position_key(&outer), position_key(&inner));
+
+        // but not once the rights are lost, when the rooks they castled with no longer matter
+        let mut outer_lost = outer.clone();
+        let mut inner_lost = inner.clone();
+        for board in [&mut outer_lost, &mut inner_lost] {
+            board.white_king_side_castle = false;
+            board.white_queen_side_castle = false;
+        }
+        assert!(outer_lost == inner_lost);
+        assert_eq!(position_key(&outer_lost), position_key(&inner_lost));
+    }
+
+    #[test]
+    fn en_passant_only_counts_when_a_capture_is_possible() {
+        // no black pawn can take on e3
+        let a = board_from_fen("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1").unwrap();
+        let b = board_from_fen("4k3/8/8/8/4P3/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(position_key(&a), position_key(&b));
+        assert!(a == b);
+
+        let a = board_from_fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1").unwrap();
+        let b = board_from_fen("4k3/8/8/8/3pP3/8/8/4K3 b - - 0 1").unwrap();
+        assert_ne!(position_key(&a), position_key(&b));
+        assert!(a != b);
+    }
+}